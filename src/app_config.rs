@@ -1,103 +1,1286 @@
 use std::error::Error;
+use std::ffi::{OsStr, OsString};
 use std::fmt::{Display, Formatter};
+use std::io::IsTerminal;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
+use std::{env, fs, iter};
 
-use clap::{Parser, ValueEnum};
+use clap::{CommandFactory, FromArgMatches, Parser, ValueEnum};
 
+use crate::config_file;
+use crate::glob::Glob;
+use crate::regex::{Regex, Replacement};
 use crate::str_constant;
+use crate::transform;
+use crate::value_format::PathFormat;
 
-#[derive(Parser, Debug)]
+/// The name of the configuration file looked up in the working directory.
+pub const CONFIG_FILE_NAME: &str = "jsonprops.toml";
+
+/// The default value written in place of redacted values.
+const REDACTED_PLACEHOLDER: &str = "****";
+/// The default key segment replacing empty keys.
+const EMPTY_KEY_PLACEHOLDER: &str = "_empty_";
+/// The time after which fetching a URL source is given up, unless `--timeout` is set.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Parser, Clone, Debug)]
+#[command(after_help = "Exit codes:
+  0  the conversion succeeded
+  1  an I/O error (or any other failure) occurred
+  2  the options are invalid
+  3  the source cannot be parsed
+  4  the source cannot be converted to properties
+  5  the destination is out of sync with the source (--check)")]
 pub struct Config {
   /// The source JSON file to parse.
   ///
-  /// Detailed description here...
-  #[arg()]
-  source: PathBuf,
+  /// Pass `-` to read the JSON from the standard input instead. When built with the `remote`
+  /// feature, an `http://` URL is fetched instead.
+  #[cfg_attr(not(feature = "serve"), arg(required = true))]
+  #[cfg_attr(feature = "serve", arg(required_unless_present = "serve"))]
+  source: Option<PathBuf>,
 
   /// The destination .properties file; if not provided, the output will be printed to the standard 
   /// output.
   #[arg()]
   dest: Option<PathBuf>,
 
+  /// Writes the output into this directory if no destination is given, naming the file after the
+  /// source, e.g. `app.json` becomes `<DIR>/app.properties` (`.xml` and `.env` for the other
+  /// output formats).
+  #[arg(long, value_name = "DIR")]
+  output_dir: Option<PathBuf>,
+
+  /// Creates the output directory (and its parents) if it does not exist.
+  #[arg(long, requires = "output_dir")]
+  pub mkdir: bool,
+
+  /// Writes the entries under every top-level key into their own file in the output directory,
+  /// named after the key and without it in the keys, e.g. `server.port` becomes `port` in
+  /// `<DIR>/server.properties`. Top-level scalars and lists are written into
+  /// `<DIR>/root.properties`.
+  #[arg(
+    long, requires = "output_dir",
+    conflicts_with_all = ["dest", "check", "watch", "ndjson", "prefix", "key_manifest"])]
+  pub split_top_level: bool,
+
   /// Raises the logging level to DEBUG.
   ///
   /// Detailed description here...
   #[arg(short, long)]
   pub debug: bool,
 
+  /// Lowers the logging level of the standard output to WARN, so only warnings and errors are
+  /// printed. The log file still receives INFO messages.
+  #[arg(short, long, conflicts_with = "debug")]
+  pub quiet: bool,
+
   /// Defines the behaviour for handling lists.
+  ///
+  /// `count` replaces every list with a single `<key>.count` property holding its length.
   #[arg(short, long, value_enum, default_value_t = ListHandling::SingleProp)]
   list_handling: ListHandling,
 
-  /// Defines the character sequence for separating keys and values.
+  /// Emits a `<key>.count` property holding the length of every list expanded into a property
+  /// per member, alongside the members.
+  #[arg(long)]
+  pub count_lists: bool,
+
+  /// Drops the repeated members of lists joined into a single property with `single-prop` list
+  /// handling, keeping the first of each, e.g. `[1, 2, 1]` becomes `1,2`.
+  #[arg(long)]
+  pub dedup_list: bool,
+
+  /// Sorts the members of lists joined into a single property with `single-prop` list handling
+  /// lexicographically, e.g. `["b", "a", "c"]` becomes `a,b,c`.
+  #[arg(long)]
+  pub sort_list: bool,
+
+  /// Sorts the members of lists joined into a single property with `single-prop` list handling by
+  /// their numeric value, e.g. `[10, 9, 1.5]` becomes `1.5,9,10`. Members which are not numbers
+  /// are sorted lexicographically after the numbers.
+  #[arg(long, conflicts_with = "sort_list")]
+  pub sort_list_numeric: bool,
+
+  /// Defines the character sequence for separating keys and values. The spaced variants surround
+  /// the `=` or `:` with a single space on both sides.
   #[arg(short, long, value_enum, default_value_t = EntrySeparator::Equals)]
   entry_separator: EntrySeparator,
 
+  /// Surrounds the entry separator with a single space on both sides, e.g. `key = value`.
+  #[arg(long)]
+  pub pad_separator: bool,
+
   #[arg(long)]
   pub discard_wsp: bool,
+
+  /// Defines the character sequence joining the keys of nested objects.
+  ///
+  /// Must not contain characters requiring escaping in keys (whitespace, `:`, `=`).
+  #[arg(short, long, default_value_t = String::from(str_constant::DOT))]
+  namespace_separator: String,
+
+  /// Writes every non-ASCII character of keys and values as a `\uXXXX` escape sequence.
+  ///
+  /// Use this to produce ISO-8859-1 compatible output, as expected by `java.util.Properties.load`.
+  #[arg(long, visible_alias = "unicode-escape")]
+  pub ascii: bool,
+
+  /// Writes keys as they are, without escaping `=`, `:`, whitespace and the other characters
+  /// special to `.properties` files, for consumers which split entries at the first separator.
+  ///
+  /// The output may then be invalid for `java.util.Properties`, e.g. the key `a=b` is read back
+  /// as the key `a`.
+  #[arg(long)]
+  pub no_key_escape: bool,
+
+  /// Defines which spaces of keys are escaped: `all` of them (which `java.util.Properties` needs),
+  /// only the `leading` ones, or `none` for consumers reading keys up to the entry separator.
+  #[arg(long, value_enum, default_value_t = SpaceEscape::All)]
+  space_escape: SpaceEscape,
+
+  /// Escapes every `#` of keys, e.g. `a#b` as `a\#b`, for consumers taking a `#` anywhere in a
+  /// line for the start of a comment. Only a leading `#` needs escaping for
+  /// `java.util.Properties`, and only that one is escaped by default.
+  #[arg(long, conflicts_with = "no_key_escape")]
+  pub escape_all_hashes: bool,
+
+  /// Defines how the special characters of keys are written: `properties` escapes them with a
+  /// backslash, while `percent` percent-encodes every character of a key segment but ASCII
+  /// letters, digits and `-._~` (e.g. `a b:c` becomes `a%20b%3Ac`), keeping the namespace
+  /// separator literal, e.g. for keys used as URL path segments.
+  ///
+  /// Percent-encoded keys are no longer the keys `java.util.Properties` reads: it reads them
+  /// encoded.
+  #[arg(long, value_enum, default_value_t = KeyEncoding::Properties)]
+  key_encoding: KeyEncoding,
+
+  /// Defines the maximum number of nested levels to flatten; `0` flattens everything.
+  ///
+  /// Objects (and lists, when using `multi-prop` list handling) on the last level are written as
+  /// a single compact JSON string instead of being expanded any further.
+  #[arg(long, default_value_t = 0)]
+  max_depth: usize,
+
+  /// Defines the order in which the properties are written.
+  ///
+  /// `sorted` (or `key`) sorts the entries by key, `value` by value, then by key, and `depth` by
+  /// the number of namespace separators in the key, then by key. Numbers in keys are compared by
+  /// their value, so `items.2` comes before `items.10`.
+  /// `insertion` (or `none`) keeps the order the keys appear in the JSON. Object members are only
+  /// visited in their original order when `serde_json` is built with its `preserve_order` feature,
+  /// otherwise the members of each object are visited alphabetically.
+  #[arg(short, long, visible_alias = "sort", value_enum, default_value_t = EntryOrder::Sorted)]
+  order: EntryOrder,
+
+  /// Defines how list indices are appended to keys when using `multi-prop` list handling.
+  ///
+  /// `dot` produces `items.0.name`, `bracket` produces `items[0].name`.
+  #[arg(short, long, value_enum, default_value_t = ArrayStyle::Dot)]
+  array_style: ArrayStyle,
+
+  /// Defines the index of the first list member when using `multi-prop` list handling: `0` or
+  /// `1`, e.g. `1` produces `items.1`, `items.2`, ...
+  #[arg(long, default_value_t = 0)]
+  array_base: usize,
+
+  /// Defines a prefix prepended to every key, joined with the namespace separator.
+  ///
+  /// `--prefix app.db` turns the key `url` into `app.db.url`.
+  #[arg(short, long)]
+  prefix: Option<String>,
+
+  /// Uses the file stem of the source as the `--prefix`, e.g. the keys of `db.json` are prefixed
+  /// by `db`. The stem is escaped like any other key.
+  #[arg(long, conflicts_with_all = ["prefix", "split_top_level"])]
+  pub prefix_from_filename: bool,
+
+  /// Defines the format of the source file.
+  ///
+  /// If not provided, it is inferred from the extension of the source file: `.toml` files are read
+  /// as TOML, `.json5` files as JSON5, everything else (including the standard input) as JSON.
+  #[arg(short, long, value_enum)]
+  format: Option<InputFormat>,
+
+  /// Defines the character sequence joining list members when using `single-prop` list handling.
+  #[arg(long, default_value_t = String::from(str_constant::COMMA))]
+  list_delimiter: String,
+
+  /// Defines a character to escape list delimiters inside list members with.
+  ///
+  /// If not provided, list members containing the delimiter are joined as they are, with a
+  /// warning.
+  #[arg(long)]
+  list_escape: Option<char>,
+
+  /// Defines the behaviour for lists of non-primitives when using `single-prop` list handling.
+  ///
+  /// `error` aborts the conversion, `omit` drops the list, and `fallback-multi` expands the list
+  /// as if `multi-prop` list handling was used for it.
+  #[arg(long, value_enum, default_value_t = ComplexListHandling::Error)]
+  on_complex_list: ComplexListHandling,
+
+  /// Writes a comment header naming the source file and the time of the conversion.
+  #[arg(long)]
+  pub header: bool,
+
+  /// Writes a comment before every entry naming the JSON type it is converted from, e.g.
+  /// `# type: number`: `string`, `number`, `bool`, `null`, `list` (of `single-prop` list handling)
+  /// or `object` (beyond the maximum depth).
+  #[arg(long)]
+  pub annotate_types: bool,
+
+  /// Writes the string value of this member of an object (e.g. `description`) as a `#` comment
+  /// above the properties of the object, instead of as a property. Members of any other type are
+  /// converted as usual.
+  #[arg(long, value_name = "NAME")]
+  comment_field: Option<String>,
+
+  /// Forces the settings which make the output depend on nothing but the source: sorted order,
+  /// normalized numbers, LF line endings and no header (which holds the time of the conversion).
+  #[arg(long, conflicts_with_all = ["order", "number_format", "line_ending", "header"])]
+  pub canonical: bool,
+
+  /// Defines the format of the output.
+  ///
+  /// `xml` produces the format of `java.util.Properties.storeToXML`, and `env` an env-file with
+  /// `DB_URL=...` style keys (e.g. for `docker --env-file`). The entry separator applies to
+  /// neither.
+  #[arg(long, value_enum, default_value_t = OutputFormat::Properties)]
+  output: OutputFormat,
+
+  /// Writes only the (escaped) key of every entry, one per line, e.g. to feed them into `grep`.
+  #[arg(long, conflicts_with = "values_only")]
+  pub keys_only: bool,
+
+  /// Writes only the (escaped) value of every entry, one per line, in the order of the entries.
+  #[arg(long)]
+  pub values_only: bool,
+
+  /// Only keeps the properties whose (unescaped) key matches one of these glob patterns.
+  ///
+  /// `*` matches within a single namespace segment, `**` matches across segments: `db.**` keeps
+  /// every key under `db`, `*.password` keeps the `password` keys one level deep.
+  #[arg(long)]
+  include: Vec<Glob>,
+
+  /// Drops the properties whose (unescaped) key matches one of these glob patterns.
+  ///
+  /// Exclusions take precedence over inclusions.
+  #[arg(long)]
+  exclude: Vec<Glob>,
+
+  /// Replaces the value of the properties whose (unescaped) key matches one of these glob
+  /// patterns with the `--redact-placeholder`, e.g. `--redact '**password*'`.
+  #[arg(long, value_name = "GLOB")]
+  redact: Vec<Glob>,
+
+  /// Defines the value written in place of redacted values.
+  #[arg(long, default_value_t = String::from(REDACTED_PLACEHOLDER))]
+  redact_placeholder: String,
+
+  /// Decodes the base64 string value of the properties whose (unescaped) key matches one of these
+  /// glob patterns into UTF-8 text.
+  #[arg(long, value_name = "GLOB")]
+  base64_decode: Vec<Glob>,
+
+  /// Defines the behaviour for base64 decoded values which are not valid UTF-8.
+  ///
+  /// `hex` writes the decoded bytes as lowercase hexadecimal digits instead.
+  #[arg(long, value_enum, default_value_t = InvalidUtf8Handling::Error)]
+  on_invalid_utf8: InvalidUtf8Handling,
+
+  /// Formats the string and number values of the properties whose (unescaped) key matches a glob
+  /// pattern, written as `<glob>=<formatter>`, e.g. `*.created=epoch`.
+  ///
+  /// The formatters are `epoch` (an RFC 3339 date or timestamp as milliseconds since the Unix
+  /// epoch), `iso-date` (milliseconds since the Unix epoch as an RFC 3339 timestamp in UTC),
+  /// `upper`, `lower` and `trim`. Only the first matching pattern applies to a value.
+  #[arg(long, value_name = "GLOB=FORMATTER")]
+  format_path: Vec<PathFormat>,
+
+  /// Deep-merges these JSON files into the source, in the order given.
+  ///
+  /// Objects are merged recursively; every other value of a later file replaces the value of an
+  /// earlier one.
+  #[arg(short, long)]
+  merge: Vec<PathBuf>,
+
+  /// Defines how lists are merged when using `--merge`.
+  #[arg(long, value_enum, default_value_t = ArrayMerge::Replace)]
+  array_merge: ArrayMerge,
+
+  /// Applies these JSON Merge Patch (RFC 7386) files to the source, in the order given, after
+  /// the `--merge` files.
+  ///
+  /// Unlike `--merge`, a `null` member of a patch removes the member of the source.
+  #[arg(long, value_name = "FILE")]
+  patch: Vec<PathBuf>,
+
+  /// Converts only the object at this JSON Pointer (RFC 6901), e.g. `/spring/datasource`.
+  #[arg(long)]
+  pointer: Option<String>,
+
+  /// Converts a top-level array instead of rejecting it, e.g. `[{"name":"a"}]` into `0.name=a`.
+  ///
+  /// The members are expanded under their indices with `multi-prop` list handling. With any other
+  /// list handling the array needs a `--prefix` to name its key after, as a list of the prefix.
+  #[arg(long)]
+  pub allow_top_array: bool,
+
+  /// Defines the case every key segment is converted to, e.g. `maxPoolSize` becomes
+  /// `max-pool-size` in `kebab` case.
+  ///
+  /// List indices and the `--prefix` are left unchanged.
+  #[arg(short, long, value_enum, default_value_t = KeyCase::Original)]
+  key_case: KeyCase,
+
+  /// Substitutes the matches of a regular expression in every key segment, written as
+  /// `<regex>=<replacement>`, e.g. `@=_at_`; `$1` in the replacement is the first capture group.
+  ///
+  /// Use `\=` for a `=` in the regex. The substitutions are applied in the order given, after
+  /// `--key-case` and before escaping. They see one segment at a time, never the namespace
+  /// separator: a replacement producing the separator makes the segment look nested, and is
+  /// warned about.
+  #[arg(long, value_name = "REGEX=REPLACEMENT")]
+  key_replace: Vec<Replacement>,
+
+  /// Defines the behaviour for object members with an empty key (`""`), which produce an empty key
+  /// segment, e.g. `a.` or a key which is empty altogether.
+  ///
+  /// `skip` omits the member, `placeholder` uses `--empty-key-placeholder` as its segment instead.
+  #[arg(long, value_enum, default_value_t = EmptyKeyHandling::Keep)]
+  on_empty_key: EmptyKeyHandling,
+
+  /// Defines the key segment used for empty keys with `--on-empty-key placeholder`.
+  #[arg(long, value_name = "SEGMENT", default_value_t = String::from(EMPTY_KEY_PLACEHOLDER))]
+  empty_key_placeholder: String,
+
+  /// Checks every (unescaped) key against the `--key-pattern` after flattening, failing with the
+  /// keys which do not match, and the JSON Pointers of the values they are flattened from.
+  #[arg(long)]
+  pub validate_keys: bool,
+
+  /// Defines the regular expression every key must match as a whole with `--validate-keys`, e.g.
+  /// `[A-Za-z0-9._-]+`. Every key matches by default.
+  #[arg(long, value_name = "REGEX", requires = "validate_keys")]
+  key_pattern: Option<String>,
+
+  /// Defines how booleans are rendered: `true`/`false`, `yes`/`no`, `on`/`off` or `1`/`0`.
+  ///
+  /// Reading the properties back into booleans requires the same mapping on the consumer's side.
+  #[arg(short, long, value_enum, default_value_t = BoolFormat::TrueFalse)]
+  bool_format: BoolFormat,
+
+  /// Defines how `null` values are handled.
+  ///
+  /// `empty` writes an empty value, `omit` writes no property at all, and `literal` writes the
+  /// text `null`.
+  #[arg(long, value_enum, default_value_t = NullHandling::Empty)]
+  null_handling: NullHandling,
+
+  /// Defines how `null` members of lists are handled when using `single-prop` list handling.
+  ///
+  /// `omit` leaves them out of the joined value (`a,b`), `empty` joins them as empty members
+  /// (`a,,b`), and `literal` as the text `null` (`a,null,b`).
+  #[arg(long, value_enum, default_value_t = NullHandling::Literal)]
+  list_null_handling: NullHandling,
+
+  /// Defines how empty objects (`{}`) are handled.
+  ///
+  /// `omit` writes no property at all, `key-only` writes the key with an empty value, and `marker`
+  /// writes a `<key>.empty=true` property.
+  #[arg(long, value_enum, default_value_t = EmptyObjectHandling::Omit)]
+  empty_object: EmptyObjectHandling,
+
+  /// Defines the behaviour when different JSON values are flattened into the same key, e.g.
+  /// `{"a.b": 1, "a": {"b": 2}}`.
+  #[arg(long, value_enum, default_value_t = DuplicateHandling::KeepLast)]
+  on_duplicate: DuplicateHandling,
+
+  /// Parses the JSON source straight from the file (or standard input) without reading it into
+  /// memory first. Parse errors are reported without quoting the offending line.
+  #[arg(long)]
+  pub stream: bool,
+
+  /// Fails if an object of the JSON source (or of a merged file) has duplicate keys, instead of
+  /// keeping the last one of them.
+  #[arg(long)]
+  pub strict: bool,
+
+  /// Keeps running after the conversion, regenerating the destination file whenever the source
+  /// or one of the merged files changes, until interrupted.
+  #[arg(long)]
+  pub watch: bool,
+
+  /// Runs the conversion without writing anything, only logging the number of entries and the
+  /// keys themselves which would be written.
+  #[arg(long)]
+  pub dry_run: bool,
+
+  /// Converts the source without writing anything, failing with the added, removed and changed
+  /// keys if the existing destination file differs from what would be written (line endings
+  /// aside).
+  #[arg(long, conflicts_with_all = ["dry_run", "watch"])]
+  pub check: bool,
+
+  /// Appends the properties to the destination file instead of replacing it, after a blank line
+  /// separating them from its existing entries. Keys the file has already are warned about.
+  #[arg(long, conflicts_with_all = ["check", "watch", "diff_against", "split_top_level"])]
+  pub append: bool,
+
+  /// Writes only the entries which differ from this previously generated .properties file: added
+  /// and changed entries prefixed by `+` and `~` with their new value, and removed ones by `-` with
+  /// their old value.
+  #[arg(
+    long, value_name = "FILE",
+    conflicts_with_all = ["check", "key_manifest", "split_top_level"])]
+  diff_against: Option<PathBuf>,
+
+  /// Defines the quotes wrapping list members which contain the list delimiter, whitespace or the
+  /// quote itself, when using `single-prop` list handling.
+  ///
+  /// Inside quoted members, quotes and backslashes are escaped with a backslash.
+  #[arg(long, value_enum, default_value_t = QuoteStyle::None)]
+  quote_style: QuoteStyle,
+
+  /// Defines how numbers are written: `as-written` keeps the exact token of the source (e.g.
+  /// `1.50`, `1e10` or integers beyond 64 bits), while `normalized` writes integers as they are and
+  /// every other number as a 64-bit float (e.g. `1.5`, `10000000000.0`).
+  #[arg(long, value_enum, default_value_t = NumberFormat::Normalized)]
+  number_format: NumberFormat,
+
+  /// Writes floats without a fractional part as integers, e.g. `1.0` and `1e3` as `1` and `1000`.
+  ///
+  /// Only floats up to 2^53 in magnitude are written as integers, as larger ones are not exact.
+  #[arg(long, conflicts_with = "force_decimal")]
+  pub integer_floats: bool,
+
+  /// Writes integers as decimals, e.g. `1` as `1.0`.
+  #[arg(long)]
+  pub force_decimal: bool,
+
+  /// Defines the behaviour for numbers which are not finite as a 64-bit float, e.g. `1e400`
+  /// overflowing to infinity, or the `NaN` of lenient parsers.
+  ///
+  /// `skip` omits the property (or list member), `placeholder` writes `--nonfinite-placeholder`
+  /// instead.
+  #[arg(long, value_enum, default_value_t = NonFiniteHandling::Error)]
+  on_nonfinite: NonFiniteHandling,
+
+  /// The value written for numbers which are not finite with `--on-nonfinite placeholder`.
+  ///
+  /// Defaults to the spelling of `java.lang.Double`: `Infinity`, `-Infinity` or `NaN`.
+  #[arg(long, value_name = "VALUE")]
+  nonfinite_placeholder: Option<String>,
+
+  /// Reads the default options from the given TOML file instead of `jsonprops.toml` in the
+  /// working directory.
+  #[arg(long, value_name = "PATH")]
+  config: Option<PathBuf>,
+
+  /// Defines the file the log is written to.
+  #[arg(long, value_name = "PATH", default_value = "output.log")]
+  log_file: PathBuf,
+
+  /// Disables writing the log to a file.
+  #[arg(long, conflicts_with = "log_file")]
+  pub no_log_file: bool,
+
+  /// Fails the conversion if it produces no properties, e.g. because the source is `{}` or `null`.
+  #[arg(long)]
+  pub fail_on_empty: bool,
+
+  /// Replaces `${VAR}` placeholders in string values with the value of the `VAR` environment
+  /// variable. Write `$${VAR}` for a literal `${VAR}`.
+  #[arg(long)]
+  pub expand_env: bool,
+
+  /// Defines the behaviour for placeholders of undefined environment variables when using
+  /// `--expand-env`.
+  #[arg(long, value_enum, default_value_t = MissingEnvHandling::Error)]
+  on_missing_env: MissingEnvHandling,
+
+  /// Prints a summary of the conversion to the standard error: the number of properties, the
+  /// maximum depth, and the number of lists expanded or dropped.
+  #[arg(long)]
+  pub stats: bool,
+
+  /// Writes a JSON summary of the conversion to this file, for tooling: the source and the
+  /// destination, the counts of `--stats`, the keys of the dropped lists and of the duplicate keys,
+  /// and the JSON Pointers of the coerced values.
+  #[arg(long, value_name = "FILE", conflicts_with = "ndjson")]
+  report: Option<PathBuf>,
+
+  /// Discards trailing whitespace from values, independently of `--discard-wsp`.
+  #[arg(long)]
+  pub trim_trailing: bool,
+
+  /// Defines the maximum length of values in bytes, as they are written (i.e. escaped).
+  #[arg(long, value_name = "N")]
+  max_value_length: Option<usize>,
+
+  /// Defines the behaviour for values exceeding `--max-value-length`.
+  ///
+  /// `truncate` cuts the value short, ending it with `...` within the maximum length.
+  #[arg(long, value_enum, default_value_t = OversizeHandling::Error)]
+  on_oversize: OversizeHandling,
+
+  /// Defines the maximum number of members of a list expanded with `multi-prop` list handling (or
+  /// `--on-complex-list fallback-multi`). Unlimited by default.
+  #[arg(long, value_name = "N")]
+  max_array_elements: Option<usize>,
+
+  /// Defines the behaviour for lists longer than `--max-array-elements`.
+  ///
+  /// `truncate` expands only the first members, and marks the list with a `<key>.truncated=true`
+  /// property.
+  #[arg(long, value_enum, default_value_t = ArrayOverflowHandling::Truncate)]
+  on_array_overflow: ArrayOverflowHandling,
+
+  /// Coerces string values to numbers or booleans wherever this JSON Schema declares them to be
+  /// `integer`, `number` or `boolean`, e.g. `"port": "8080"` to `8080`.
+  #[arg(long, value_name = "FILE")]
+  schema: Option<PathBuf>,
+
+  /// Coerces every string value which is a valid JSON number to a number, unless `--schema`
+  /// declares it to be a `string`.
+  #[arg(long)]
+  pub coerce_numeric_strings: bool,
+
+  /// Writes the string values `"true"` and `"false"` as booleans, in the `--bool-format`.
+  #[arg(long)]
+  pub normalize_bool_strings: bool,
+
+  /// Recognises boolean strings regardless of their case with `--normalize-bool-strings`, e.g.
+  /// `"True"` or `"FALSE"`.
+  #[arg(long, requires = "normalize_bool_strings")]
+  pub bool_strings_ignore_case: bool,
+
+  /// Reads the properties back before writing them, failing if any key does not read back with
+  /// the value it was converted to.
+  #[arg(long)]
+  pub verify: bool,
+
+  /// Shows the progress of the conversion on the standard error, ticking per top-level key. It is
+  /// not shown when writing to the standard output, or when the standard error is not a terminal.
+  #[arg(long)]
+  pub progress: bool,
+
+  /// Reads the source as newline-delimited JSON, converting every object (line) into a separate
+  /// file. A `{}` in the destination is replaced by the index of the object, otherwise `_<index>`
+  /// is appended to the file stem of the destination, e.g. `dest_0.properties`. Blank lines are
+  /// skipped.
+  #[arg(long)]
+  pub ndjson: bool,
+
+  /// Continues with the next line of an NDJSON source if a line cannot be converted, failing only
+  /// once every line has been processed.
+  #[arg(long, requires = "ndjson")]
+  pub keep_going: bool,
+
+  /// Writes the unescaped keys of the properties to this file, one per line in sorted order,
+  /// without their values.
+  #[arg(long, value_name = "FILE")]
+  key_manifest: Option<PathBuf>,
+
+  /// Defines the line ending written after every line.
+  #[arg(long, value_enum, default_value_t = LineEnding::Lf)]
+  line_ending: LineEnding,
+
+  /// Flattens string values holding a JSON object or array (e.g. `"{\"a\":1}"`) as if they were
+  /// part of the document. Strings which are not valid JSON are kept as they are.
+  #[arg(long)]
+  pub parse_embedded_json: bool,
+
+  /// Pipes every value through this command, replacing it with what the command writes to its
+  /// standard output (without the final line break), e.g. to resolve secrets. Keys are kept.
+  ///
+  /// The command is split at unquoted whitespace and run without a shell. A process is spawned
+  /// for every value, which is slow for large documents: narrow the values down with `--include`
+  /// or `--pointer`, or have the command resolve a batch by itself in a preceding step instead.
+  #[arg(long, value_name = "CMD")]
+  transform_cmd: Option<String>,
+
+  /// Serves conversions over HTTP on this address (e.g. `127.0.0.1:8080`) instead of converting
+  /// a source: every POSTed JSON body is answered with its properties. Options are taken from the
+  /// query parameters, e.g. `?list-handling=multi-prop&entry-separator=colon`.
+  #[cfg_attr(feature = "serve", arg(
+    long, value_name = "ADDR",
+    conflicts_with_all = ["dest", "output_dir", "watch", "check", "ndjson"]))]
+  #[cfg_attr(not(feature = "serve"), arg(skip))]
+  serve: Option<String>,
+
+  /// Gives up fetching a URL source if connecting to its server, or any read from it, takes longer
+  /// than this, e.g. `10s` or `1m 30s`. Defaults to 30 seconds.
+  #[cfg_attr(feature = "remote", arg(long, value_name = "DURATION"))]
+  #[cfg_attr(not(feature = "remote"), arg(skip))]
+  timeout: Option<humantime::Duration>,
+
+  /// Sends this header along with the request fetching a URL source, e.g.
+  /// `--http-header "Authorization: Bearer <TOKEN>"`. May be given more than once.
+  #[cfg_attr(feature = "remote", arg(long, value_name = "HEADER"))]
+  #[cfg_attr(not(feature = "remote"), arg(skip))]
+  http_header: Vec<String>,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum ListHandling {
   SingleProp,
   MultiProp,
+  Count,
+}
+
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum ComplexListHandling {
+  Omit,
+  Error,
+  FallbackMulti,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum QuoteStyle {
+  None,
+  Double,
+  Single,
+}
+
+impl QuoteStyle {
+  /// Returns the quote character, or `None` if list members are not quoted.
+  pub fn quote(&self) -> Option<char> {
+    match self {
+      QuoteStyle::None => None,
+      QuoteStyle::Double => Some('"'),
+      QuoteStyle::Single => Some('\''),
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum NumberFormat {
+  AsWritten,
+  Normalized,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum KeyEncoding {
+  Properties,
+  Percent,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum EmptyKeyHandling {
+  Keep,
+  Error,
+  Skip,
+  Placeholder,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum SpaceEscape {
+  All,
+  Leading,
+  None,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum NonFiniteHandling {
+  Error,
+  Skip,
+  Placeholder,
 }
 
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum MissingEnvHandling {
+  Error,
+  Keep,
+  Empty,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum OversizeHandling {
+  Truncate,
+  Error,
+  Keep,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum ArrayOverflowHandling {
+  Truncate,
+  Error,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum OutputFormat {
+  Properties,
+  Xml,
+  Env,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum LineEnding {
+  Lf,
+  Crlf,
+  /// CRLF on Windows, LF everywhere else.
+  Platform,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum InputFormat {
+  Json,
+  Json5,
+  Toml,
+}
+
+impl InputFormat {
+  fn of_path(path: &Path) -> Self {
+    match path.extension().and_then(OsStr::to_str) {
+      Some("toml") => InputFormat::Toml,
+      Some("json5") => InputFormat::Json5,
+      _ => InputFormat::Json,
+    }
+  }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum DuplicateHandling {
+  KeepLast,
+  KeepFirst,
+  Error,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum NullHandling {
+  Empty,
+  Omit,
+  Literal,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum InvalidUtf8Handling {
+  Error,
+  Hex,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum EmptyObjectHandling {
+  Omit,
+  KeyOnly,
+  Marker,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum BoolFormat {
+  #[value(name = "truefalse")]
+  TrueFalse,
+  #[value(name = "yesno")]
+  YesNo,
+  #[value(name = "onoff")]
+  OnOff,
+  #[value(name = "10")]
+  OneZero,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum KeyCase {
+  Original,
+  Kebab,
+  Snake,
+  Lower,
+  Upper,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum ArrayMerge {
+  Replace,
+  Concat,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum ArrayStyle {
+  Dot,
+  Bracket,
+}
+
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
+pub enum EntryOrder {
+  #[value(alias = "none")]
+  Insertion,
+  #[value(alias = "key")]
+  Sorted,
+  Value,
+  Depth,
+}
 
 #[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, ValueEnum, Debug)]
 pub enum EntrySeparator {
   Colon,
   Equals,
   Space,
+  ColonSpaced,
+  EqualsSpaced,
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum ConfigValidationError {
   InvalidPathError(String),
   MissingFileError(String),
+  InvalidNamespaceSeparatorError(String),
+  InvalidListDelimiterError(String),
+  IncompatibleOptionsError(String),
+  InvalidPointerError(String),
+  InvalidConfigFileError(String),
+  InvalidArrayBaseError(usize),
+  InvalidTransformCommandError(String),
+  InvalidKeyReplacementError(String),
+  InvalidFormatPathError(String),
+  InvalidKeyPatternError(String),
 }
 
 impl Display for ConfigValidationError {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     match self {
       Self::InvalidPathError(s) => write!(f, "Invalid filepath: {s}"),
-      Self::MissingFileError(s) => write!(f, "File does not exist: {s}")
+      Self::MissingFileError(s) => write!(f, "File does not exist: {s}"),
+      Self::InvalidNamespaceSeparatorError(s) => write!(
+        f, "Invalid namespace separator: [ {s} ]. It must be non-empty and must not contain \
+        whitespace, ':' or '='"),
+      Self::InvalidListDelimiterError(s) => write!(
+        f, "Invalid list delimiter: [ {s} ]. It must be non-empty"),
+      Self::IncompatibleOptionsError(s) => write!(f, "Incompatible options: {s}"),
+      Self::InvalidPointerError(s) => write!(
+        f, "Invalid JSON Pointer: [ {s} ]. It must be empty or start with '/'"),
+      Self::InvalidConfigFileError(s) => write!(f, "Invalid configuration file: {s}"),
+      Self::InvalidArrayBaseError(n) => write!(
+        f, "Invalid array base: [ {n} ]. It must be 0 or 1"),
+      Self::InvalidKeyReplacementError(s) => write!(f, "Invalid key replacement: {s}"),
+      Self::InvalidFormatPathError(s) => write!(f, "Invalid path format: {s}"),
+      Self::InvalidKeyPatternError(s) => write!(f, "Invalid key pattern: {s}"),
+      Self::InvalidTransformCommandError(s) => write!(
+        f, "Invalid transform command: [ {s} ]. It must be non-empty and close every quote"),
     }
   }
 }
 
 impl Error for ConfigValidationError {}
 
+/// The defaults are the same as the ones applied on the command line.
+impl Default for Config {
+  fn default() -> Self {
+    Config {
+      source: Some(PathBuf::new()),
+      dest: None,
+      output_dir: None,
+      mkdir: false,
+      split_top_level: false,
+      debug: false,
+      quiet: false,
+      list_handling: ListHandling::SingleProp,
+      count_lists: false,
+      dedup_list: false,
+      sort_list: false,
+      sort_list_numeric: false,
+      entry_separator: EntrySeparator::Equals,
+      pad_separator: false,
+      discard_wsp: false,
+      namespace_separator: String::from(str_constant::DOT),
+      ascii: false,
+      no_key_escape: false,
+      space_escape: SpaceEscape::All,
+      escape_all_hashes: false,
+      key_encoding: KeyEncoding::Properties,
+      max_depth: 0,
+      order: EntryOrder::Sorted,
+      array_style: ArrayStyle::Dot,
+      array_base: 0,
+      prefix: None,
+      prefix_from_filename: false,
+      format: None,
+      list_delimiter: String::from(str_constant::COMMA),
+      list_escape: None,
+      on_complex_list: ComplexListHandling::Error,
+      header: false,
+      annotate_types: false,
+      comment_field: None,
+      canonical: false,
+      output: OutputFormat::Properties,
+      keys_only: false,
+      values_only: false,
+      include: Vec::new(),
+      exclude: Vec::new(),
+      redact: Vec::new(),
+      redact_placeholder: String::from(REDACTED_PLACEHOLDER),
+      base64_decode: Vec::new(),
+      on_invalid_utf8: InvalidUtf8Handling::Error,
+      format_path: Vec::new(),
+      merge: Vec::new(),
+      array_merge: ArrayMerge::Replace,
+      patch: Vec::new(),
+      pointer: None,
+      allow_top_array: false,
+      key_case: KeyCase::Original,
+      key_replace: Vec::new(),
+      on_empty_key: EmptyKeyHandling::Keep,
+      empty_key_placeholder: String::from(EMPTY_KEY_PLACEHOLDER),
+      validate_keys: false,
+      key_pattern: None,
+      bool_format: BoolFormat::TrueFalse,
+      null_handling: NullHandling::Empty,
+      list_null_handling: NullHandling::Literal,
+      empty_object: EmptyObjectHandling::Omit,
+      on_duplicate: DuplicateHandling::KeepLast,
+      stream: false,
+      strict: false,
+      watch: false,
+      dry_run: false,
+      check: false,
+      append: false,
+      diff_against: None,
+      quote_style: QuoteStyle::None,
+      number_format: NumberFormat::Normalized,
+      integer_floats: false,
+      force_decimal: false,
+      on_nonfinite: NonFiniteHandling::Error,
+      nonfinite_placeholder: None,
+      config: None,
+      log_file: PathBuf::from("output.log"),
+      no_log_file: false,
+      fail_on_empty: false,
+      expand_env: false,
+      on_missing_env: MissingEnvHandling::Error,
+      stats: false,
+      report: None,
+      trim_trailing: false,
+      max_value_length: None,
+      on_oversize: OversizeHandling::Error,
+      max_array_elements: None,
+      on_array_overflow: ArrayOverflowHandling::Truncate,
+      schema: None,
+      coerce_numeric_strings: false,
+      normalize_bool_strings: false,
+      bool_strings_ignore_case: false,
+      verify: false,
+      progress: false,
+      ndjson: false,
+      keep_going: false,
+      key_manifest: None,
+      line_ending: LineEnding::Lf,
+      parse_embedded_json: false,
+      transform_cmd: None,
+      serve: None,
+      timeout: None,
+      http_header: Vec::new(),
+    }
+  }
+}
+
 impl Config {
   // This is here only to allow module props tests to create a config:
   #[allow(dead_code)]
   pub fn empty() -> Config {
     Config {
-      source: PathBuf::new(),
-      dest: None,
       debug: true,
       list_handling: ListHandling::MultiProp,
-      entry_separator: EntrySeparator::Equals,
-      discard_wsp: false,
+      ..Config::default()
     }
   }
 
-  pub fn validate(self) -> Result<Self, ConfigValidationError> {
-    let source = &self.source;
-    let source_exists = source
-      .try_exists()
-      .map_err(|_| Self::invalid_path_error(source))?;
-    if !source_exists {
-      return Err(ConfigValidationError::MissingFileError(Self::path_to_string(source)));
+  /// Creates a [ConfigBuilder] for configuring the conversion programmatically.
+  pub fn builder() -> ConfigBuilder {
+    ConfigBuilder::default()
+  }
+
+  /// Parses the command line arguments like [Config::parse], taking the defaults of the options
+  /// from a configuration file.
+  ///
+  /// The configuration file is the one given by `--config`, or else `jsonprops.toml` in the
+  /// working directory, if it exists. Each option is taken from the first of:
+  /// 1. the command line,
+  /// 2. the configuration file,
+  /// 3. the built-in defaults.
+  pub fn parse_with_config_file() -> Result<Self, ConfigValidationError> {
+    Self::parse_with_config_file_from(env::args_os())
+  }
+
+  /// Parses the given command line arguments like [Config::parse_with_config_file].
+  pub fn parse_with_config_file_from<I, T>(args: I) -> Result<Self, ConfigValidationError>
+  where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString>,
+  {
+    let args: Vec<OsString> = args.into_iter().map(Into::into).collect();
+    let matches = Self::command().get_matches_from(&args);
+    let cli = Self::from_arg_matches(&matches).unwrap_or_else(|e| e.exit());
+    let path = match cli.config {
+      Some(path) => {
+        Self::validate_source(&path)?;
+        path
+      }
+      None if Path::new(CONFIG_FILE_NAME).is_file() => PathBuf::from(CONFIG_FILE_NAME),
+      None => return Ok(cli),
+    };
+
+    let invalid_config_file_error = |reason: String| {
+      ConfigValidationError::InvalidConfigFileError(format!("{}: {reason}", path.display()))
+    };
+    let text = fs::read_to_string(&path).map_err(|e| invalid_config_file_error(e.to_string()))?;
+    let defaults = config_file::to_args(&text, &Self::command(), &matches)
+      .map_err(invalid_config_file_error)?;
+    let (program, cli_args) = args.split_first().expect("the program name is always present");
+    Ok(Self::parse_from(iter::once(program).chain(&defaults).chain(cli_args)))
+  }
+
+  /// Validates the source and destination paths, then every other option.
+  pub fn validate(self) -> Result<Self, ConfigValidationError> {
+    if !self.reads_stdin() && !self.is_remote() {
+      Self::validate_source(self.source())?;
+    }
+    for overlay in self.merge.iter().chain(&self.patch) {
+      Self::validate_source(overlay)?;
+    }
+    if let Some(schema) = &self.schema {
+      Self::validate_source(schema)?;
+    }
+    if let Some(old) = &self.diff_against {
+      Self::validate_source(old)?;
     }
 
-    if let Some(dest) = &self.dest {
+    if let Some(dir) = &self.output_dir {
+      if self.mkdir {
+        fs::create_dir_all(dir).map_err(|_| Self::invalid_path_error(dir))?;
+      } else if !dir.is_dir() {
+        return Err(ConfigValidationError::MissingFileError(Self::path_to_string(dir)));
+      }
+    }
+
+    for dest in self.dest.iter().chain(&self.key_manifest).chain(&self.report) {
       let _ = dest.try_exists().map_err(|_| Self::invalid_path_error(dest))?;
     }
 
+    self.validate_options()
+  }
+
+  /// Validates every option which is not a filesystem path.
+  fn validate_options(mut self) -> Result<Self, ConfigValidationError> {
+    // The canonical settings are the defaults, so they only have to be kept, as `--canonical`
+    // conflicts with the options overriding them on the command line:
+    let is_canonical = self.order == EntryOrder::Sorted
+      && self.number_format == NumberFormat::Normalized
+      && self.line_ending == LineEnding::Lf
+      && !self.header;
+    if self.canonical && !is_canonical {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "canonical output is sorted, with normalized numbers, LF line endings and no header")));
+    }
+
+    if self.prefix_from_filename {
+      let Some(stem) = self.source().file_stem().filter(|_| !self.reads_stdin()) else {
+        return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+          "the prefix can only be taken from the name of a source file")));
+      };
+      self.prefix = Some(stem.to_string_lossy().into_owned());
+    }
+
+    if self.dest.is_none() && !self.split_top_level {
+      if let Some(dir) = &self.output_dir {
+        let Some(stem) = self.source().file_stem().filter(|_| !self.reads_stdin()) else {
+          return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+            "an output directory needs a source file to name the destination after")));
+        };
+        self.dest = Some(dir.join(stem).with_extension(self.output_extension()));
+      }
+    }
+
+    if self.is_remote() {
+      if cfg!(not(feature = "remote")) {
+        return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+          "URL sources can only be fetched when jsonprops is built with the `remote` feature")));
+      }
+      if self.source_name().starts_with("https://") {
+        return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+          "https:// sources cannot be fetched, as TLS is not supported: download the source first, \
+          or pipe it into `-`")));
+      }
+      if self.watch || self.stream || self.ndjson {
+        return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+          "URL sources are fetched whole and once, they cannot be watched, streamed or read as \
+          NDJSON")));
+      }
+    }
+
+    if self.split_top_level && (self.output_dir.is_none() || self.dest.is_some()) {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "splitting by top-level key needs an output directory instead of a destination file")));
+    }
+
+    if self.append && self.dest.is_none() {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "appending needs a destination file to append to")));
+    }
+
+    if self.append && self.output == OutputFormat::Xml {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "xml output cannot be appended to, as a document has a single root element")));
+    }
+
+    if self.watch && (self.dest.is_none() || self.reads_stdin()) {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "watch mode needs both a source and a destination file")));
+    }
+
+    if self.check && self.dest.is_none() {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "check mode needs a destination file to check")));
+    }
+
+    let sep = &self.namespace_separator;
+    if sep.is_empty() || sep.chars().any(|c| c.is_whitespace() || c == ':' || c == '=') {
+      return Err(ConfigValidationError::InvalidNamespaceSeparatorError(sep.clone()));
+    }
+
+    if self.on_empty_key == EmptyKeyHandling::Placeholder && self.empty_key_placeholder.is_empty() {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "the placeholder of empty keys cannot be empty itself")));
+    }
+
+    if let Some(pattern) = self.key_pattern.as_deref() {
+      Regex::new(pattern).map_err(|e| {
+        ConfigValidationError::InvalidKeyPatternError(format!("[ {pattern} ]: {e}"))
+      })?;
+    }
+
+    if self.list_delimiter.is_empty() {
+      return Err(ConfigValidationError::InvalidListDelimiterError(self.list_delimiter.clone()));
+    }
+
+    if self.array_base > 1 {
+      return Err(ConfigValidationError::InvalidArrayBaseError(self.array_base));
+    }
+
+    if let Some(pointer) = self.pointer.as_ref().filter(|p| !p.is_empty() && !p.starts_with('/')) {
+      return Err(ConfigValidationError::InvalidPointerError(pointer.clone()));
+    }
+
+    let is_command_invalid = |cmd: &&String| transform::split_command(cmd).is_none();
+    if let Some(cmd) = self.transform_cmd.as_ref().filter(is_command_invalid) {
+      return Err(ConfigValidationError::InvalidTransformCommandError(cmd.clone()));
+    }
+
+    let is_separator_set = self.entry_separator != EntrySeparator::Equals || self.pad_separator;
+    if self.output != OutputFormat::Properties && is_separator_set {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "an entry separator cannot be used with xml or env output")));
+    }
+
+    let is_spaced = matches!(
+      self.entry_separator, EntrySeparator::ColonSpaced | EntrySeparator::EqualsSpaced);
+    if self.pad_separator && is_spaced {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "the spaced entry separators cannot be padded any further")));
+    }
+
+    if self.quote_style != QuoteStyle::None && self.list_escape.is_some() {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "quoted list members cannot be escaped with a list escape character")));
+    }
+
+    if self.check && self.output != OutputFormat::Properties {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "only properties output can be checked")));
+    }
+
+    if self.verify && self.output != OutputFormat::Properties {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "only properties output can be verified")));
+    }
+
+    if self.integer_floats && self.force_decimal {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "floats cannot be written as integers while integers are written as decimals")));
+    }
+
+    if self.keys_only && self.values_only {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "only one of keys or values can be written alone")));
+    }
+
+    let is_single_column = self.keys_only || self.values_only;
+    if is_single_column && (self.output != OutputFormat::Properties || self.verify) {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "keys or values can only be written alone in unverified properties output")));
+    }
+
+    if self.annotate_types && self.output == OutputFormat::Xml {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "types can only be annotated in properties or env output")));
+    }
+
+    if self.comment_field.is_some() && self.output == OutputFormat::Xml {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "comment fields can only be written in properties or env output")));
+    }
+
+    let is_percent_encoded = self.key_encoding == KeyEncoding::Percent;
+    if is_percent_encoded && (self.output != OutputFormat::Properties || self.no_key_escape) {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "keys can only be percent-encoded in properties output, instead of escaping them")));
+    }
+
+    if is_percent_encoded && self.verify {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "percent-encoded keys cannot be verified, as they are read back encoded")));
+    }
+
+    if is_percent_encoded && self.diff_against.is_some() {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "percent-encoded keys cannot be diffed, as the old file is read back encoded")));
+    }
+
+    let writes_entries =
+      self.output == OutputFormat::Properties && !self.keys_only && !self.values_only;
+    if self.diff_against.is_some() && !writes_entries {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "differences can only be written as the entries of properties output")));
+    }
+
+    if self.verify && (self.no_key_escape || self.space_escape != SpaceEscape::All) {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "unescaped keys cannot be verified, as they may not be read back as written")));
+    }
+
+    if self.stream && self.input_format() != InputFormat::Json {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "only JSON sources can be streamed")));
+    }
+
+    if self.strict && self.stream {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "duplicate keys can only be detected in sources which are not streamed")));
+    }
+
+    if self.ndjson && (self.stream || self.input_format() != InputFormat::Json) {
+      return Err(ConfigValidationError::IncompatibleOptionsError(String::from(
+        "only JSON sources can be read as NDJSON, and they are always read line by line")));
+    }
+
     Ok(self)
   }
 
+  fn validate_source(source: &Path) -> Result<(), ConfigValidationError> {
+    let source_exists = source
+      .try_exists()
+      .map_err(|_| Self::invalid_path_error(source))?;
+    if !source_exists {
+      return Err(ConfigValidationError::MissingFileError(Self::path_to_string(source)));
+    }
+
+    Ok(())
+  }
+
   fn invalid_path_error(path: &Path) -> ConfigValidationError {
     ConfigValidationError::InvalidPathError(Self::path_to_string(path))
   }
@@ -106,23 +1289,856 @@ impl Config {
     String::from(path.to_str().unwrap_or(str_constant::EMPTY))
   }
 
+  /// Returns the source path, which is the standard input (`-`) in serve mode.
   pub fn source(&self) -> &Path {
-    &self.source
+    self.source.as_deref().unwrap_or(Path::new(str_constant::DASH))
   }
 
+  /// Returns a human-readable name of the source, for messages.
+  pub fn source_name(&self) -> String {
+    if self.reads_stdin() {
+      String::from(str_constant::STDIN)
+    } else {
+      self.source().display().to_string()
+    }
+  }
+
+  /// Returns `true` if the source JSON is to be read from the standard input.
+  pub fn reads_stdin(&self) -> bool {
+    self.source().as_os_str() == str_constant::DASH
+  }
+
+  /// Returns `true` if the source is an `http://` or `https://` URL to fetch.
+  pub fn is_remote(&self) -> bool {
+    let source = self.source_name();
+    source.starts_with("http://") || source.starts_with("https://")
+  }
+
+  /// Returns the destination file, which is derived from the source in the output directory if
+  /// only that is given.
   pub fn dest(&self) -> Option<&Path> {
     self.dest.as_deref()
   }
 
+  pub fn output_dir(&self) -> Option<&Path> {
+    self.output_dir.as_deref()
+  }
+
+  /// Returns the configuration converting the object at `index` of an NDJSON source, writing to
+  /// the destination derived from the configured one (see `--ndjson`).
+  pub fn for_ndjson_object(&self, index: usize) -> Config {
+    let dest = self.dest.as_deref().map(|dest| {
+      let name = dest.file_name().unwrap_or_default().to_string_lossy();
+      if name.contains("{}") {
+        return dest.with_file_name(name.replace("{}", &index.to_string()));
+      }
+      let stem = dest.file_stem().unwrap_or_default().to_string_lossy();
+      let name = match dest.extension() {
+        Some(ext) => format!("{stem}_{index}.{}", ext.to_string_lossy()),
+        None => format!("{stem}_{index}"),
+      };
+      dest.with_file_name(name)
+    });
+    Config { dest, ..self.clone() }
+  }
+
+  /// Returns the configuration writing the entries under the top-level key `name` into their own
+  /// file in the output directory (see `--split-top-level`).
+  pub fn for_top_level_key(&self, name: &str) -> Config {
+    let dir = self.output_dir.as_deref().unwrap_or(Path::new(str_constant::DOT));
+    let dest = dir.join(format!("{name}.{}", self.output_extension()));
+    Config { dest: Some(dest), split_top_level: false, ..self.clone() }
+  }
+
+  /// Returns the extension of the files of the output format, without the leading dot.
+  fn output_extension(&self) -> &'static str {
+    match self.output {
+      OutputFormat::Properties => "properties",
+      OutputFormat::Xml => "xml",
+      OutputFormat::Env => "env",
+    }
+  }
+
+  /// Returns `true` if progress was requested and it can be shown without garbling the output.
+  pub fn shows_progress(&self) -> bool {
+    self.progress && self.dest.is_some() && !self.dry_run && std::io::stderr().is_terminal()
+  }
+
   pub fn list_handling(&self) -> &ListHandling {
     &self.list_handling
   }
 
+  pub fn namespace_separator(&self) -> &str {
+    &self.namespace_separator
+  }
+
+  pub fn max_depth(&self) -> usize {
+    self.max_depth
+  }
+
+  pub fn order(&self) -> &EntryOrder {
+    &self.order
+  }
+
+  pub fn array_style(&self) -> &ArrayStyle {
+    &self.array_style
+  }
+
+  pub fn array_base(&self) -> usize {
+    self.array_base
+  }
+
+  /// Returns the key prefix, if there is a non-empty one.
+  pub fn prefix(&self) -> Option<&str> {
+    self.prefix.as_deref().filter(|p| !p.is_empty())
+  }
+
+  pub fn input_format(&self) -> InputFormat {
+    self.input_format_of(self.source())
+  }
+
+  /// Returns the format of any input file, which is the same as the source format if it is set.
+  pub fn input_format_of(&self, path: &Path) -> InputFormat {
+    self.format.unwrap_or_else(|| InputFormat::of_path(path))
+  }
+
+  pub fn merge(&self) -> &[PathBuf] {
+    &self.merge
+  }
+
+  pub fn array_merge(&self) -> ArrayMerge {
+    self.array_merge
+  }
+
+  pub fn patch(&self) -> &[PathBuf] {
+    &self.patch
+  }
+
+  pub fn list_delimiter(&self) -> &str {
+    &self.list_delimiter
+  }
+
+  pub fn list_escape(&self) -> Option<char> {
+    self.list_escape
+  }
+
+  pub fn on_complex_list(&self) -> &ComplexListHandling {
+    &self.on_complex_list
+  }
+
+  pub fn output(&self) -> &OutputFormat {
+    &self.output
+  }
+
+  /// Returns `true` if the flattened key passes the `--include` and `--exclude` filters.
+  pub fn is_key_selected(&self, key: &str) -> bool {
+    let sep = self.namespace_separator();
+    let included = self.include.is_empty() || self.include.iter().any(|g| g.matches(key, sep));
+    included && !self.exclude.iter().any(|g| g.matches(key, sep))
+  }
+
+  /// Returns `true` if the value of the flattened key is to be redacted.
+  pub fn is_key_redacted(&self, key: &str) -> bool {
+    let sep = self.namespace_separator();
+    self.redact.iter().any(|g| g.matches(key, sep))
+  }
+
+  pub fn redact_placeholder(&self) -> &str {
+    &self.redact_placeholder
+  }
+
+  /// Returns `true` if the value of the flattened key is to be base64 decoded.
+  pub fn is_key_base64(&self, key: &str) -> bool {
+    let sep = self.namespace_separator();
+    self.base64_decode.iter().any(|g| g.matches(key, sep))
+  }
+
+  pub fn on_invalid_utf8(&self) -> &InvalidUtf8Handling {
+    &self.on_invalid_utf8
+  }
+
+  /// Returns the first `--format-path` formatter applying to the value of the flattened key.
+  pub(crate) fn value_format(&self, key: &str) -> Option<&PathFormat> {
+    let sep = self.namespace_separator();
+    self.format_path.iter().find(|f| f.matches(key, sep))
+  }
+
+  pub fn pointer(&self) -> Option<&str> {
+    self.pointer.as_deref()
+  }
+
+  /// Returns the program and arguments of the transform command, if there is one.
+  pub(crate) fn transform_command(&self) -> Option<Vec<String>> {
+    self.transform_cmd.as_deref().and_then(transform::split_command)
+  }
+
+  pub fn key_case(&self) -> &KeyCase {
+    &self.key_case
+  }
+
+  pub fn key_encoding(&self) -> &KeyEncoding {
+    &self.key_encoding
+  }
+
+  pub fn space_escape(&self) -> SpaceEscape {
+    self.space_escape
+  }
+
+  pub(crate) fn key_replace(&self) -> &[Replacement] {
+    &self.key_replace
+  }
+
+  pub fn on_empty_key(&self) -> &EmptyKeyHandling {
+    &self.on_empty_key
+  }
+
+  pub fn empty_key_placeholder(&self) -> &str {
+    &self.empty_key_placeholder
+  }
+
+  /// Returns `true` if keys are validated against a `--key-pattern`.
+  pub(crate) fn validates_keys(&self) -> bool {
+    self.validate_keys && self.key_pattern.is_some()
+  }
+
+  /// Returns the regular expression matching the whole of every valid key, if there is one.
+  pub(crate) fn key_pattern(&self) -> Option<Regex> {
+    self.key_pattern.as_deref().and_then(|p| Regex::new(&format!("^(?:{p})$")).ok())
+  }
+
+  pub fn bool_format(&self) -> &BoolFormat {
+    &self.bool_format
+  }
+
+  pub fn null_handling(&self) -> &NullHandling {
+    &self.null_handling
+  }
+
+  pub fn list_null_handling(&self) -> &NullHandling {
+    &self.list_null_handling
+  }
+
+  pub fn empty_object(&self) -> &EmptyObjectHandling {
+    &self.empty_object
+  }
+
+  pub fn on_duplicate(&self) -> &DuplicateHandling {
+    &self.on_duplicate
+  }
+
+  pub fn quote_style(&self) -> &QuoteStyle {
+    &self.quote_style
+  }
+
+  pub fn number_format(&self) -> &NumberFormat {
+    &self.number_format
+  }
+
+  pub fn on_nonfinite(&self) -> &NonFiniteHandling {
+    &self.on_nonfinite
+  }
+
+  pub fn nonfinite_placeholder(&self) -> Option<&str> {
+    self.nonfinite_placeholder.as_deref()
+  }
+
+  /// Returns the file the log is written to, or `None` if file logging is disabled.
+  pub fn log_file(&self) -> Option<&Path> {
+    Some(self.log_file.as_path()).filter(|_| !self.no_log_file)
+  }
+
+  pub fn on_missing_env(&self) -> &MissingEnvHandling {
+    &self.on_missing_env
+  }
+
+  pub fn max_value_length(&self) -> Option<usize> {
+    self.max_value_length
+  }
+
+  pub fn on_oversize(&self) -> &OversizeHandling {
+    &self.on_oversize
+  }
+
+  pub fn max_array_elements(&self) -> Option<usize> {
+    self.max_array_elements
+  }
+
+  pub fn on_array_overflow(&self) -> &ArrayOverflowHandling {
+    &self.on_array_overflow
+  }
+
+  pub fn comment_field(&self) -> Option<&str> {
+    self.comment_field.as_deref()
+  }
+
+  pub fn schema(&self) -> Option<&Path> {
+    self.schema.as_deref()
+  }
+
+  pub fn key_manifest(&self) -> Option<&Path> {
+    self.key_manifest.as_deref()
+  }
+
+  pub fn diff_against(&self) -> Option<&Path> {
+    self.diff_against.as_deref()
+  }
+
+  pub fn report(&self) -> Option<&Path> {
+    self.report.as_deref()
+  }
+
+  pub fn serve(&self) -> Option<&str> {
+    self.serve.as_deref()
+  }
+
+  /// Returns the time after which fetching a URL source is given up.
+  pub fn timeout(&self) -> Duration {
+    self.timeout.map_or(DEFAULT_TIMEOUT, Into::into)
+  }
+
+  /// Returns the headers sent when fetching a URL source, each written as `Name: value`.
+  pub fn http_headers(&self) -> &[String] {
+    &self.http_header
+  }
+
+  pub fn line_ending(&self) -> &'static str {
+    match self.line_ending {
+      LineEnding::Lf => str_constant::LF,
+      LineEnding::Crlf => str_constant::CRLF,
+      LineEnding::Platform if cfg!(windows) => str_constant::CRLF,
+      LineEnding::Platform => str_constant::LF,
+    }
+  }
+
   pub fn entry_separator(&self) -> &'static str {
     match self.entry_separator {
       EntrySeparator::Equals => str_constant::EQ,
       EntrySeparator::Colon => str_constant::COLON,
       EntrySeparator::Space => str_constant::SPACE,
+      EntrySeparator::ColonSpaced => str_constant::COLON_SPACED,
+      EntrySeparator::EqualsSpaced => str_constant::EQ_SPACED,
     }
   }
 }
+
+
+/// Builds a [Config] in code, without parsing any command line arguments.
+///
+/// Options not set explicitly keep their command line defaults.
+#[derive(Debug, Default)]
+pub struct ConfigBuilder(Config);
+
+impl ConfigBuilder {
+  pub fn source(mut self, source: impl Into<PathBuf>) -> Self {
+    self.0.source = Some(source.into());
+    self
+  }
+
+  pub fn dest(mut self, dest: impl Into<PathBuf>) -> Self {
+    self.0.dest = Some(dest.into());
+    self
+  }
+
+  pub fn output_dir(mut self, output_dir: impl Into<PathBuf>) -> Self {
+    self.0.output_dir = Some(output_dir.into());
+    self
+  }
+
+  pub fn mkdir(mut self, mkdir: bool) -> Self {
+    self.0.mkdir = mkdir;
+    self
+  }
+
+  pub fn split_top_level(mut self, split_top_level: bool) -> Self {
+    self.0.split_top_level = split_top_level;
+    self
+  }
+
+  pub fn debug(mut self, debug: bool) -> Self {
+    self.0.debug = debug;
+    self
+  }
+
+  pub fn quiet(mut self, quiet: bool) -> Self {
+    self.0.quiet = quiet;
+    self
+  }
+
+  pub fn list_handling(mut self, list_handling: ListHandling) -> Self {
+    self.0.list_handling = list_handling;
+    self
+  }
+
+  pub fn entry_separator(mut self, entry_separator: EntrySeparator) -> Self {
+    self.0.entry_separator = entry_separator;
+    self
+  }
+
+  pub fn discard_wsp(mut self, discard_wsp: bool) -> Self {
+    self.0.discard_wsp = discard_wsp;
+    self
+  }
+
+  pub fn namespace_separator(mut self, namespace_separator: impl Into<String>) -> Self {
+    self.0.namespace_separator = namespace_separator.into();
+    self
+  }
+
+  pub fn ascii(mut self, ascii: bool) -> Self {
+    self.0.ascii = ascii;
+    self
+  }
+
+  pub fn no_key_escape(mut self, no_key_escape: bool) -> Self {
+    self.0.no_key_escape = no_key_escape;
+    self
+  }
+
+  pub fn key_encoding(mut self, key_encoding: KeyEncoding) -> Self {
+    self.0.key_encoding = key_encoding;
+    self
+  }
+
+  pub fn space_escape(mut self, space_escape: SpaceEscape) -> Self {
+    self.0.space_escape = space_escape;
+    self
+  }
+
+  pub fn escape_all_hashes(mut self, escape_all_hashes: bool) -> Self {
+    self.0.escape_all_hashes = escape_all_hashes;
+    self
+  }
+
+  pub fn max_depth(mut self, max_depth: usize) -> Self {
+    self.0.max_depth = max_depth;
+    self
+  }
+
+  pub fn order(mut self, order: EntryOrder) -> Self {
+    self.0.order = order;
+    self
+  }
+
+  pub fn array_style(mut self, array_style: ArrayStyle) -> Self {
+    self.0.array_style = array_style;
+    self
+  }
+
+  pub fn array_base(mut self, array_base: usize) -> Self {
+    self.0.array_base = array_base;
+    self
+  }
+
+  pub fn prefix(mut self, prefix: impl Into<String>) -> Self {
+    self.0.prefix = Some(prefix.into());
+    self
+  }
+
+  pub fn prefix_from_filename(mut self, prefix_from_filename: bool) -> Self {
+    self.0.prefix_from_filename = prefix_from_filename;
+    self
+  }
+
+  pub fn format(mut self, format: InputFormat) -> Self {
+    self.0.format = Some(format);
+    self
+  }
+
+  pub fn list_delimiter(mut self, list_delimiter: impl Into<String>) -> Self {
+    self.0.list_delimiter = list_delimiter.into();
+    self
+  }
+
+  pub fn list_escape(mut self, list_escape: char) -> Self {
+    self.0.list_escape = Some(list_escape);
+    self
+  }
+
+  pub fn on_complex_list(mut self, on_complex_list: ComplexListHandling) -> Self {
+    self.0.on_complex_list = on_complex_list;
+    self
+  }
+
+  pub fn header(mut self, header: bool) -> Self {
+    self.0.header = header;
+    self
+  }
+
+  pub fn annotate_types(mut self, annotate_types: bool) -> Self {
+    self.0.annotate_types = annotate_types;
+    self
+  }
+
+  pub fn comment_field(mut self, comment_field: impl Into<String>) -> Self {
+    self.0.comment_field = Some(comment_field.into());
+    self
+  }
+
+  pub fn output(mut self, output: OutputFormat) -> Self {
+    self.0.output = output;
+    self
+  }
+
+  pub fn keys_only(mut self, keys_only: bool) -> Self {
+    self.0.keys_only = keys_only;
+    self
+  }
+
+  pub fn values_only(mut self, values_only: bool) -> Self {
+    self.0.values_only = values_only;
+    self
+  }
+
+  /// Adds a glob pattern to the `--include` filters.
+  pub fn include(mut self, pattern: &str) -> Self {
+    self.0.include.push(Glob::new(pattern));
+    self
+  }
+
+  /// Adds a glob pattern to the `--exclude` filters.
+  pub fn exclude(mut self, pattern: &str) -> Self {
+    self.0.exclude.push(Glob::new(pattern));
+    self
+  }
+
+  /// Adds a glob pattern of the keys to redact.
+  pub fn redact(mut self, pattern: &str) -> Self {
+    self.0.redact.push(Glob::new(pattern));
+    self
+  }
+
+  pub fn redact_placeholder(mut self, redact_placeholder: impl Into<String>) -> Self {
+    self.0.redact_placeholder = redact_placeholder.into();
+    self
+  }
+
+  /// Adds a glob pattern of the keys to base64 decode.
+  pub fn base64_decode(mut self, pattern: &str) -> Self {
+    self.0.base64_decode.push(Glob::new(pattern));
+    self
+  }
+
+  pub fn on_invalid_utf8(mut self, on_invalid_utf8: InvalidUtf8Handling) -> Self {
+    self.0.on_invalid_utf8 = on_invalid_utf8;
+    self
+  }
+
+  /// Adds a formatter of the values of the matching keys, written as `<glob>=<formatter>`.
+  pub fn format_path(mut self, path_format: &str) -> Result<Self, ConfigValidationError> {
+    let path_format = path_format.parse()
+      .map_err(ConfigValidationError::InvalidFormatPathError)?;
+    self.0.format_path.push(path_format);
+    Ok(self)
+  }
+
+  /// Adds a JSON file to be deep-merged into the source.
+  pub fn merge(mut self, overlay: impl Into<PathBuf>) -> Self {
+    self.0.merge.push(overlay.into());
+    self
+  }
+
+  pub fn array_merge(mut self, array_merge: ArrayMerge) -> Self {
+    self.0.array_merge = array_merge;
+    self
+  }
+
+  /// Adds a JSON Merge Patch file to be applied to the source.
+  pub fn patch(mut self, patch: impl Into<PathBuf>) -> Self {
+    self.0.patch.push(patch.into());
+    self
+  }
+
+  pub fn pointer(mut self, pointer: impl Into<String>) -> Self {
+    self.0.pointer = Some(pointer.into());
+    self
+  }
+
+  pub fn allow_top_array(mut self, allow_top_array: bool) -> Self {
+    self.0.allow_top_array = allow_top_array;
+    self
+  }
+
+  pub fn key_case(mut self, key_case: KeyCase) -> Self {
+    self.0.key_case = key_case;
+    self
+  }
+
+  /// Adds a substitution of every key segment, written as `<regex>=<replacement>`.
+  pub fn key_replace(mut self, replacement: &str) -> Result<Self, ConfigValidationError> {
+    let replacement = replacement.parse()
+      .map_err(ConfigValidationError::InvalidKeyReplacementError)?;
+    self.0.key_replace.push(replacement);
+    Ok(self)
+  }
+
+  pub fn on_empty_key(mut self, on_empty_key: EmptyKeyHandling) -> Self {
+    self.0.on_empty_key = on_empty_key;
+    self
+  }
+
+  pub fn empty_key_placeholder(mut self, empty_key_placeholder: impl Into<String>) -> Self {
+    self.0.empty_key_placeholder = empty_key_placeholder.into();
+    self
+  }
+
+  pub fn validate_keys(mut self, validate_keys: bool) -> Self {
+    self.0.validate_keys = validate_keys;
+    self
+  }
+
+  /// Defines the regular expression every key must match with `validate_keys`.
+  pub fn key_pattern(mut self, key_pattern: impl Into<String>) -> Self {
+    self.0.key_pattern = Some(key_pattern.into());
+    self
+  }
+
+  pub fn bool_format(mut self, bool_format: BoolFormat) -> Self {
+    self.0.bool_format = bool_format;
+    self
+  }
+
+  pub fn null_handling(mut self, null_handling: NullHandling) -> Self {
+    self.0.null_handling = null_handling;
+    self
+  }
+
+  pub fn list_null_handling(mut self, list_null_handling: NullHandling) -> Self {
+    self.0.list_null_handling = list_null_handling;
+    self
+  }
+
+  pub fn empty_object(mut self, empty_object: EmptyObjectHandling) -> Self {
+    self.0.empty_object = empty_object;
+    self
+  }
+
+  pub fn on_duplicate(mut self, on_duplicate: DuplicateHandling) -> Self {
+    self.0.on_duplicate = on_duplicate;
+    self
+  }
+
+  pub fn stream(mut self, stream: bool) -> Self {
+    self.0.stream = stream;
+    self
+  }
+
+  pub fn watch(mut self, watch: bool) -> Self {
+    self.0.watch = watch;
+    self
+  }
+
+  pub fn dry_run(mut self, dry_run: bool) -> Self {
+    self.0.dry_run = dry_run;
+    self
+  }
+
+  pub fn check(mut self, check: bool) -> Self {
+    self.0.check = check;
+    self
+  }
+
+  pub fn append(mut self, append: bool) -> Self {
+    self.0.append = append;
+    self
+  }
+
+  pub fn diff_against(mut self, diff_against: impl Into<PathBuf>) -> Self {
+    self.0.diff_against = Some(diff_against.into());
+    self
+  }
+
+  pub fn quote_style(mut self, quote_style: QuoteStyle) -> Self {
+    self.0.quote_style = quote_style;
+    self
+  }
+
+  pub fn number_format(mut self, number_format: NumberFormat) -> Self {
+    self.0.number_format = number_format;
+    self
+  }
+
+  pub fn integer_floats(mut self, integer_floats: bool) -> Self {
+    self.0.integer_floats = integer_floats;
+    self
+  }
+
+  pub fn force_decimal(mut self, force_decimal: bool) -> Self {
+    self.0.force_decimal = force_decimal;
+    self
+  }
+
+  pub fn on_nonfinite(mut self, on_nonfinite: NonFiniteHandling) -> Self {
+    self.0.on_nonfinite = on_nonfinite;
+    self
+  }
+
+  pub fn nonfinite_placeholder(mut self, nonfinite_placeholder: impl Into<String>) -> Self {
+    self.0.nonfinite_placeholder = Some(nonfinite_placeholder.into());
+    self
+  }
+
+  pub fn log_file(mut self, log_file: impl Into<PathBuf>) -> Self {
+    self.0.log_file = log_file.into();
+    self
+  }
+
+  pub fn no_log_file(mut self, no_log_file: bool) -> Self {
+    self.0.no_log_file = no_log_file;
+    self
+  }
+
+  pub fn fail_on_empty(mut self, fail_on_empty: bool) -> Self {
+    self.0.fail_on_empty = fail_on_empty;
+    self
+  }
+
+  pub fn expand_env(mut self, expand_env: bool) -> Self {
+    self.0.expand_env = expand_env;
+    self
+  }
+
+  pub fn on_missing_env(mut self, on_missing_env: MissingEnvHandling) -> Self {
+    self.0.on_missing_env = on_missing_env;
+    self
+  }
+
+  pub fn stats(mut self, stats: bool) -> Self {
+    self.0.stats = stats;
+    self
+  }
+
+  pub fn trim_trailing(mut self, trim_trailing: bool) -> Self {
+    self.0.trim_trailing = trim_trailing;
+    self
+  }
+
+  pub fn max_value_length(mut self, max_value_length: usize) -> Self {
+    self.0.max_value_length = Some(max_value_length);
+    self
+  }
+
+  pub fn on_oversize(mut self, on_oversize: OversizeHandling) -> Self {
+    self.0.on_oversize = on_oversize;
+    self
+  }
+
+  pub fn max_array_elements(mut self, max_array_elements: usize) -> Self {
+    self.0.max_array_elements = Some(max_array_elements);
+    self
+  }
+
+  pub fn on_array_overflow(mut self, on_array_overflow: ArrayOverflowHandling) -> Self {
+    self.0.on_array_overflow = on_array_overflow;
+    self
+  }
+
+  pub fn schema(mut self, schema: impl Into<PathBuf>) -> Self {
+    self.0.schema = Some(schema.into());
+    self
+  }
+
+  pub fn coerce_numeric_strings(mut self, coerce_numeric_strings: bool) -> Self {
+    self.0.coerce_numeric_strings = coerce_numeric_strings;
+    self
+  }
+
+  pub fn normalize_bool_strings(mut self, normalize_bool_strings: bool) -> Self {
+    self.0.normalize_bool_strings = normalize_bool_strings;
+    self
+  }
+
+  pub fn bool_strings_ignore_case(mut self, bool_strings_ignore_case: bool) -> Self {
+    self.0.bool_strings_ignore_case = bool_strings_ignore_case;
+    self
+  }
+
+  pub fn verify(mut self, verify: bool) -> Self {
+    self.0.verify = verify;
+    self
+  }
+
+  pub fn progress(mut self, progress: bool) -> Self {
+    self.0.progress = progress;
+    self
+  }
+
+  pub fn ndjson(mut self, ndjson: bool) -> Self {
+    self.0.ndjson = ndjson;
+    self
+  }
+
+  pub fn keep_going(mut self, keep_going: bool) -> Self {
+    self.0.keep_going = keep_going;
+    self
+  }
+
+  pub fn count_lists(mut self, count_lists: bool) -> Self {
+    self.0.count_lists = count_lists;
+    self
+  }
+
+  pub fn dedup_list(mut self, dedup_list: bool) -> Self {
+    self.0.dedup_list = dedup_list;
+    self
+  }
+
+  pub fn sort_list(mut self, sort_list: bool) -> Self {
+    self.0.sort_list = sort_list;
+    self
+  }
+
+  pub fn sort_list_numeric(mut self, sort_list_numeric: bool) -> Self {
+    self.0.sort_list_numeric = sort_list_numeric;
+    self
+  }
+
+  pub fn key_manifest(mut self, key_manifest: impl Into<PathBuf>) -> Self {
+    self.0.key_manifest = Some(key_manifest.into());
+    self
+  }
+
+  pub fn report(mut self, report: impl Into<PathBuf>) -> Self {
+    self.0.report = Some(report.into());
+    self
+  }
+
+  pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+    self.0.line_ending = line_ending;
+    self
+  }
+
+  pub fn parse_embedded_json(mut self, parse_embedded_json: bool) -> Self {
+    self.0.parse_embedded_json = parse_embedded_json;
+    self
+  }
+
+  pub fn transform_cmd(mut self, transform_cmd: impl Into<String>) -> Self {
+    self.0.transform_cmd = Some(transform_cmd.into());
+    self
+  }
+
+  pub fn strict(mut self, strict: bool) -> Self {
+    self.0.strict = strict;
+    self
+  }
+
+  pub fn pad_separator(mut self, pad_separator: bool) -> Self {
+    self.0.pad_separator = pad_separator;
+    self
+  }
+
+  /// Overrides the order, number format, line ending and header settings on [ConfigBuilder::build]
+  /// to make the output deterministic.
+  pub fn canonical(mut self, canonical: bool) -> Self {
+    self.0.canonical = canonical;
+    self
+  }
+
+  /// Validates the options and returns the [Config].
+  ///
+  /// The source and destination paths are not checked, as they are only used by
+  /// [Properties::export](crate::Properties::export). Use [Config::validate] to check them.
+  pub fn build(self) -> Result<Config, ConfigValidationError> {
+    self.0.validate_options()
+  }
+}
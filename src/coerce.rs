@@ -0,0 +1,130 @@
+//! Coercion of string values to the JSON types they represent, before conversion.
+
+use serde_json::{Number, Value};
+
+/// Coerces the strings of `value` in place.
+///
+/// A string becomes a number or a boolean if the `schema` (a JSON Schema of `value`) declares its
+/// type as `integer`, `number` or `boolean`, and the string is a valid literal of that type. The
+/// `type`, `properties`, `additionalProperties` and `items` keywords of the schema are honoured,
+/// everything else is ignored.
+///
+/// If `numeric_strings` is `true`, every other string which is a valid JSON number becomes a
+/// number as well, unless the schema declares it as a `string`.
+///
+/// Returns the JSON Pointers (RFC 6901) of the coerced strings.
+pub(crate) fn coerce(
+  value: &mut Value,
+  schema: Option<&Value>,
+  numeric_strings: bool,
+) -> Vec<String> {
+  let mut coerced = Vec::new();
+  coerce_at(value, schema, numeric_strings, String::new(), &mut coerced);
+  coerced
+}
+
+fn coerce_at(
+  value: &mut Value,
+  schema: Option<&Value>,
+  numeric_strings: bool,
+  pointer: String,
+  coerced: &mut Vec<String>,
+) {
+  let member = |token: &str| format!("{pointer}/{}", token.replace('~', "~0").replace('/', "~1"));
+  match value {
+    Value::Object(members) => {
+      for (k, v) in members {
+        let schema = schema.and_then(|s| member_schema(s, k));
+        coerce_at(v, schema, numeric_strings, member(k), coerced);
+      }
+    }
+    Value::Array(values) => {
+      for (i, v) in values.iter_mut().enumerate() {
+        let schema = schema.and_then(|s| s.get("items"));
+        coerce_at(v, schema, numeric_strings, member(&i.to_string()), coerced);
+      }
+    }
+    Value::String(s) => {
+      let types = schema.map(types_of).unwrap_or_default();
+      if let Some(value_of_type) = coerce_string(s, &types, numeric_strings) {
+        *value = value_of_type;
+        coerced.push(pointer);
+      }
+    }
+    Value::Null | Value::Bool(_) | Value::Number(_) => {}
+  }
+}
+
+fn member_schema<'a>(schema: &'a Value, key: &str) -> Option<&'a Value> {
+  schema.get("properties")
+    .and_then(|properties| properties.get(key))
+    .or_else(|| schema.get("additionalProperties").filter(|s| s.is_object()))
+}
+
+fn types_of(schema: &Value) -> Vec<&str> {
+  match schema.get("type") {
+    Some(Value::String(t)) => vec![t.as_str()],
+    Some(Value::Array(types)) => types.iter().filter_map(Value::as_str).collect(),
+    _ => vec![],
+  }
+}
+
+fn coerce_string(s: &str, types: &[&str], numeric_strings: bool) -> Option<Value> {
+  if types.contains(&"string") {
+    return None;
+  }
+
+  let coerced = types.iter().find_map(|t| match *t {
+    "integer" => parse_number(s).filter(|_| !s.contains(['.', 'e', 'E'])),
+    "number" => parse_number(s),
+    "boolean" => s.parse::<bool>().ok().map(Value::Bool),
+    _ => None,
+  });
+  coerced.or_else(|| parse_number(s).filter(|_| numeric_strings))
+}
+
+fn parse_number(s: &str) -> Option<Value> {
+  s.parse::<Number>().ok().map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::coerce::coerce;
+
+  #[test]
+  fn strings_are_coerced_to_the_types_of_the_schema() {
+    let schema = json!({
+      "type" : "object",
+      "properties" : {
+        "port" : { "type" : "integer" },
+        "ratio" : { "type" : ["number", "null"] },
+        "ssl" : { "type" : "boolean" },
+        "version" : { "type" : "string" },
+        "hosts" : { "type" : "array", "items" : { "type" : "object", "additionalProperties" : {
+          "type" : "integer"
+        } } }
+      }
+    });
+    let mut value = json!({
+      "port" : "8080", "ratio" : "0.5", "ssl" : "true", "version" : "2", "other" : "3",
+      "hosts" : [ { "weight" : "10" }, { "weight" : "heavy" } ]
+    });
+    let coerced = coerce(&mut value, Some(&schema), false);
+    assert_eq!(coerced, ["/hosts/0/weight", "/port", "/ratio", "/ssl"]);
+    assert_eq!(value, json!({
+      "port" : 8080, "ratio" : 0.5, "ssl" : true, "version" : "2", "other" : "3",
+      "hosts" : [ { "weight" : 10 }, { "weight" : "heavy" } ]
+    }));
+  }
+
+  #[test]
+  fn numeric_strings_are_coerced_unless_the_schema_declares_a_string() {
+    let schema = json!({ "properties" : { "zip" : { "type" : "string" } } });
+    let mut value = json!({ "port" : "8080", "zip" : "1234", "id" : "007", "name" : "x1" });
+    let coerced = coerce(&mut value, Some(&schema), true);
+    assert_eq!(value, json!({ "port" : 8080, "zip" : "1234", "id" : "007", "name" : "x1" }));
+    assert_eq!(coerced, ["/port"]);
+  }
+}
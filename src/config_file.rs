@@ -0,0 +1,124 @@
+//! Default options read from a TOML configuration file.
+
+use std::ffi::OsString;
+
+use clap::parser::ValueSource;
+use clap::{ArgMatches, Command};
+use serde_json::Value;
+
+/// Translates the options set in a configuration file to command line arguments, skipping the
+/// ones already given on the command line, or conflicting with an option given there.
+///
+/// Every key is the long name of an option, e.g. `list-handling = "multi-prop"` (`list_handling` is
+/// accepted as well). Flags are set by `true`, and options taking multiple values by arrays.
+pub(crate) fn to_args(
+  text: &str,
+  command: &Command,
+  matches: &ArgMatches,
+) -> Result<Vec<OsString>, String> {
+  let Value::Object(options) = crate::toml::from_str(text).map_err(|e| e.to_string())? else {
+    unreachable!("TOML documents are tables")
+  };
+
+  let mut args = Vec::new();
+  for (key, value) in options {
+    let long = key.replace('_', "-");
+    let arg = command.get_arguments()
+      .find(|arg| arg.get_long() == Some(long.as_str()) && long != "config")
+      .ok_or_else(|| format!("unknown option [ {key} ]"))?;
+    let on_command_line = |id: &str| matches.value_source(id) == Some(ValueSource::CommandLine);
+    let conflicts_with_command_line = command.get_arguments()
+      .filter(|other| on_command_line(other.get_id().as_str()))
+      .any(|other| {
+        command.get_arg_conflicts_with(other).contains(&arg)
+          || command.get_arg_conflicts_with(arg).contains(&other)
+      });
+    if on_command_line(arg.get_id().as_str()) || conflicts_with_command_line {
+      continue;
+    }
+
+    if !arg.get_action().takes_values() {
+      match value {
+        Value::Bool(true) => args.push(OsString::from(format!("--{long}"))),
+        Value::Bool(false) => {}
+        _ => return Err(format!("option [ {key} ] is a flag, it must be true or false")),
+      }
+      continue;
+    }
+
+    let values = match value {
+      Value::Array(values) => values,
+      value => vec![value],
+    };
+    for value in values {
+      let value = match value {
+        Value::String(s) => s,
+        Value::Number(_) | Value::Bool(_) => value.to_string(),
+        _ => return Err(format!("option [ {key} ] must be a string, number or boolean")),
+      };
+      args.push(OsString::from(format!("--{long}={value}")));
+    }
+  }
+  Ok(args)
+}
+
+#[cfg(test)]
+mod tests {
+  use std::ffi::OsString;
+
+  use clap::CommandFactory;
+
+  use crate::app_config::Config;
+  use crate::config_file::to_args;
+
+  fn args_of(text: &str, cli: &[&str]) -> Result<Vec<OsString>, String> {
+    let command = Config::command();
+    let matches = command.clone().get_matches_from(cli);
+    to_args(text, &command, &matches)
+  }
+
+  #[test]
+  fn options_are_translated_unless_given_on_the_command_line() {
+    let text = r#"
+      list-handling = "multi-prop"
+      discard_wsp = true
+      ascii = false
+      max-depth = 2
+      include = ["db.**", "app.*"]
+      order = "insertion"
+    "#;
+    let args = args_of(text, &["jsonprops", "foo.json", "--order", "sorted"]).unwrap();
+    let expected = [
+      "--discard-wsp", "--include=db.**", "--include=app.*", "--list-handling=multi-prop",
+      "--max-depth=2",
+    ];
+    assert_eq!(args, expected.map(OsString::from));
+  }
+
+  #[test]
+  fn options_conflicting_with_the_command_line_are_skipped() {
+    let text = "order = \"insertion\"\nheader = true\ndiscard-wsp = true";
+    let args = args_of(text, &["jsonprops", "foo.json", "--canonical"]).unwrap();
+    assert_eq!(args, ["--discard-wsp"].map(OsString::from));
+  }
+
+  #[test]
+  fn a_config_file_may_set_options_conflicting_with_the_command_line() {
+    let path = std::env::temp_dir().join(format!("jsonprops-config-{}.toml", std::process::id()));
+    std::fs::write(&path, "order = \"insertion\"\nheader = true\n").expect("file is written");
+    let config = Config::parse_with_config_file_from([
+      "jsonprops", "foo.json", "--canonical", "--config", path.to_str().unwrap(),
+    ]);
+    std::fs::remove_file(&path).expect("file is removed");
+    let config = config.expect("config file is valid");
+    assert!(config.canonical);
+    assert!(!config.header);
+  }
+
+  #[test]
+  fn unknown_options_and_mistyped_flags_are_rejected() {
+    assert!(args_of("colour = true", &["jsonprops", "foo.json"]).is_err());
+    assert!(args_of("config = \"other.toml\"", &["jsonprops", "foo.json"]).is_err());
+    assert!(args_of("debug = \"yes\"", &["jsonprops", "foo.json"]).is_err());
+  }
+}
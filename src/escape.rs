@@ -0,0 +1,185 @@
+//! Escaping of keys and values according to the `.properties` file format, as used by
+//! [Properties](crate::Properties) when writing them.
+
+use crate::app_config::SpaceEscape;
+
+/// Escapes a key, so that it is read back as it is: the separator characters (`' '`, `:` and `=`)
+/// and control characters are escaped, as is a leading `#` or `!` commencing a comment line.
+///
+/// ```
+/// assert_eq!(jsonprops::escape::escape_key("#a b"), r"\#a\ b");
+/// ```
+pub fn escape_key(s: &str) -> String {
+  escape_key_with(s, SpaceEscape::All)
+}
+
+/// Escapes a key like [escape_key], but only the spaces selected by `spaces`. Keys with unescaped
+/// spaces are not read back as they are by `java.util.Properties`.
+///
+/// ```
+/// use jsonprops::SpaceEscape;
+///
+/// assert_eq!(jsonprops::escape::escape_key_with(" a b", SpaceEscape::Leading), r"\ a b");
+/// assert_eq!(jsonprops::escape::escape_key_with(" #a b", SpaceEscape::None), r" \#a b");
+/// ```
+pub fn escape_key_with(s: &str, spaces: SpaceEscape) -> String {
+  escape_key_with_hashes(s, spaces, false)
+}
+
+/// Escapes a key like [escape_key_with], and every `#` in it if `all_hashes` is `true`, for
+/// consumers taking a `#` anywhere in a line for the start of a comment.
+///
+/// ```
+/// use jsonprops::SpaceEscape;
+///
+/// assert_eq!(jsonprops::escape::escape_key_with_hashes("a#b", SpaceEscape::All, false), "a#b");
+/// assert_eq!(jsonprops::escape::escape_key_with_hashes("a#b", SpaceEscape::All, true), r"a\#b");
+/// ```
+pub fn escape_key_with_hashes(s: &str, spaces: SpaceEscape, all_hashes: bool) -> String {
+  // if the string starts with '#' or '!', we need to escape it. If it doesn't there is no need
+  // (only line commencing '#' or '!' would signal a comment line).
+  // There is a possibility the string starts with leading whitespace and the first
+  // non-whitespace character is a '#' or '!' => the escaping loop later accounts for that:
+  // escaping " #foo" as "\ #foo" is sufficient, unless leading spaces are left unescaped.
+  let mut inner = String::with_capacity(s.len() + 1);
+  let mut leading = true;
+  for (i, c) in s.char_indices() {
+    let is_comment_start = i == 0 || (spaces == SpaceEscape::None && leading);
+    let escapes_space = match spaces {
+      SpaceEscape::All => true,
+      SpaceEscape::Leading => leading,
+      SpaceEscape::None => false,
+    };
+    leading &= c == ' ';
+    let is_special = match c {
+      ' ' => escapes_space,
+      ':' | '=' => true,
+      '#' => is_comment_start || all_hashes,
+      '!' => is_comment_start,
+      _ => false,
+    };
+    if is_special {
+      inner.extend(&['\\', c]);
+    } else if !push_control_escape(&mut inner, c) {
+      inner.push(c);
+    }
+  }
+  inner
+}
+
+/// Escapes a value, so that it is read back as it is: backslashes and control characters are
+/// escaped, as is leading whitespace, unless `discard_wsp` is `true`, in which case it is trimmed.
+///
+/// ```
+/// assert_eq!(jsonprops::escape::escape_value("  C:\\dir", false), r"\  C:\\dir");
+/// assert_eq!(jsonprops::escape::escape_value("  C:\\dir", true), r"C:\\dir");
+/// ```
+pub fn escape_value(s: &str, discard_wsp: bool) -> String {
+  // Leading whitespace must be discarded before escaping, otherwise leading line breaks and tabs
+  // would be hidden behind a backslash:
+  let s = if discard_wsp { String::from(s).normalise(true) } else { String::from(s) };
+  s.escape_special().normalise(false)
+}
+
+/// .properties file behaviour
+///
+/// Trailing whitespace is always significant.
+///
+/// A leading whitespace is dropped because the following formats should yield the same result:
+/// ```properties
+/// key=val
+/// key = val
+/// ```
+/// (There are optional whitespaces around the entry separator.)
+/// To preserve the whitespace, it must be escaped with a backslash:
+/// ```properties
+/// key=\     val
+/// ```
+///
+pub(crate) trait WhiteSpaceNormalised {
+  /// Normalises a value to abide by the `.properties` file leading whitespace rules.
+  fn normalise(self, discard_wsp: bool) -> Self;
+}
+
+impl WhiteSpaceNormalised for String {
+  /// Normalises a [String] to abide by the `.properties` file leading whitespace rules.
+  ///
+  /// If the provided argument is `true` the provided [String] is trimmed of leading whitespace if
+  /// necessary.
+  /// - `"foo"` and `"    foo"` will both be rendered as `"foo"`
+  ///
+  /// If the provided argument is `false`, a leading backslash is inserted if necessary to preserve
+  /// the leading whitespace:
+  /// - `"bar"` will be left unchanged
+  /// - `"    bar"` will be rendered as `"\    bar"`
+  fn normalise(self, discard_wsp: bool) -> Self {
+    let starts_with_wsp = match self.chars().next() {
+      Some(c) => c.is_whitespace(),
+      _ => false,
+    };
+
+    if starts_with_wsp && discard_wsp {
+      self.trim_start().into()
+    } else if starts_with_wsp {
+      let mut ret = String::with_capacity(self.len() + 1);
+      ret.push('\\');
+      ret.push_str(&self);
+      ret
+    } else {
+      self
+    }
+  }
+}
+
+/// .properties file behaviour
+///
+/// A value ends at the first line terminator, and a backslash always begins an escape sequence.
+/// Line breaks, tabs and backslashes must therefore be escaped to be read back as they were:
+/// ```properties
+/// key=first line\nsecond line
+/// path=C:\\Program Files
+/// ```
+trait SpecialCharEscaped {
+  /// Escapes the characters which cannot appear verbatim in a `.properties` value.
+  fn escape_special(self) -> Self;
+}
+
+impl SpecialCharEscaped for String {
+  /// Escapes control characters and backslashes in a [String].
+  ///
+  /// - `"foo"` will be left unchanged
+  /// - `"foo<LF>bar"` will be rendered as `"foo\nbar"`
+  /// - `"C:\foo"` will be rendered as `"C:\\foo"`
+  /// - `"foo<BEL>"` will be rendered as `"foo\u0007"`
+  fn escape_special(self) -> Self {
+    if !self.contains(|c: char| c == '\\' || (c.is_ascii_control() && c != '\u{7f}')) {
+      return self;
+    }
+
+    let mut ret = String::with_capacity(self.len() + 1);
+    for c in self.chars() {
+      if c == '\\' {
+        ret.push_str("\\\\");
+      } else if !push_control_escape(&mut ret, c) {
+        ret.push(c);
+      }
+    }
+    ret
+  }
+}
+
+/// Pushes the escape sequence of a C0 control character: line breaks, tabs and form feeds have
+/// their own escapes (`\n`, `\r`, `\t`, `\f`), the rest are escaped as `\uXXXX`.
+///
+/// Returns `false`, pushing nothing, if the character is not a C0 control character.
+pub(crate) fn push_control_escape(s: &mut String, c: char) -> bool {
+  match c {
+    '\n' => s.push_str("\\n"),
+    '\r' => s.push_str("\\r"),
+    '\t' => s.push_str("\\t"),
+    '\u{c}' => s.push_str("\\f"),
+    '\0'..='\u{1f}' => s.push_str(&format!("\\u{:04X}", c as u32)),
+    _ => return false,
+  }
+  true
+}
@@ -0,0 +1,127 @@
+//! Glob patterns matched against flattened (unescaped) property keys.
+
+use std::convert::Infallible;
+use std::str::FromStr;
+
+/// A glob pattern over namespaced keys.
+///
+/// - `*` matches any character sequence within a single namespace segment
+/// - `**` matches any character sequence, including namespace separators
+/// - `?` matches a single character, other than a namespace separator
+///
+/// Every other character matches itself: `db.**` matches `db.url` and `db.pool.size`, while
+/// `*.password` matches `db.password` but not `db.admin.password`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Glob {
+  pattern: String,
+  tokens: Vec<Token>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Token {
+  Char(char),
+  AnyChar,
+  AnyInSegment,
+  Any,
+}
+
+impl Glob {
+  pub(crate) fn new(pattern: &str) -> Self {
+    let mut tokens = Vec::new();
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+      let token = match c {
+        '*' if chars.next_if_eq(&'*').is_some() => Token::Any,
+        '*' => Token::AnyInSegment,
+        '?' => Token::AnyChar,
+        _ => Token::Char(c),
+      };
+      tokens.push(token);
+    }
+    Glob { pattern: String::from(pattern), tokens }
+  }
+
+  /// Returns `true` if the whole key matches the pattern, with `separator` delimiting the
+  /// namespace segments of the key.
+  pub(crate) fn matches(&self, key: &str, separator: &str) -> bool {
+    let key: Vec<char> = key.chars().collect();
+    let separator: Vec<char> = separator.chars().collect();
+    Self::matches_from(&self.tokens, &key, &separator)
+  }
+
+  fn matches_from(tokens: &[Token], key: &[char], separator: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+      return key.is_empty();
+    };
+    match token {
+      Token::Char(c) => key.first() == Some(c) && Self::matches_from(rest, &key[1..], separator),
+      Token::AnyChar => Self::segment_len(key, separator) > 0
+        && Self::matches_from(rest, &key[1..], separator),
+      Token::AnyInSegment => (0..=Self::segment_len(key, separator))
+        .any(|i| Self::matches_from(rest, &key[i..], separator)),
+      Token::Any => (0..=key.len()).any(|i| Self::matches_from(rest, &key[i..], separator)),
+    }
+  }
+
+  /// Returns the number of characters before the first namespace separator in the key.
+  fn segment_len(key: &[char], separator: &[char]) -> usize {
+    if separator.is_empty() {
+      return key.len();
+    }
+    key.windows(separator.len()).position(|w| w == separator).unwrap_or(key.len())
+  }
+}
+
+impl FromStr for Glob {
+  type Err = Infallible;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    Ok(Glob::new(s))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::glob::Glob;
+
+  #[test]
+  fn literal_patterns_match_only_themselves() {
+    let glob = Glob::new("db.url");
+    assert!(glob.matches("db.url", "."));
+    assert!(!glob.matches("db.url2", "."));
+    assert!(!glob.matches("db", "."));
+  }
+
+  #[test]
+  fn single_star_does_not_cross_namespace_separators() {
+    let glob = Glob::new("*.password");
+    assert!(glob.matches("db.password", "."));
+    assert!(glob.matches(".password", "."));
+    assert!(!glob.matches("db.admin.password", "."));
+
+    let glob = Glob::new("db__*");
+    assert!(glob.matches("db__url", "__"));
+    assert!(!glob.matches("db__pool__size", "__"));
+    assert!(glob.matches("db__pool_size", "__"));
+  }
+
+  #[test]
+  fn double_star_crosses_namespace_separators() {
+    let glob = Glob::new("db.**");
+    assert!(glob.matches("db.url", "."));
+    assert!(glob.matches("db.pool.size", "."));
+    assert!(!glob.matches("dba.url", "."));
+
+    let glob = Glob::new("**password*");
+    assert!(glob.matches("db.admin.password", "."));
+    assert!(glob.matches("password_hash", "."));
+  }
+
+  #[test]
+  fn question_mark_matches_a_single_character_within_a_segment() {
+    let glob = Glob::new("item?");
+    assert!(glob.matches("items", "."));
+    assert!(!glob.matches("item", "."));
+    assert!(!glob.matches("item.", "."));
+  }
+}
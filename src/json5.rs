@@ -0,0 +1,386 @@
+//! A JSON5 parser producing the same [Value] tree `serde_json` produces for JSON.
+//!
+//! Comments are discarded while parsing, so they never become entries. `Infinity` and `NaN` have
+//! no JSON counterpart, they are rejected.
+
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use serde_json::{Map, Number, Value};
+
+#[derive(Debug)]
+pub struct Json5Error {
+  line: usize,
+  column: usize,
+  message: String,
+}
+
+impl Json5Error {
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  pub fn column(&self) -> usize {
+    self.column
+  }
+}
+
+impl Display for Json5Error {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} at line {} column {}", self.message, self.line, self.column)
+  }
+}
+
+impl Error for Json5Error {}
+
+/// Parses a JSON5 document into a JSON [Value].
+pub fn from_str(s: &str) -> Result<Value, Json5Error> {
+  let mut parser = Json5Parser { chars: s.chars().collect(), pos: 0 };
+  parser.skip_ws_and_comments()?;
+  let value = parser.parse_value()?;
+  parser.skip_ws_and_comments()?;
+  match parser.peek() {
+    None => Ok(value),
+    Some(_) => Err(parser.error("trailing characters")),
+  }
+}
+
+struct Json5Parser {
+  chars: Vec<char>,
+  pos: usize,
+}
+
+impl Json5Parser {
+  fn parse_value(&mut self) -> Result<Value, Json5Error> {
+    match self.peek() {
+      Some('{') => {
+        self.advance();
+        self.parse_object()
+      }
+      Some('[') => {
+        self.advance();
+        self.parse_array()
+      }
+      Some(quote @ ('"' | '\'')) => {
+        self.advance();
+        self.parse_string(quote).map(Value::String)
+      }
+      Some(c) if c.is_ascii_digit() || matches!(c, '+' | '-' | '.' | 'I' | 'N') => {
+        self.parse_number()
+      }
+      Some(_) => {
+        let word = self.take_while(is_identifier_part);
+        match word.as_str() {
+          "null" => Ok(Value::Null),
+          "true" => Ok(Value::Bool(true)),
+          "false" => Ok(Value::Bool(false)),
+          _ => Err(self.error("expected a value")),
+        }
+      }
+      None => Err(self.error("unexpected end of input")),
+    }
+  }
+
+  fn parse_object(&mut self) -> Result<Value, Json5Error> {
+    let mut members = Map::new();
+    loop {
+      self.skip_ws_and_comments()?;
+      if self.eat('}') {
+        return Ok(Value::Object(members));
+      }
+      let key = self.parse_key()?;
+      self.skip_ws_and_comments()?;
+      self.expect(':')?;
+      self.skip_ws_and_comments()?;
+      let value = self.parse_value()?;
+      members.insert(key, value);
+      self.skip_ws_and_comments()?;
+      if !self.eat(',') {
+        self.skip_ws_and_comments()?;
+        self.expect('}')?;
+        return Ok(Value::Object(members));
+      }
+    }
+  }
+
+  fn parse_array(&mut self) -> Result<Value, Json5Error> {
+    let mut values = Vec::new();
+    loop {
+      self.skip_ws_and_comments()?;
+      if self.eat(']') {
+        return Ok(Value::Array(values));
+      }
+      values.push(self.parse_value()?);
+      self.skip_ws_and_comments()?;
+      if !self.eat(',') {
+        self.skip_ws_and_comments()?;
+        self.expect(']')?;
+        return Ok(Value::Array(values));
+      }
+    }
+  }
+
+  fn parse_key(&mut self) -> Result<String, Json5Error> {
+    match self.peek() {
+      Some(quote @ ('"' | '\'')) => {
+        self.advance();
+        self.parse_string(quote)
+      }
+      Some(c) if is_identifier_start(c) => Ok(self.take_while(is_identifier_part)),
+      _ => Err(self.error("expected a key")),
+    }
+  }
+
+  fn parse_string(&mut self, quote: char) -> Result<String, Json5Error> {
+    let mut s = String::new();
+    loop {
+      match self.advance() {
+        None | Some('\n' | '\r') => return Err(self.error("unterminated string")),
+        Some('\\') => {
+          if let Some(c) = self.parse_escape()? {
+            s.push(c);
+          }
+        }
+        Some(c) if c == quote => return Ok(s),
+        Some(c) => s.push(c),
+      }
+    }
+  }
+
+  /// Parses an escape sequence, returning `None` for a line continuation.
+  fn parse_escape(&mut self) -> Result<Option<char>, Json5Error> {
+    let escaped = match self.advance() {
+      Some('b') => '\u{8}',
+      Some('f') => '\u{c}',
+      Some('n') => '\n',
+      Some('r') => '\r',
+      Some('t') => '\t',
+      Some('v') => '\u{b}',
+      Some('0') if !self.peek().is_some_and(|c| c.is_ascii_digit()) => '\0',
+      Some('x') => self.parse_hex_escape(2)?,
+      Some('u') => return self.parse_unicode_escape().map(Some),
+      Some('\n' | '\u{2028}' | '\u{2029}') => return Ok(None),
+      Some('\r') => {
+        self.eat('\n');
+        return Ok(None);
+      }
+      Some(c) if c.is_ascii_digit() => return Err(self.error("invalid escape sequence")),
+      Some(c) => c,
+      None => return Err(self.error("unterminated string")),
+    };
+    Ok(Some(escaped))
+  }
+
+  fn parse_unicode_escape(&mut self) -> Result<char, Json5Error> {
+    let high = self.parse_hex_code(4)?;
+    if !(0xD800..0xDC00).contains(&high) {
+      return char::from_u32(high).ok_or_else(|| self.error("invalid unicode escape"));
+    }
+
+    // A high surrogate must be followed by an escaped low surrogate:
+    if !(self.eat('\\') && self.eat('u')) {
+      return Err(self.error("unpaired surrogate in unicode escape"));
+    }
+    let low = self.parse_hex_code(4)?;
+    if !(0xDC00..0xE000).contains(&low) {
+      return Err(self.error("unpaired surrogate in unicode escape"));
+    }
+    char::from_u32(0x10000 + ((high - 0xD800) << 10) + (low - 0xDC00))
+      .ok_or_else(|| self.error("invalid unicode escape"))
+  }
+
+  fn parse_hex_escape(&mut self, len: usize) -> Result<char, Json5Error> {
+    let code = self.parse_hex_code(len)?;
+    char::from_u32(code).ok_or_else(|| self.error("invalid hexadecimal escape"))
+  }
+
+  fn parse_hex_code(&mut self, len: usize) -> Result<u32, Json5Error> {
+    let digits: String = (0..len).filter_map(|_| self.advance()).collect();
+    if digits.len() != len || !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+      return Err(self.error("invalid hexadecimal digits in escape"));
+    }
+    u32::from_str_radix(&digits, 16).map_err(|_| self.error("invalid hexadecimal digits in escape"))
+  }
+
+  fn parse_number(&mut self) -> Result<Value, Json5Error> {
+    let start = self.pos;
+    let token = self.take_while(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+    parse_number(&token).ok_or_else(|| {
+      self.pos = start;
+      self.error(format!("invalid number [ {token} ]"))
+    })
+  }
+
+  fn skip_ws_and_comments(&mut self) -> Result<(), Json5Error> {
+    loop {
+      if self.peek().is_some_and(|c| c.is_whitespace() || c == '\u{feff}') {
+        self.advance();
+      } else if self.lookahead("//") {
+        self.take_while(|c| c != '\n');
+      } else if self.lookahead("/*") {
+        self.pos += 2;
+        while !self.lookahead("*/") {
+          if self.advance().is_none() {
+            return Err(self.error("unterminated block comment"));
+          }
+        }
+        self.pos += 2;
+      } else {
+        return Ok(());
+      }
+    }
+  }
+
+  fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+    let mut s = String::new();
+    while let Some(c) = self.peek().filter(|c| predicate(*c)) {
+      s.push(c);
+      self.pos += 1;
+    }
+    s
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn lookahead(&self, s: &str) -> bool {
+    s.chars().enumerate().all(|(i, c)| self.chars.get(self.pos + i) == Some(&c))
+  }
+
+  fn advance(&mut self) -> Option<char> {
+    let c = self.peek();
+    if c.is_some() {
+      self.pos += 1;
+    }
+    c
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let matches = self.peek() == Some(c);
+    if matches {
+      self.pos += 1;
+    }
+    matches
+  }
+
+  fn expect(&mut self, c: char) -> Result<(), Json5Error> {
+    if self.eat(c) {
+      Ok(())
+    } else {
+      Err(self.error(format!("expected {c:?}")))
+    }
+  }
+
+  fn error(&self, message: impl Into<String>) -> Json5Error {
+    let consumed = &self.chars[..self.pos.min(self.chars.len())];
+    let line = consumed.iter().filter(|c| **c == '\n').count() + 1;
+    let column = consumed.iter().rev().take_while(|c| **c != '\n').count() + 1;
+    Json5Error { line, column, message: message.into() }
+  }
+}
+
+fn is_identifier_start(c: char) -> bool {
+  c.is_alphabetic() || c == '$' || c == '_'
+}
+
+fn is_identifier_part(c: char) -> bool {
+  c.is_alphanumeric() || c == '$' || c == '_'
+}
+
+/// Parses a JSON5 number token, keeping its digits as written where JSON allows them.
+fn parse_number(token: &str) -> Option<Value> {
+  let (negative, unsigned) = match token.as_bytes().first() {
+    Some(b'-') => (true, &token[1..]),
+    Some(b'+') => (false, &token[1..]),
+    _ => (false, token),
+  };
+  let sign = if negative { "-" } else { "" };
+
+  if let Some(hex) = unsigned.strip_prefix("0x").or_else(|| unsigned.strip_prefix("0X")) {
+    if hex.is_empty() || !hex.chars().all(|c| c.is_ascii_hexdigit()) {
+      return None;
+    }
+    return i128::from_str_radix(hex, 16).ok()
+      .map(|n| if negative { -n } else { n })
+      .and_then(|n| n.to_string().parse::<Number>().ok())
+      .map(Value::Number);
+  }
+
+  let (mantissa, exponent) = match unsigned.find(['e', 'E']) {
+    Some(i) => unsigned.split_at(i),
+    None => (unsigned, ""),
+  };
+  let (int, frac) = mantissa.split_once('.').map_or((mantissa, None), |(i, f)| (i, Some(f)));
+  let is_digits = |s: &str| s.chars().all(|c| c.is_ascii_digit());
+  let has_digits = !int.is_empty() || frac.is_some_and(|f| !f.is_empty());
+  let has_leading_zero = int.len() > 1 && int.starts_with('0');
+  if !is_digits(int) || !frac.is_none_or(is_digits) || !has_digits || has_leading_zero {
+    return None;
+  }
+
+  // JSON demands digits on both sides of the decimal point:
+  let int = if int.is_empty() { "0" } else { int };
+  let frac = match frac {
+    Some("") => String::from(".0"),
+    Some(f) => format!(".{f}"),
+    None => String::new(),
+  };
+  format!("{sign}{int}{frac}{exponent}").parse::<Number>().ok().map(Value::Number)
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::json5::from_str;
+
+  #[test]
+  fn comments_trailing_commas_and_unquoted_keys_are_accepted() {
+    let doc = r#"
+      // The database
+      {
+        db: {
+          url: 'jdbc:h2:mem', /* inline */
+          'pool-size': 10,
+        },
+        hosts: ["a", "b",],
+      }
+    "#;
+    let expected = json!({
+      "db" : { "url" : "jdbc:h2:mem", "pool-size" : 10 },
+      "hosts" : ["a", "b"]
+    });
+    assert_eq!(from_str(doc).unwrap(), expected);
+  }
+
+  #[test]
+  fn strings_are_unescaped() {
+    let doc = r#"{ a: 'it\'s', b: "tab\tnew\nline", c: "\x41é😀", d: "con\
+tinued" }"#;
+    let expected = json!({
+      "a" : "it's", "b" : "tab\tnew\nline", "c" : "Aé😀", "d" : "continued"
+    });
+    assert_eq!(from_str(doc).unwrap(), expected);
+  }
+
+  #[test]
+  fn numbers_in_every_notation_are_parsed() {
+    let doc = "[ 0x1F, -0xff, +1, .5, 5., 1.5e3, -0 ]";
+    let expected = json!([31, -255, 1, 0.5, 5.0, 1500.0, 0]);
+    let parsed = from_str(doc).unwrap();
+    for (actual, expected) in parsed.as_array().unwrap().iter().zip(expected.as_array().unwrap()) {
+      assert_eq!(actual.as_f64(), expected.as_f64());
+    }
+  }
+
+  #[test]
+  fn invalid_documents_are_rejected_with_their_position() {
+    let err = from_str("{\n  a: 1,\n  b: Infinity\n}").unwrap_err();
+    assert_eq!((err.line(), err.column()), (3, 6));
+    assert!(from_str("{ a: 1 } x").is_err());
+    assert!(from_str("[ 01 ]").is_err());
+    assert!(from_str("{ a: 'open }").is_err());
+    assert!(from_str("/* open").is_err());
+  }
+}
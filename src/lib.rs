@@ -0,0 +1,45 @@
+//! Converts JSON documents into `.properties` files.
+//!
+//! ```
+//! use jsonprops::{Config, ListHandling, Properties};
+//!
+//! let config = Config::builder()
+//!   .list_handling(ListHandling::MultiProp)
+//!   .build()
+//!   .expect("config is valid");
+//! let value = serde_json::json!({ "db" : { "hosts" : ["a", "b"] } });
+//! let rendered = Properties::create(value, &config)
+//!   .expect("JSON is converted")
+//!   .render(&config)
+//!   .expect("properties are rendered");
+//! assert_eq!(rendered, "db.hosts.0=a\ndb.hosts.1=b\n");
+//! ```
+
+pub use crate::app_config::{
+  ArrayMerge, ArrayOverflowHandling, ArrayStyle, BoolFormat, CONFIG_FILE_NAME, ComplexListHandling,
+  Config, ConfigBuilder, ConfigValidationError, DuplicateHandling, EmptyKeyHandling,
+  EmptyObjectHandling, EntryOrder, EntrySeparator, InputFormat, InvalidUtf8Handling, KeyCase,
+  KeyEncoding, LineEnding, ListHandling, MissingEnvHandling, NonFiniteHandling, NullHandling,
+  NumberFormat, OutputFormat, OversizeHandling, QuoteStyle, SpaceEscape,
+};
+pub use crate::props::{ConversionStats, Properties, PropertyConstructionError};
+
+mod app_config;
+mod coerce;
+mod config_file;
+pub mod escape;
+mod glob;
+pub mod json5;
+pub mod merge;
+mod props;
+mod reader;
+mod regex;
+#[cfg(feature = "remote")]
+pub mod remote;
+#[cfg(feature = "serve")]
+pub mod serve;
+mod str_constant;
+pub mod strict;
+pub mod toml;
+mod transform;
+mod value_format;
@@ -1,22 +1,213 @@
-use std::fs;
-use std::time::SystemTime;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, Read};
+use std::iter;
+use std::path::{Path, PathBuf};
+use std::process::ExitCode;
+use std::thread;
+use std::time::{Duration, SystemTime};
 
-use clap::Parser;
-use log::debug;
+use anyhow::Context;
+use log::{debug, error, info};
 use serde_json::Value;
 
-use crate::app_config::Config;
-use crate::props::Properties;
+use jsonprops::json5::Json5Error;
+use jsonprops::toml::TomlError;
+use jsonprops::{
+  merge, Config, ConfigValidationError, InputFormat, Properties, PropertyConstructionError,
+};
 
-mod app_config;
-mod props;
-mod str_constant;
+/// The interval of polling the watched files for changes, which also debounces bursts of writes.
+const WATCH_INTERVAL: Duration = Duration::from_millis(200);
 
-fn main() -> anyhow::Result<()> {
+/// The byte order mark some tools (mostly on Windows) start UTF-8 text with.
+const UTF8_BOM: [u8; 3] = [0xEF, 0xBB, 0xBF];
+
+/// The number of characters shown on either side of the offending column, so that the snippet of
+/// a long (e.g. minified) line stays readable.
+const SNIPPET_CONTEXT: usize = 40;
+
+/// The exit code of I/O errors, and of any other failure not listed below.
+const EXIT_FAILURE: u8 = 1;
+/// The exit code of invalid options, see [ConfigValidationError].
+const EXIT_INVALID_CONFIG: u8 = 2;
+/// The exit code of sources which cannot be parsed.
+const EXIT_INVALID_SOURCE: u8 = 3;
+/// The exit code of sources which cannot be converted, see [PropertyConstructionError].
+const EXIT_CONVERSION_FAILURE: u8 = 4;
+/// The exit code of a destination file differing from what would be written, see `--check`.
+const EXIT_OUT_OF_SYNC: u8 = 5;
+
+fn main() -> ExitCode {
+  match run() {
+    Ok(()) => ExitCode::SUCCESS,
+    Err(e) => {
+      eprintln!("Error: {e:?}");
+      ExitCode::from(exit_status(&e))
+    }
+  }
+}
+
+fn run() -> anyhow::Result<()> {
   let config: Config = init()?;
-  parse_json(&config)
-    .and_then(|json| Properties::create(json, &config))
-    .and_then(|prop| prop.export(&config))
+  #[cfg(feature = "serve")]
+  if let Some(addr) = config.serve() {
+    return jsonprops::serve::serve(addr);
+  }
+  if config.watch {
+    // Like every later conversion, a failing first one does not stop watching the source:
+    if let Err(e) = convert(&config) {
+      error!("Cannot convert {}: {e:#}", config.source().display());
+    }
+    return watch(&config);
+  }
+  convert(&config)
+}
+
+/// Maps an error to the exit code of its category.
+fn exit_status(e: &anyhow::Error) -> u8 {
+  let is_parse_error = e.downcast_ref::<serde_json::Error>().is_some_and(|e| !e.is_io())
+    || e.is::<NdjsonLineError>()
+    || e.is::<Json5Error>()
+    || e.is::<TomlError>();
+  let is_out_of_sync = matches!(
+    e.downcast_ref::<PropertyConstructionError>(),
+    Some(PropertyConstructionError::OutOfSyncError(..)));
+  if e.is::<ConfigValidationError>() {
+    EXIT_INVALID_CONFIG
+  } else if is_out_of_sync {
+    EXIT_OUT_OF_SYNC
+  } else if is_parse_error {
+    EXIT_INVALID_SOURCE
+  } else if e.is::<PropertyConstructionError>() {
+    EXIT_CONVERSION_FAILURE
+  } else {
+    EXIT_FAILURE
+  }
+}
+
+fn convert(config: &Config) -> anyhow::Result<()> {
+  if config.ndjson {
+    return convert_ndjson(config);
+  }
+  parse_json(config).and_then(|json| convert_value(json, config))
+}
+
+fn convert_value(json: Value, config: &Config) -> anyhow::Result<()> {
+  Properties::create(json, config)
+    .inspect(|prop| if config.stats {
+      eprintln!("{}", prop.stats());
+    })
+    .and_then(|prop| {
+      let summary = config.report().map(|report| (report, prop.summary(config)));
+      prop.export(config)?;
+      match summary {
+        Some((report, summary)) => fs::write(report, format!("{summary:#}\n"))
+          .with_context(|| format!("Cannot write the report {}", report.display())),
+        None => Ok(()),
+      }
+    })
+}
+
+/// Converts every object of an NDJSON source into its own destination, stopping at the first
+/// failing line unless `--keep-going` is set.
+fn convert_ndjson(config: &Config) -> anyhow::Result<()> {
+  let reader: Box<dyn BufRead> = if config.reads_stdin() {
+    debug!("Reading NDJSON from standard input...");
+    Box::new(io::stdin().lock())
+  } else {
+    Box::new(BufReader::new(File::open(config.source())?))
+  };
+
+  let (mut converted, mut failed) = (0, 0);
+  for (index, (line_no, line)) in ndjson_lines(reader).enumerate() {
+    let result = line.map_err(anyhow::Error::new)
+      .and_then(|line| parse_ndjson_line(&line, line_no, config))
+      .and_then(|json| merge_overlays(json, config))
+      .and_then(|json| convert_value(json, &config.for_ndjson_object(index)));
+    match result {
+      Ok(()) => converted += 1,
+      Err(e) if config.keep_going => {
+        error!("Cannot convert line {line_no}: {e:#}");
+        failed += 1;
+      }
+      Err(e) => return Err(e),
+    }
+  }
+
+  info!("Converted {converted} object(s) from {}", config.source_name());
+  if failed > 0 {
+    anyhow::bail!("{failed} line(s) of {} cannot be converted", config.source_name());
+  }
+  Ok(())
+}
+
+/// Returns the non-blank lines of `reader`, with their (1-based) line numbers.
+fn ndjson_lines(reader: impl BufRead) -> impl Iterator<Item=(usize, io::Result<String>)> {
+  reader.lines()
+    .enumerate()
+    .map(|(i, line)| {
+      // Only the first line can start with a byte order mark:
+      let line = line.map(|l| l.strip_prefix('\u{feff}').map(String::from).unwrap_or(l));
+      (i + 1, line)
+    })
+    .filter(|(_, line)| line.as_ref().map_or(true, |l| !l.trim().is_empty()))
+}
+
+fn parse_ndjson_line(line: &str, line_no: usize, config: &Config) -> anyhow::Result<Value> {
+  parse_json_text(line, config.strict).map_err(|e| {
+    let context = format!(
+      "Cannot parse {} at line {line_no}, column {}:\n{}",
+      config.source_name(), e.column(), snippet(line, line_no, e.column()));
+    anyhow::Error::new(NdjsonLineError(e)).context(context)
+  })
+}
+
+/// A parse error of an NDJSON line, described without the position `serde_json` reports, as that
+/// is relative to the line, not to the source.
+#[derive(Debug)]
+struct NdjsonLineError(serde_json::Error);
+
+impl Display for NdjsonLineError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    let message = self.0.to_string();
+    let position = format!(" at line {} column {}", self.0.line(), self.0.column());
+    f.write_str(message.strip_suffix(&position).unwrap_or(&message))
+  }
+}
+
+impl Error for NdjsonLineError {}
+
+/// Regenerates the destination whenever the modification time of the source or of a merged file
+/// changes, once it has not changed for a whole polling interval.
+fn watch(config: &Config) -> anyhow::Result<()> {
+  let paths: Vec<&Path> = iter::once(config.source())
+    .chain(config.merge().iter().map(PathBuf::as_path))
+    .chain(config.patch().iter().map(PathBuf::as_path))
+    .collect();
+  info!("Watching {} for changes...", config.source().display());
+
+  let mut seen = modification_times(&paths);
+  let mut pending = false;
+  loop {
+    thread::sleep(WATCH_INTERVAL);
+    let current = modification_times(&paths);
+    if current != seen {
+      seen = current;
+      pending = true;
+    } else if pending {
+      pending = false;
+      match convert(config) {
+        Ok(()) => info!("Regenerated from {}", config.source().display()),
+        Err(e) => error!("Cannot regenerate from {}: {e:#}", config.source().display()),
+      }
+    }
+  }
+}
+
+fn modification_times(paths: &[&Path]) -> Vec<Option<SystemTime>> {
+  paths.iter().map(|p| fs::metadata(p).and_then(|m| m.modified()).ok()).collect()
 }
 
 fn init() -> anyhow::Result<Config> {
@@ -31,7 +222,9 @@ fn init() -> anyhow::Result<Config> {
 }
 
 fn parse_config() -> anyhow::Result<Config> {
-  Config::parse().validate().map_err(anyhow::Error::new)
+  Config::parse_with_config_file()
+    .and_then(Config::validate)
+    .map_err(anyhow::Error::new)
 }
 
 fn setup_logger(config: &Config) -> Result<(), fern::InitError> {
@@ -47,14 +240,263 @@ fn setup_logger(config: &Config) -> Result<(), fern::InitError> {
       ))
     })
     .level(level_filter);
-  if config.debug || config.dest().is_some() {
-    logger = logger.chain(std::io::stdout());
+  if config.debug || config.dry_run || config.dest().is_some() || config.serve().is_some() {
+    let stdout_level = if config.quiet { log::LevelFilter::Warn } else { level_filter };
+    logger = logger.chain(fern::Dispatch::new().level(stdout_level).chain(io::stdout()));
   }
-  logger.chain(fern::log_file("output.log")?).apply()?;
+  if let Some(log_file) = config.log_file() {
+    logger = logger.chain(fern::log_file(log_file)?);
+  }
+  logger.apply()?;
   Ok(())
 }
 
 fn parse_json(config: &Config) -> anyhow::Result<Value> {
-  let s = fs::read_to_string(config.source())?;
-  serde_json::from_str(&s).map_err(anyhow::Error::new)
+  let value = if config.stream {
+    parse_stream(config)?
+  } else {
+    let s = if config.reads_stdin() {
+      debug!("Reading JSON from standard input...");
+      let mut bytes = Vec::new();
+      io::stdin().read_to_end(&mut bytes)?;
+      decode_text(bytes)?
+    } else if config.is_remote() {
+      fetch_text(config)?
+    } else {
+      read_text(config.source())?
+    };
+    parse_text(&s, config.input_format(), &config.source_name(), config.strict)?
+  };
+  merge_overlays(value, config)
+}
+
+fn merge_overlays(mut value: Value, config: &Config) -> anyhow::Result<Value> {
+  for overlay in config.merge() {
+    debug!("Merging {} into the source...", overlay.display());
+    let s = read_text(overlay)?;
+    let format = config.input_format_of(overlay);
+    let overlay_value = parse_text(&s, format, &overlay.display(), config.strict)?;
+    merge::deep_merge(&mut value, overlay_value, config.array_merge());
+  }
+  for patch in config.patch() {
+    debug!("Applying {} to the source as a merge patch...", patch.display());
+    let s = read_text(patch)?;
+    let format = config.input_format_of(patch);
+    let patch_value = parse_text(&s, format, &patch.display(), config.strict)?;
+    merge::merge_patch(&mut value, patch_value);
+  }
+  Ok(value)
+}
+
+#[cfg(feature = "remote")]
+fn fetch_text(config: &Config) -> anyhow::Result<String> {
+  debug!("Fetching JSON from {}...", config.source_name());
+  let bytes =
+    jsonprops::remote::fetch(&config.source_name(), config.timeout(), config.http_headers())?;
+  Ok(decode_text(bytes)?)
+}
+
+#[cfg(not(feature = "remote"))]
+fn fetch_text(_: &Config) -> anyhow::Result<String> {
+  unreachable!("URL sources are rejected by the validation without the `remote` feature")
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+  fs::read(path).and_then(decode_text)
+}
+
+/// Decodes text as UTF-8, unless it starts with a UTF-16 byte order mark. A leading byte order
+/// mark is dropped.
+fn decode_text(bytes: Vec<u8>) -> io::Result<String> {
+  let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message);
+  let utf16_unit: Option<fn([u8; 2]) -> u16> = match bytes.get(..2) {
+    Some([0xFF, 0xFE]) => Some(u16::from_le_bytes),
+    Some([0xFE, 0xFF]) => Some(u16::from_be_bytes),
+    _ => None,
+  };
+  let Some(utf16_unit) = utf16_unit else {
+    let bytes = bytes.strip_prefix(UTF8_BOM.as_slice()).map(<[u8]>::to_vec).unwrap_or(bytes);
+    return String::from_utf8(bytes).map_err(|_| invalid("stream did not contain valid UTF-8"));
+  };
+
+  if !bytes.len().is_multiple_of(2) {
+    return Err(invalid("stream did not contain valid UTF-16: odd number of bytes"));
+  }
+  let units: Vec<u16> = bytes[2..].chunks_exact(2).map(|c| utf16_unit([c[0], c[1]])).collect();
+  String::from_utf16(&units).map_err(|_| invalid("stream did not contain valid UTF-16"))
+}
+
+/// Parses the JSON source directly from a buffered reader, so the raw text is never held in
+/// memory alongside the parsed tree.
+fn parse_stream(config: &Config) -> anyhow::Result<Value> {
+  let result = if config.reads_stdin() {
+    debug!("Streaming JSON from standard input...");
+    serde_json::from_reader(skip_utf8_bom(io::stdin().lock())?)
+  } else {
+    debug!("Streaming JSON from {}...", config.source().display());
+    serde_json::from_reader(skip_utf8_bom(BufReader::new(File::open(config.source())?))?)
+  };
+  result.map_err(|e| {
+    let context = format!(
+      "Cannot parse {} at line {}, column {}",
+      config.source_name(), e.line(), e.column());
+    anyhow::Error::new(e).context(context)
+  })
+}
+
+/// Consumes a leading UTF-8 byte order mark of a reader. Streamed sources are not transcoded, so
+/// UTF-16 sources must be read without `--stream`.
+fn skip_utf8_bom<R: BufRead>(mut reader: R) -> io::Result<R> {
+  if reader.fill_buf()?.starts_with(&UTF8_BOM) {
+    reader.consume(UTF8_BOM.len());
+  }
+  Ok(reader)
+}
+
+fn parse_text(
+  s: &str,
+  format: InputFormat,
+  name: &dyn Display,
+  strict: bool,
+) -> anyhow::Result<Value> {
+  match format {
+    InputFormat::Json => parse_json_text(s, strict).map_err(|e| {
+      let context = parse_error_context(name, s, e.line(), e.column());
+      anyhow::Error::new(e).context(context)
+    }),
+    InputFormat::Json5 => jsonprops::json5::from_str(s).map_err(|e| {
+      let context = parse_error_context(name, s, e.line(), e.column());
+      anyhow::Error::new(e).context(context)
+    }),
+    InputFormat::Toml => jsonprops::toml::from_str(s).map_err(|e| {
+      let context = parse_error_context(name, s, e.line(), e.column());
+      anyhow::Error::new(e).context(context)
+    }),
+  }
+}
+
+/// Parses JSON, rejecting duplicate keys if `strict`.
+fn parse_json_text(s: &str, strict: bool) -> serde_json::Result<Value> {
+  if strict {
+    jsonprops::strict::from_str(s)
+  } else {
+    serde_json::from_str(s)
+  }
+}
+
+/// Describes the location of a parse error, quoting the offending line with a caret under the
+/// (1-based) column.
+fn parse_error_context(name: &dyn Display, text: &str, line: usize, column: usize) -> String {
+  let offending_line = text.lines().nth(line.saturating_sub(1)).unwrap_or_default();
+  format!(
+    "Cannot parse {name} at line {line}, column {column}:\n{}",
+    snippet(offending_line, line, column))
+}
+
+fn snippet(offending_line: &str, line: usize, column: usize) -> String {
+  let gutter = line.to_string();
+  let chars: Vec<char> = offending_line.chars().collect();
+  let caret = column.saturating_sub(1).min(chars.len());
+  let start = caret.saturating_sub(SNIPPET_CONTEXT);
+  let end = (caret + SNIPPET_CONTEXT + 1).min(chars.len());
+  let head = if start > 0 { "…" } else { "" };
+  let tail = if end < chars.len() { "…" } else { "" };
+  let shown: String = chars[start..end].iter().collect();
+  // Tabs are kept, so the caret lines up regardless of the tab width:
+  let indent: String = chars[start..caret].iter()
+    .map(|c| if *c == '\t' { '\t' } else { ' ' })
+    .collect();
+  format!(
+    "{gutter} | {head}{shown}{tail}\n{} | {}{indent}^",
+    " ".repeat(gutter.len()),
+    " ".repeat(head.chars().count()))
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+  use jsonprops::{Config, ConfigValidationError, InputFormat, PropertyConstructionError};
+
+  use crate::{
+    decode_text, exit_status, ndjson_lines, parse_ndjson_line, parse_text, skip_utf8_bom, snippet,
+  };
+
+  #[test]
+  fn snippet_points_at_the_offending_column() {
+    assert_eq!(snippet(r#"  "a" : 1 2"#, 12, 11), "12 |   \"a\" : 1 2\n   |           ^");
+    assert_eq!(snippet("\t\"a\" 1", 1, 6), "1 | \t\"a\" 1\n  | \t    ^");
+  }
+
+  #[test]
+  fn snippet_of_a_long_line_is_clipped_around_the_offending_column() {
+    let line = format!("{}x{}", "a".repeat(250), "b".repeat(250));
+    let expected = format!(
+      "1 | …{}x{}…\n  | {}^", "a".repeat(40), "b".repeat(40), " ".repeat(41));
+    assert_eq!(snippet(&line, 1, 251), expected);
+    let expected = format!("1 | x{}…\n  | ^", "b".repeat(40));
+    assert_eq!(snippet(&line[250..], 1, 1), expected);
+  }
+
+  #[test]
+  fn ndjson_parse_errors_only_report_the_line_of_the_source() {
+    let config = Config::parse_from(["jsonprops", "foo.ndjson", "--ndjson"]);
+    let err = parse_ndjson_line("{1}", 4, &config).unwrap_err();
+    assert_eq!(
+      format!("{err:#}"),
+      "Cannot parse foo.ndjson at line 4, column 2:\n4 | {1}\n  |  ^: key must be a string");
+    assert_eq!(exit_status(&err), 3);
+  }
+
+  #[test]
+  fn errors_are_mapped_to_the_exit_codes_of_their_category() {
+    let config_error = ConfigValidationError::InvalidArrayBaseError(2);
+    assert_eq!(exit_status(&anyhow::Error::new(config_error)), 2);
+
+    let parse_error = parse_text("{ 1", InputFormat::Json, &"foo.json", false).unwrap_err();
+    assert_eq!(exit_status(&parse_error), 3);
+    let parse_error = parse_text("{ \"a\" : 1, \"a\" : 2 }", InputFormat::Json, &"foo.json", true)
+      .unwrap_err();
+    assert_eq!(exit_status(&parse_error), 3);
+    let parse_error = parse_text("a = ", InputFormat::Toml, &"foo.toml", false).unwrap_err();
+    assert_eq!(exit_status(&parse_error), 3);
+
+    let conversion_error = PropertyConstructionError::MissingPointerError(String::from("/a"));
+    assert_eq!(exit_status(&anyhow::Error::new(conversion_error)), 4);
+    let out_of_sync = PropertyConstructionError::OutOfSyncError(String::from("a"), vec![]);
+    assert_eq!(exit_status(&anyhow::Error::new(out_of_sync)), 5);
+
+    let io_error = std::io::Error::from(std::io::ErrorKind::NotFound);
+    assert_eq!(exit_status(&anyhow::Error::new(io_error)), 1);
+  }
+
+  #[test]
+  fn ndjson_lines_skip_blank_lines_and_keep_their_numbers() {
+    let text = "{\"a\":1}\n\n  \n{\"b\":2}\n";
+    let lines: Vec<(usize, String)> = ndjson_lines(text.as_bytes())
+      .map(|(i, line)| (i, line.unwrap()))
+      .collect();
+    assert_eq!(lines, [(1, String::from("{\"a\":1}")), (4, String::from("{\"b\":2}"))]);
+  }
+
+  #[test]
+  fn byte_order_marks_are_dropped_and_utf16_is_transcoded() {
+    let json = "{ \"é\" : 1 }";
+    let utf8_bom = [&[0xEF, 0xBB, 0xBF], json.as_bytes()].concat();
+    assert_eq!(decode_text(utf8_bom.clone()).unwrap(), json);
+    assert_eq!(decode_text(json.as_bytes().to_vec()).unwrap(), json);
+
+    let utf16_le: Vec<u8> = [0xFF, 0xFE].into_iter()
+      .chain(json.encode_utf16().flat_map(u16::to_le_bytes))
+      .collect();
+    assert_eq!(decode_text(utf16_le).unwrap(), json);
+    let utf16_be: Vec<u8> = [0xFE, 0xFF].into_iter()
+      .chain(json.encode_utf16().flat_map(u16::to_be_bytes))
+      .collect();
+    assert_eq!(decode_text(utf16_be).unwrap(), json);
+    assert!(decode_text(vec![0xFF, 0xFE, 0x7B]).is_err());
+
+    let value: serde_json::Value = serde_json::from_reader(skip_utf8_bom(&utf8_bom[..]).unwrap())
+      .expect("JSON is valid");
+    assert_eq!(value, serde_json::json!({ "é" : 1 }));
+  }
 }
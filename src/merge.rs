@@ -0,0 +1,110 @@
+//! Combining multiple JSON documents into one before conversion.
+
+use serde_json::Value;
+
+use crate::app_config::ArrayMerge;
+
+/// Deep-merges `overlay` into `base`.
+///
+/// Objects are merged recursively, with the members of `overlay` taking precedence. Every other
+/// value of `overlay` replaces the corresponding value of `base`, except lists, which are
+/// concatenated if `arrays` is [ArrayMerge::Concat].
+pub fn deep_merge(base: &mut Value, overlay: Value, arrays: ArrayMerge) {
+  match (base, overlay) {
+    (Value::Object(base_map), Value::Object(overlay_map)) => {
+      for (k, v) in overlay_map {
+        match base_map.get_mut(&k) {
+          Some(base_value) => deep_merge(base_value, v, arrays),
+          None => {
+            base_map.insert(k, v);
+          }
+        }
+      }
+    }
+    (Value::Array(base_values), Value::Array(overlay_values)) if arrays == ArrayMerge::Concat => {
+      base_values.extend(overlay_values);
+    }
+    (base, overlay) => *base = overlay,
+  }
+}
+
+/// Applies `patch` to `target` as a JSON Merge Patch (RFC 7386).
+///
+/// If `patch` is an object, its members are applied to the members of `target` recursively, with a
+/// `null` member removing the member of `target`. Every other value of `patch` replaces `target`.
+pub fn merge_patch(target: &mut Value, patch: Value) {
+  let Value::Object(patch_map) = patch else {
+    *target = patch;
+    return;
+  };
+  if !target.is_object() {
+    *target = Value::Object(serde_json::Map::new());
+  }
+  let Value::Object(target_map) = target else {
+    unreachable!("the target has just been replaced with an object")
+  };
+
+  for (k, v) in patch_map {
+    if v.is_null() {
+      target_map.remove(&k);
+    } else {
+      merge_patch(target_map.entry(k).or_insert(Value::Null), v);
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::app_config::ArrayMerge;
+  use crate::merge::{deep_merge, merge_patch};
+
+  #[test]
+  fn objects_are_merged_recursively_and_other_values_are_replaced() {
+    let mut base = json!({
+      "db" : { "url" : "localhost", "pool" : { "size" : 10, "timeout" : 30 } },
+      "hosts" : ["a", "b"],
+      "name" : "base"
+    });
+    let overlay = json!({
+      "db" : { "pool" : { "size" : 20 }, "user" : "admin" },
+      "hosts" : ["c"],
+      "name" : { "first" : "overlay" }
+    });
+    deep_merge(&mut base, overlay, ArrayMerge::Replace);
+    assert_eq!(base, json!({
+      "db" : { "url" : "localhost", "pool" : { "size" : 20, "timeout" : 30 }, "user" : "admin" },
+      "hosts" : ["c"],
+      "name" : { "first" : "overlay" }
+    }));
+  }
+
+  #[test]
+  fn lists_are_concatenated_with_concat_array_merge() {
+    let mut base = json!({ "hosts" : ["a", "b"], "ports" : [1] });
+    deep_merge(&mut base, json!({ "hosts" : ["c"], "ports" : 2 }), ArrayMerge::Concat);
+    assert_eq!(base, json!({ "hosts" : ["a", "b", "c"], "ports" : 2 }));
+  }
+
+  #[test]
+  fn merge_patch_removes_null_members_and_replaces_everything_else() {
+    let mut target = json!({
+      "db" : { "url" : "localhost", "password" : "secret" },
+      "hosts" : ["a", "b"],
+      "name" : "base"
+    });
+    let patch = json!({
+      "db" : { "password" : null, "pool" : { "size" : 10, "timeout" : null } },
+      "hosts" : ["c"],
+      "name" : { "first" : "patched" },
+      "missing" : null
+    });
+    merge_patch(&mut target, patch);
+    assert_eq!(target, json!({
+      "db" : { "url" : "localhost", "pool" : { "size" : 10 } },
+      "hosts" : ["c"],
+      "name" : { "first" : "patched" }
+    }));
+  }
+}
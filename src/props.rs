@@ -1,26 +1,138 @@
-use std::collections::BTreeMap;
+use std::cell::RefCell;
+use std::cmp::{Ordering, Reverse};
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::error::Error;
 use std::fmt::{Debug, Display, Formatter};
-use std::fs::File;
-use std::io::{BufWriter, Write};
+use std::ffi::OsString;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
-use log::debug;
-use serde_json::Value;
+use anyhow::Context;
+use log::{debug, info, warn};
+use serde_json::{Number, Value};
 
-use crate::app_config::{Config, ListHandling};
+use crate::app_config::{
+  ArrayOverflowHandling, ArrayStyle, ComplexListHandling, Config, DuplicateHandling,
+  EmptyKeyHandling, EmptyObjectHandling, EntryOrder, InvalidUtf8Handling, KeyEncoding,
+  ListHandling, NonFiniteHandling, NullHandling, OutputFormat, OversizeHandling,
+};
 use crate::props::prop_key::PropKey;
 use crate::props::prop_val::PropVal;
-use crate::props::PropertyConstructionError::{TopLevelArrayError, TopLevelPrimitiveError};
-use crate::str_constant;
+use crate::props::progress::Progress;
+use crate::props::PropertyConstructionError::{
+  ComplexListError, DuplicateKeyError, EmptyKeyError, EmptyPropertiesError, FormatError,
+  InvalidBase64Error, InvalidFileNameError, InvalidKeyError, InvalidUtf8Error, MissingEnvVarError,
+  MissingPointerError, NonFiniteNumberError, OutOfSyncError, RoundTripError, TopLevelArrayError,
+  TopLevelPrimitiveError, TooManyElementsError, TransformError, ValueTooLongError,
+};
+use crate::{coerce, escape, reader, str_constant, transform};
+
+/// The last segment of the key of the property holding the length of a list.
+const LIST_COUNT_SEGMENT: &str = "count";
+/// The last segment of the key of the property marking an empty object.
+const EMPTY_OBJECT_SEGMENT: &str = "empty";
+/// The last segment of the key of the property marking a list truncated to its first members.
+const TRUNCATED_LIST_SEGMENT: &str = "truncated";
+/// The name of the file of the entries which are not under a top-level object, see
+/// `--split-top-level`.
+const SPLIT_ROOT_NAME: &str = "root";
 
 pub struct Properties {
   props: BTreeMap<PropKey, PropVal>,
+  /// The keys of `props` in the order they were first encountered in the JSON.
+  insertion_order: Vec<PropKey>,
+  /// The `(namespace, comment)` pairs of the objects holding a comment field, outer objects first.
+  /// A comment is written above the first entry in the namespace.
+  comments: Vec<(String, String)>,
+  /// The namespaces of the top-level members of the JSON, kept only to split by top-level key.
+  top_level: Vec<String>,
+  stats: ConversionStats,
+}
+
+/// A summary of the conversion producing the [Properties].
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConversionStats {
+  properties: usize,
+  max_depth: usize,
+  expanded_lists: usize,
+  dropped_lists: usize,
+  dropped_list_keys: Vec<String>,
+  duplicate_keys: Vec<String>,
+  coerced_values: Vec<String>,
+}
+
+impl ConversionStats {
+  /// Returns the number of properties produced.
+  pub fn properties(&self) -> usize {
+    self.properties
+  }
+
+  /// Returns the deepest nesting level a value was encountered at, top-level members being at 1.
+  pub fn max_depth(&self) -> usize {
+    self.max_depth
+  }
+
+  /// Returns the number of lists expanded into a property per member.
+  pub fn expanded_lists(&self) -> usize {
+    self.expanded_lists
+  }
+
+  /// Returns the number of lists of non-primitives omitted.
+  pub fn dropped_lists(&self) -> usize {
+    self.dropped_lists
+  }
+
+  /// Returns the keys of the lists of non-primitives omitted.
+  pub fn dropped_list_keys(&self) -> &[String] {
+    &self.dropped_list_keys
+  }
+
+  /// Returns the keys which multiple JSON values were flattened into, of which only one is kept.
+  pub fn duplicate_keys(&self) -> &[String] {
+    &self.duplicate_keys
+  }
+
+  /// Returns the JSON Pointers of the strings coerced to other types before the conversion.
+  pub fn coerced_values(&self) -> &[String] {
+    &self.coerced_values
+  }
+}
+
+impl Display for ConversionStats {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "Properties:     {}", self.properties)?;
+    writeln!(f, "Maximum depth:  {}", self.max_depth)?;
+    writeln!(f, "Expanded lists: {}", self.expanded_lists)?;
+    write!(f, "Dropped lists:  {}", self.dropped_lists)
+  }
 }
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum PropertyConstructionError {
   TopLevelPrimitiveError(Value),
   TopLevelArrayError(Value),
+  ComplexListError(String, Vec<Value>),
+  MissingPointerError(String),
+  DuplicateKeyError(String),
+  EmptyPropertiesError(String),
+  MissingEnvVarError(String, String),
+  ValueTooLongError(String, usize),
+  RoundTripError(String),
+  OutOfSyncError(String, Vec<String>),
+  InvalidBase64Error(String),
+  InvalidUtf8Error(String),
+  TransformError(String, String),
+  NonFiniteNumberError(String, String),
+  InvalidFileNameError(String),
+  EmptyKeyError(String),
+  TooManyElementsError(String, usize),
+  FormatError(String, String),
+  InvalidKeyError(Vec<(String, String)>),
 }
 
 impl Display for PropertyConstructionError {
@@ -29,9 +141,68 @@ impl Display for PropertyConstructionError {
       TopLevelPrimitiveError(v) => write!(
         f, "JSON value is a primitive, which cannot be formatted as properties: {}",
         v),
-      TopLevelArrayError(_) => write!(
-        f, "JSON value is an array, which cannot be formatted as properties.\n\
-        Break up the JSON into individual objects and convert them separately!"),
+      TopLevelArrayError(v) => write!(
+        f, "JSON value is an array (of {} elements), which cannot be formatted as properties.\n\
+        Break up the JSON into individual objects and convert them separately, or use \
+        [ --allow-top-array ] with [ --list-handling multi-prop ] or a [ --prefix ]!",
+        v.as_array().map_or(0, Vec::len)),
+      ComplexListError(k, values) => write!(
+        f, "{k} denotes a list, and its members are not exclusively primitives, which cannot be \
+        formatted as a single property: {}\n\
+        Use [ --on-complex-list ] to omit or expand such lists instead!",
+        Value::Array(values.clone())),
+      MissingPointerError(p) => write!(f, "JSON Pointer [ {p} ] does not point to any value"),
+      DuplicateKeyError(k) => write!(
+        f, "Multiple JSON values are flattened into the same key: {k}\n\
+        Use [ --on-duplicate ] to keep the first or the last one instead!"),
+      EmptyPropertiesError(source) => write!(f, "No properties are produced from {source}"),
+      MissingEnvVarError(name, k) => write!(
+        f, "Environment variable [ {name} ] referenced by {k} is not defined\n\
+        Use [ --on-missing-env ] to keep the placeholder or to leave it empty instead!"),
+      TooManyElementsError(k, len) => write!(
+        f, "{k} denotes a list of {len} members, exceeding the maximum number of array elements\n\
+        Use [ --on-array-overflow ] to truncate such lists instead!"),
+      ValueTooLongError(k, len) => write!(
+        f, "The value of {k} is {len} bytes long, exceeding the maximum value length\n\
+        Use [ --on-oversize ] to truncate or keep such values instead!"),
+      RoundTripError(k) => write!(
+        f, "The written properties do not read back as converted, starting at key {k}"),
+      InvalidBase64Error(k) => write!(f, "The value of {k} is not valid base64"),
+      NonFiniteNumberError(k, n) => write!(
+        f, "The value of {k} is not a finite number: {n}\n\
+        Use [ --on-nonfinite ] to skip it or write a placeholder instead!"),
+      TransformError(k, reason) => write!(
+        f, "The value of {k} cannot be transformed by the transform command: {reason}"),
+      FormatError(k, reason) => write!(
+        f, "The value of {k} cannot be formatted: {reason}\n\
+        Fix the value, or the pattern of [ --format-path ] matching it!"),
+      EmptyKeyError(namespace) if namespace.is_empty() => write!(
+        f, "A member of the root object has an empty key\n\
+        Use [ --on-empty-key ] to skip it or replace it with a placeholder instead!"),
+      EmptyKeyError(namespace) => write!(
+        f, "A member of {namespace} has an empty key\n\
+        Use [ --on-empty-key ] to skip it or replace it with a placeholder instead!"),
+      InvalidKeyError(keys) => write!(
+        f, "Keys do not match the key pattern (key <- JSON Pointer of its value):\n{}\n\
+        Rename them with [ --key-replace ], or exclude them instead!",
+        keys.iter()
+          .map(|(k, pointer)| format!("  {k} <- {pointer}"))
+          .collect::<Vec<_>>()
+          .join("\n")),
+      InvalidFileNameError(k) => write!(
+        f, "Top-level key [ {k} ] cannot name a file of its own\n\
+        Rename or exclude it, or run without [ --split-top-level ]!"),
+      InvalidUtf8Error(k) => write!(
+        f, "The base64 decoded value of {k} is not valid UTF-8\n\
+        Use [ --on-invalid-utf8 ] to write such values as hexadecimal instead!"),
+      OutOfSyncError(dest, diff) if diff.is_empty() => write!(
+        f, "{dest} is out of sync with the source: its entries are the same, but formatted \
+        differently\n\
+        Run without [ --check ] to regenerate it!"),
+      OutOfSyncError(dest, diff) => write!(
+        f, "{dest} is out of sync with the source (+ added, - removed, ~ changed):\n{}\n\
+        Run without [ --check ] to regenerate it!",
+        diff.join("\n")),
     }
   }
 }
@@ -40,303 +211,3357 @@ impl Error for PropertyConstructionError {}
 
 impl Properties {
   pub fn create(value: Value, config: &Config) -> anyhow::Result<Self> {
-    PropertiesBuilder(config).build(value).map_err(anyhow::Error::new)
+    let (value, coerced_values) = Self::prepare(value, config)?;
+    let mut props = PropertiesBuilder::new(config).build(value)?;
+    props.stats.coerced_values = coerced_values;
+    if config.fail_on_empty && props.props.is_empty() {
+      return Err(anyhow::Error::new(EmptyPropertiesError(config.source_name())));
+    }
+    Ok(props)
+  }
+
+  /// Flattens the JSON value like [Properties::create], but hands every entry to `f` as its
+  /// (unescaped) key and value instead of collecting them, one top-level member at a time.
+  ///
+  /// Only the entries of a single top-level member are held at once, so `--order` and
+  /// `--on-duplicate` do not apply: the entries are handed over as they are flattened, and
+  /// duplicate keys across top-level members are handed over as many times as they occur.
+  ///
+  /// ```
+  /// use jsonprops::{Config, Properties};
+  ///
+  /// let config = Config::builder().build().expect("config is valid");
+  /// let value = serde_json::json!({ "a" : "x", "b" : { "c" : 1 } });
+  /// let mut entries = Vec::new();
+  /// Properties::for_each_entry(value, &config, |k, v| entries.push(format!("{k}={v}")))
+  ///   .expect("JSON is converted");
+  /// assert_eq!(entries, ["a=x", "b.c=1"]);
+  /// ```
+  pub fn for_each_entry<F: FnMut(&str, &str)>(
+    value: Value,
+    config: &Config,
+    mut f: F,
+  ) -> anyhow::Result<()> {
+    let (value, _) = Self::prepare(value, config)?;
+    let builder = PropertiesBuilder::new(config);
+    let mut count = 0;
+    for (namespace, value) in builder.members(value)? {
+      let entries = builder.parse_value(&namespace, value, 1)?;
+      for (k, v) in builder.finish_entries(entries)? {
+        f(k.raw(), v.raw());
+        count += 1;
+      }
+    }
+    if config.fail_on_empty && count == 0 {
+      return Err(anyhow::Error::new(EmptyPropertiesError(config.source_name())));
+    }
+    Ok(())
+  }
+
+  /// Coerces the values of the source as configured, returning the value to be converted and the
+  /// JSON Pointers of the coerced values.
+  fn prepare(mut value: Value, config: &Config) -> anyhow::Result<(Value, Vec<String>)> {
+    let schema: Option<Value> = match config.schema() {
+      Some(path) => {
+        let schema = fs::read_to_string(path)?;
+        let context = || format!("Cannot parse the schema {}", path.display());
+        Some(serde_json::from_str(&schema).with_context(context)?)
+      }
+      None => None,
+    };
+    let coerced_values = if schema.is_some() || config.coerce_numeric_strings {
+      coerce::coerce(&mut value, schema.as_ref(), config.coerce_numeric_strings)
+    } else {
+      Vec::new()
+    };
+
+    let value = match config.pointer() {
+      Some(pointer) => Self::select(value, pointer)?,
+      None => value,
+    };
+    Ok((value, coerced_values))
+  }
+
+  /// Selects the value at the JSON Pointer to be converted instead of the whole document.
+  fn select(mut value: Value, pointer: &str) -> Result<Value, PropertyConstructionError> {
+    value.pointer_mut(pointer)
+      .map(Value::take)
+      .ok_or_else(|| MissingPointerError(String::from(pointer)))
   }
 
   fn empty() -> Self {
     Properties {
-      props: BTreeMap::new()
+      props: BTreeMap::new(),
+      insertion_order: Vec::new(),
+      comments: Vec::new(),
+      top_level: Vec::new(),
+      stats: ConversionStats::default(),
     }
   }
 
-  pub fn export(self, config: &Config) -> anyhow::Result<()> {
-    let out = match config.dest() {
-      None => Box::new(std::io::stdout()) as Box<dyn Write>,
-      Some(p) => Box::new(File::create(p)?) as Box<dyn Write>,
-    };
-    let mut w = BufWriter::new(out);
+  fn from_entries(
+    entries: Vec<(PropKey, PropVal)>,
+    on_duplicate: &DuplicateHandling,
+  ) -> Result<Self, PropertyConstructionError> {
+    let mut props = Self::empty();
+    for (k, v) in entries {
+      match props.props.entry(k) {
+        Entry::Vacant(entry) => {
+          props.insertion_order.push(entry.key().clone());
+          entry.insert(v);
+        }
+        Entry::Occupied(mut entry) => match on_duplicate {
+          DuplicateHandling::KeepLast => {
+            props.stats.duplicate_keys.push(String::from(entry.key().raw()));
+            entry.insert(v);
+          }
+          DuplicateHandling::KeepFirst => {
+            props.stats.duplicate_keys.push(String::from(entry.key().raw()));
+          }
+          DuplicateHandling::Error => {
+            return Err(DuplicateKeyError(String::from(entry.key().raw())));
+          }
+        },
+      }
+    }
+    Ok(props)
+  }
 
-    let sep = config.entry_separator();
-    for (k, v) in self.props {
-      writeln!(w, "{k}{sep}{v}")?;
+  fn into_entries(mut self, order: &EntryOrder, separator: &str) -> Vec<(PropKey, PropVal)> {
+    match order {
+      EntryOrder::Sorted => self.props.into_iter().collect(),
+      EntryOrder::Depth => {
+        // The sort is stable, so entries at the same depth remain sorted by key:
+        let mut entries: Vec<_> = self.props.into_iter().collect();
+        entries.sort_by_key(|(k, _)| k.raw().matches(separator).count());
+        entries
+      }
+      EntryOrder::Value => {
+        // The sort is stable, so entries with equal values remain sorted by key:
+        let mut entries: Vec<_> = self.props.into_iter().collect();
+        entries.sort_by(|(_, v1), (_, v2)| v1.raw().cmp(v2.raw()));
+        entries
+      }
+      EntryOrder::Insertion => self.insertion_order.into_iter()
+        .filter_map(|k| self.props.remove_entry(&k))
+        .collect(),
     }
-    w.flush().map_err(anyhow::Error::new)
   }
-}
 
-struct PropertiesBuilder<'a>(&'a Config);
+  /// Writes the properties to the destination file of the [Config], or to the standard output if
+  /// there is none.
+  ///
+  /// A destination file is replaced atomically: the properties are written to a sibling temporary
+  /// file first, which is renamed over the destination only once it is completely written.
+  pub fn export(self, config: &Config) -> anyhow::Result<()> {
+    if config.split_top_level {
+      return self.export_split(config);
+    }
+
+    if config.dry_run {
+      self.report(config);
+      return Ok(());
+    }
 
-impl PropertiesBuilder<'_> {
-  fn build(&self, value: Value) -> Result<Properties, PropertyConstructionError> {
-    match value {
-      Value::Object(object_map) => Ok(self.parse_internal(object_map)),
-      Value::Null => Ok(Properties::empty()),
-      Value::String(_) | Value::Bool(_) | Value::Number(_) => Err(TopLevelPrimitiveError(value)),
-      Value::Array(_) => Err(TopLevelArrayError(value)),
+    if config.check {
+      let dest = config.dest().expect("check mode is validated to have a destination");
+      return self.check(dest, config);
+    }
+
+    if let Some(old) = config.diff_against() {
+      return self.export_diff(old, config);
     }
+
+    if let Some(manifest) = config.key_manifest() {
+      let text = self.key_manifest(config);
+      Self::write_dest(Some(manifest), |w| Ok(w.write_all(text.as_bytes())?))
+        .with_context(|| format!("Cannot write the key manifest {}", manifest.display()))?;
+    }
+
+    if config.append {
+      let dest = config.dest().expect("appending is validated to have a destination");
+      return self.append_to(dest, config);
+    }
+
+    Self::write_dest(config.dest(), |w| self.write_to(w, config))
   }
 
-  fn parse_internal(&self, object_map: serde_json::Map<String, Value>) -> Properties {
-    let props: BTreeMap<PropKey, PropVal> = object_map.into_iter()
-      .flat_map(|(s, v)| self.parse_value(&s, v).into_iter())
+  /// Appends to the `dest` file after a blank line, warning about the keys it has already.
+  fn append_to(self, dest: &Path, config: &Config) -> anyhow::Result<()> {
+    let existing = match fs::read_to_string(dest) {
+      Ok(existing) => existing,
+      Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+      Err(e) => {
+        return Err(e).with_context(|| format!("Cannot read {} to append to it", dest.display()));
+      }
+    };
+    let mut rendered = Vec::new();
+    self.write_to(&mut rendered, config)?;
+    let rendered = String::from_utf8(rendered)?;
+
+    let existing_keys: HashSet<_> = reader::read(&existing).into_iter().map(|(k, _)| k).collect();
+    let colliding: Vec<_> = reader::read(&rendered).into_iter()
+      .map(|(k, _)| k)
+      .filter(|k| existing_keys.contains(k))
       .collect();
-    Properties { props }
-  }
+    if !colliding.is_empty() {
+      warn!(
+        "{} has these keys already, they are appended again: {}",
+        dest.display(), colliding.join(", "));
+    }
 
-  fn parse_value(&self, namespace: &str, value: Value) -> Vec<(PropKey, PropVal)> {
-    let key = PropKey::new(namespace);
-    match value {
-      Value::Null => vec![(key, PropVal::empty())],
-      Value::Number(n) => vec![(key, PropVal::of_num(n))],
-      Value::String(s) => vec![(key, PropVal::of_string(s, self.0.discard_wsp))],
-      Value::Bool(b) => vec![(key, PropVal::of_bool(b))],
-      Value::Object(object_map) => object_map.into_iter()
-        .flat_map(|(s, v)| {
-          let inner_namespace = Self::concat_namespace(namespace, &s);
-          self.parse_value(&inner_namespace, v)
-        })
-        .collect(),
-      Value::Array(values) => match self.0.list_handling() {
-        ListHandling::SingleProp => if Self::has_only_primitives(&values) {
-          let list_val = values.into_iter()
-            .map(Self::primitive_to_string)
-            .collect::<Vec<String>>()
-            .join(str_constant::COMMA);
-          vec![(key, PropVal::of_string(list_val, self.0.discard_wsp))]
-        } else {
-          debug!(
-            "{0} denotes a list, and its members are not exclusively primitives!\n\
-            List handling is configured to run as [ single-prop ], thus key {0} shall be omitted.\n\
-            The list values were: {1:?}",
-            namespace, &values);
-          vec![]
-        },
-        ListHandling::MultiProp => values.into_iter().enumerate()
-          .flat_map(|(i, v)| {
-            let inner_namespace = Self::concat_namespace(namespace, &i.to_string());
-            self.parse_value(&inner_namespace, v)
-          })
-          .collect(),
-      },
+    let mut w = BufWriter::new(OpenOptions::new().create(true).append(true).open(dest)
+      .with_context(|| format!("Cannot open {} to append to it", dest.display()))?);
+    if !existing.is_empty() {
+      let eol = config.line_ending();
+      if !existing.ends_with('\n') {
+        write!(w, "{eol}")?;
+      }
+      write!(w, "{eol}")?;
     }
+    w.write_all(rendered.as_bytes())?;
+    Ok(w.flush()?)
   }
 
-  fn concat_namespace(namespace: &str, sub_key: &str) -> String {
-    let mut inner_namespace = String::with_capacity(namespace.len() + sub_key.len() + 1);
-    inner_namespace.push_str(namespace);
-    inner_namespace.push('.');
-    inner_namespace.push_str(sub_key);
-    inner_namespace
+  /// Exports the entries under every top-level key as separate [Properties] without the key, and
+  /// the entries which are not under a top-level object as the [SPLIT_ROOT_NAME] properties.
+  ///
+  /// A key belongs to the longest top-level key it is nested in, so the top-level keys `a` and
+  /// `a.b` are split apart, even though the keys of both start with `a.`.
+  fn export_split(mut self, config: &Config) -> anyhow::Result<()> {
+    let sep = config.namespace_separator();
+    let mut top_level = mem::take(&mut self.top_level);
+    top_level.sort_by_key(|name| Reverse(name.len()));
+    let nested_in = |key: &str| {
+      top_level.iter().find_map(|name| {
+        let rest = key.strip_prefix(name.as_str())?.strip_prefix(sep)?;
+        Some((name.clone(), String::from(rest)))
+      })
+    };
+
+    let mut part_comments: BTreeMap<String, Vec<(String, String)>> = BTreeMap::new();
+    for (namespace, comment) in mem::take(&mut self.comments) {
+      let part = if namespace.is_empty() {
+        Some((String::from(SPLIT_ROOT_NAME), namespace))
+      } else if top_level.contains(&namespace) {
+        Some((namespace, String::new()))
+      } else {
+        nested_in(&namespace)
+      };
+      if let Some((name, namespace)) = part {
+        part_comments.entry(name).or_default().push((namespace, comment));
+      }
+    }
+
+    let mut parts: BTreeMap<String, Vec<(PropKey, PropVal)>> = BTreeMap::new();
+    let (mut has_root_entries, mut has_root_object) = (false, false);
+    for (k, v) in self.into_entries(&EntryOrder::Insertion, sep) {
+      let (name, k) = match nested_in(k.raw()) {
+        Some((name, key)) => {
+          has_root_object |= name == SPLIT_ROOT_NAME;
+          (name, PropKey::new(&key))
+        }
+        None => {
+          has_root_entries = true;
+          (String::from(SPLIT_ROOT_NAME), k)
+        }
+      };
+      parts.entry(name).or_default().push((k, v));
+    }
+    if has_root_entries && has_root_object {
+      return Err(anyhow::Error::new(InvalidFileNameError(String::from(SPLIT_ROOT_NAME))));
+    }
+
+    for (name, entries) in parts {
+      if name.is_empty() || name == "." || name == ".." || name.contains(['/', '\\']) {
+        return Err(anyhow::Error::new(InvalidFileNameError(name)));
+      }
+      let mut part = Self::from_entries(entries, config.on_duplicate())?;
+      part.comments = part_comments.remove(&name).unwrap_or_default();
+      part.export(&config.for_top_level_key(&name))?;
+    }
+    Ok(())
   }
 
-  fn has_only_primitives(values: &[Value]) -> bool {
-    values.iter().all(|v| !matches!(v, Value::Array { .. } | Value::Object { .. }))
+  /// Writes the properties to `w` exactly as [Properties::export] writes them to the destination,
+  /// e.g. to capture them in a `Vec<u8>` without touching the filesystem.
+  ///
+  /// Dry runs, checks and the key manifest are left to [Properties::export].
+  pub fn write_to<W: Write>(self, mut w: W, config: &Config) -> anyhow::Result<()> {
+    if config.verify {
+      let text = self.render_verified(config)?;
+      return Ok(w.write_all(text.as_bytes())?);
+    }
+    Ok(self.write(&mut w, config)?)
   }
 
-  fn primitive_to_string(value: Value) -> String {
-    match value {
-      Value::String(s) => s,
-      Value::Bool { .. } | Value::Number { .. } | Value::Null => value.to_string(),
-      _ => unreachable!()
+  /// Writes to the destination file through a temporary file, or to the standard output if there
+  /// is none.
+  fn write_dest(
+    dest: Option<&Path>,
+    write: impl FnOnce(&mut dyn Write) -> anyhow::Result<()>,
+  ) -> anyhow::Result<()> {
+    let Some(dest) = dest else {
+      let mut w = BufWriter::new(std::io::stdout());
+      write(&mut w)?;
+      return w.flush().map_err(anyhow::Error::new);
+    };
+
+    let tmp = Self::temp_path(dest);
+    let written = File::create(&tmp).map_err(anyhow::Error::new).and_then(|f| {
+      let mut w = BufWriter::new(f);
+      write(&mut w)?;
+      Ok(w.into_inner().map_err(|e| e.into_error())?.sync_all()?)
+    });
+    match written.and_then(|_| Ok(fs::rename(&tmp, dest)?)) {
+      Ok(()) => Ok(()),
+      Err(e) => {
+        let _ = fs::remove_file(&tmp);
+        Err(e)
+      }
     }
   }
-}
 
-/// Property keys employing the necessary escaping logic.
-mod prop_key {
-  use std::fmt::{Display, Formatter};
+  /// Compares the rendered properties with the contents of `dest`, line endings aside, failing
+  /// with the keys which differ.
+  fn check(self, dest: &Path, config: &Config) -> anyhow::Result<()> {
+    let existing = fs::read_to_string(dest)
+      .with_context(|| format!("Cannot read {} to check it", dest.display()))?
+      .replace("\r\n", "\n");
+    let rendered = self.render(config)?.replace("\r\n", "\n");
+    if existing == rendered {
+      info!("{} is in sync with {}", dest.display(), config.source_name());
+      return Ok(());
+    }
 
-  #[derive(PartialEq, PartialOrd, Eq, Ord)]
-  pub(super) struct PropKey(String);
+    let diff = Self::key_diff(&reader::read(&existing), &reader::read(&rendered));
+    Err(anyhow::Error::new(OutOfSyncError(dest.display().to_string(), diff)))
+  }
 
-  impl PropKey {
-    pub(super) fn new(s: &str) -> Self {
-      // if the string starts with '#', we need to escape it. If it doesn't there is no need (only
-      // line commencing '#' would signal a comment line).
-      // There is a possibility the string starts with leading whitespace and the first
-      // non-whitespace character is a '#' => the escaping loop later accounts for that: escaping
-      // " #foo" as "\ #foo" is sufficient.
-      let mut inner = if let Some('#') = s.chars().next() {
-        let mut temp = String::with_capacity(s.len() + 1);
-        temp.push('\\');
-        temp
-      } else {
-        String::with_capacity(s.len())
-      };
-      for c in s.chars() {
-        if c == ' ' || c == ':' || c == '=' {
-          inner.extend(&['\\', c]);
+  /// Writes the entries differing from the .properties file `old` to the destination, see
+  /// [Properties::entry_diff].
+  fn export_diff(self, old: &Path, config: &Config) -> anyhow::Result<()> {
+    let existing = fs::read_to_string(old)
+      .with_context(|| format!("Cannot read {} to compare with it", old.display()))?;
+    let existing = reader::read(&existing);
+    let converted: Vec<(String, String)> = self.props.iter()
+      .map(|(k, v)| (String::from(k.raw()), String::from(v.raw())))
+      .collect();
+    let (eol, sep) = (config.line_ending(), Self::separator(config));
+    Self::write_dest(config.dest(), |w| {
+      for (op, k, v) in Self::entry_diff(&existing, &converted) {
+        let k = Self::written_key(&PropKey::new(k), config);
+        let line = format!("{op} {k}{sep}{}", escape::escape_value(v, false));
+        if config.ascii {
+          write!(w, "{}{eol}", unicode_escape::escape(&line))?;
         } else {
-          inner.push(c);
+          write!(w, "{line}{eol}")?;
         }
       }
+      Ok(())
+    })
+  }
+
+  /// Lists the keys added to, removed from and changed in `existing` to become `rendered`, in this
+  /// order, each prefixed by `+`, `-` or `~` respectively.
+  fn key_diff(existing: &[(String, String)], rendered: &[(String, String)]) -> Vec<String> {
+    Self::entry_diff(existing, rendered).into_iter()
+      .map(|(op, k, _)| format!("{op} {k}"))
+      .collect()
+  }
+
+  /// Lists the entries added to, removed from and changed in `existing` to become `rendered`, in
+  /// this order, each with its operation (`+`, `-` or `~`) and its value in `rendered`, or in
+  /// `existing` if it is removed.
+  fn entry_diff<'a>(
+    existing: &'a [(String, String)],
+    rendered: &'a [(String, String)],
+  ) -> Vec<(char, &'a str, &'a str)> {
+    let existing: BTreeMap<&str, &str> = existing.iter()
+      .map(|(k, v)| (k.as_str(), v.as_str()))
+      .collect();
+    let rendered: BTreeMap<&str, &str> = rendered.iter()
+      .map(|(k, v)| (k.as_str(), v.as_str()))
+      .collect();
+    let added = rendered.iter()
+      .filter(|(k, _)| !existing.contains_key(*k))
+      .map(|(k, v)| ('+', *k, *v));
+    let removed = existing.iter()
+      .filter(|(k, _)| !rendered.contains_key(*k))
+      .map(|(k, v)| ('-', *k, *v));
+    let changed = rendered.iter()
+      .filter(|(k, v)| existing.get(*k).is_some_and(|old| old != *v))
+      .map(|(k, v)| ('~', *k, *v));
+    added.chain(removed).chain(changed).collect()
+  }
+
+  /// Renders the properties, then reads them back, failing at the first key which does not read
+  /// back with the same value as it was converted to.
+  fn render_verified(self, config: &Config) -> anyhow::Result<String> {
+    let mut expected: BTreeMap<String, String> = self.props.iter()
+      .map(|(k, v)| (String::from(k.raw()), String::from(v.raw())))
+      .collect();
+    let text = self.render(config)?;
+    for (k, v) in reader::read(&text) {
+      if expected.remove(&k).is_none_or(|expected_v| expected_v != v) {
+        return Err(anyhow::Error::new(RoundTripError(k)));
+      }
+    }
+    if let Some((k, _)) = expected.pop_first() {
+      return Err(anyhow::Error::new(RoundTripError(k)));
+    }
+    Ok(text)
+  }
 
-      PropKey(inner)
+  /// Logs what [Properties::export] would write, instead of writing it.
+  fn report(self, config: &Config) {
+    for line in self.report_lines(config) {
+      info!("{line}");
     }
   }
 
-  impl Display for PropKey {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-      self.0.fmt(f)
+  /// Returns the lines [Properties::report] logs: the number of entries, then their keys in the
+  /// order they would be written.
+  fn report_lines(self, config: &Config) -> Vec<String> {
+    let target = config.dest()
+      .map(|p| p.display().to_string())
+      .unwrap_or_else(|| String::from("standard output"));
+    let count = self.props.len();
+    let mut lines = vec![format!("Dry run: {count} entries would be written to {target}")];
+    for (k, _) in self.into_entries(config.order(), config.namespace_separator()) {
+      lines.push(format!("Dry run: {k}"));
     }
+    lines
   }
-}
 
-mod prop_val {
-  use std::fmt::{Display, Formatter};
+  /// Returns the path of the temporary file next to the destination, e.g. `app.properties.tmp`.
+  fn temp_path(dest: &Path) -> PathBuf {
+    let mut file_name = dest.file_name().map(OsString::from).unwrap_or_default();
+    file_name.push(".tmp");
+    dest.with_file_name(file_name)
+  }
 
-  const PROP_VAL_TRUE: &str = "true";
-  const PROP_VAL_FALSE: &str = "false";
+  /// Describes the conversion as a JSON object for tooling (see `--report`): its source and
+  /// destination (`null` for the standard output), and its [ConversionStats].
+  pub fn summary(&self, config: &Config) -> Value {
+    let stats = &self.stats;
+    let dest = config.dest().or(config.output_dir()).map(|dest| dest.display().to_string());
+    serde_json::json!({
+      "source" : config.source_name(),
+      "dest" : dest,
+      "properties" : stats.properties,
+      "max_depth" : stats.max_depth,
+      "expanded_lists" : stats.expanded_lists,
+      "dropped_lists" : stats.dropped_list_keys,
+      "duplicate_keys" : stats.duplicate_keys,
+      "coerced_values" : stats.coerced_values,
+    })
+  }
 
-  #[derive(PartialEq, PartialOrd, Eq, Ord)]
-  pub(super) struct PropVal(String);
+  /// Returns the summary of the conversion producing these properties.
+  pub fn stats(&self) -> &ConversionStats {
+    &self.stats
+  }
 
-  impl PropVal {
-    pub(super) fn empty() -> Self {
-      PropVal(String::new())
+  /// Returns the flattened properties, mapping their unescaped keys to the JSON values they are
+  /// rendered from, without any formatting or escaping applied.
+  ///
+  /// Values are mostly leaves of the JSON document, except for lists joined into a single
+  /// property and subtrees beyond the maximum depth, which are returned as a whole.
+  pub fn into_map(self) -> BTreeMap<String, Value> {
+    self.props.into_iter()
+      .map(|(k, v)| (String::from(k.raw()), v.into_value()))
+      .collect()
+  }
+
+  /// Returns the keys of the properties as they are written, i.e. escaped, in sorted order.
+  pub fn escaped_keys(&self) -> impl Iterator<Item = String> + '_ {
+    self.props.keys().map(PropKey::to_string)
+  }
+
+  /// Returns the unescaped keys of the properties in sorted order, one per line, ending with the
+  /// configured line ending.
+  pub fn key_manifest(&self, config: &Config) -> String {
+    let eol = config.line_ending();
+    self.props.keys().map(|k| format!("{}{eol}", k.raw())).collect()
+  }
+
+  /// Renders the properties as they would be written by [Properties::export].
+  pub fn render(self, config: &Config) -> anyhow::Result<String> {
+    let mut buf = Vec::new();
+    self.write(&mut buf, config)?;
+    String::from_utf8(buf).map_err(anyhow::Error::new)
+  }
+
+  /// Writes the properties in the XML format of `java.util.Properties.storeToXML`.
+  fn write_xml(self, w: &mut impl Write, config: &Config) -> std::io::Result<()> {
+    let eol = config.line_ending();
+    write!(w, r#"<?xml version="1.0" encoding="UTF-8" standalone="no"?>{eol}"#)?;
+    write!(w, r#"<!DOCTYPE properties SYSTEM "http://java.sun.com/dtd/properties.dtd">{eol}"#)?;
+    write!(w, "<properties>{eol}")?;
+    if config.header {
+      let header = xml_escape::escape(&Self::header(config), config.ascii);
+      write!(w, "<comment>{header}</comment>{eol}")?;
     }
+    for (k, v) in self.into_entries(config.order(), config.namespace_separator()) {
+      let k = xml_escape::escape(k.raw(), config.ascii);
+      let v = xml_escape::escape(v.raw(), config.ascii);
+      write!(w, r#"<entry key="{k}">{v}</entry>{eol}"#)?;
+    }
+    write!(w, "</properties>{eol}")
+  }
 
-    pub(super) fn of_bool(b: bool) -> Self {
-      let str_val = if b { PROP_VAL_TRUE } else { PROP_VAL_FALSE };
-      PropVal(String::from(str_val))
+  /// Creates a comment describing where and when the properties were generated from.
+  fn header(config: &Config) -> String {
+    // Line breaks in the path would end the comment:
+    let source = config.source_name().replace(['\n', '\r'], str_constant::SPACE);
+    format!(
+      "Generated from {source} at {}",
+      humantime::format_rfc3339_seconds(SystemTime::now()))
+  }
+
+  /// Removes the comments of the namespaces holding `key` from `comments`, returning their lines
+  /// as `#` comments.
+  fn take_comments(
+    comments: &mut Vec<(String, String)>,
+    key: &str,
+    separator: &str,
+  ) -> Vec<String> {
+    let holds_key = |namespace: &str| namespace.is_empty() || key.strip_prefix(namespace)
+      .is_some_and(|rest| rest.is_empty() || rest.starts_with(separator));
+    let (due, pending) = mem::take(comments).into_iter()
+      .partition::<Vec<_>, _>(|(namespace, _)| holds_key(namespace));
+    *comments = pending;
+    due.iter()
+      .flat_map(|(_, comment)| comment.lines())
+      .map(|line| format!("# {line}").trim_end().to_string())
+      .collect()
+  }
+
+  fn write(self, w: &mut impl Write, config: &Config) -> std::io::Result<()> {
+    match config.output() {
+      OutputFormat::Properties => self.write_properties(w, config),
+      OutputFormat::Xml => self.write_xml(w, config),
+      OutputFormat::Env => self.write_env(w, config),
     }
+  }
 
-    pub(super) fn of_num(n: serde_json::Number) -> Self {
-      PropVal(n.to_string())
+  /// Writes the properties as an env-file with `DB_URL` style keys.
+  fn write_env(mut self, w: &mut impl Write, config: &Config) -> std::io::Result<()> {
+    let eol = config.line_ending();
+    if config.header {
+      write!(w, "# {}{eol}", Self::header(config))?;
     }
 
-    pub(super) fn of_string(s: String, discard_wsp: bool) -> Self {
-      PropVal(s.normalise(discard_wsp))
+    let mut comments = mem::take(&mut self.comments);
+    for (k, v) in self.into_entries(config.order(), config.namespace_separator()) {
+      for comment in Self::take_comments(&mut comments, k.raw(), config.namespace_separator()) {
+        write!(w, "{comment}{eol}")?;
+      }
+      if config.annotate_types {
+        write!(w, "# type: {}{eol}", v.json_type())?;
+      }
+      let k = env_format::key(k.raw(), config.namespace_separator());
+      let v = env_format::value(v.raw());
+      write!(w, "{k}={v}{eol}")?;
     }
+    Ok(())
   }
 
-  impl Display for PropVal {
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-      self.0.fmt(f)
+  fn write_properties(mut self, w: &mut impl Write, config: &Config) -> std::io::Result<()> {
+    let eol = config.line_ending();
+    if config.header {
+      let header = Self::header(config);
+      if config.ascii {
+        write!(w, "# {}{eol}", unicode_escape::escape(&header))?;
+      } else {
+        write!(w, "# {header}{eol}")?;
+      }
     }
-  }
 
-  /// .properties file behaviour
-  ///
-  /// Trailing whitespace is always significant.
-  ///
-  /// A leading whitespace is dropped because the following formats should yield the same result:
-  /// ```properties
-  /// key=val
-  /// key = val
-  /// ```
-  /// (There are optional whitespaces around the entry separator.)
-  /// To preserve the whitespace, it must be escaped with a backslash:
-  /// ```properties
-  /// key=\     val
-  /// ```
-  ///
-  trait WhiteSpaceNormalised {
-    /// Normalises a value to abide by the `.properties` file leading whitespace rules.
-    fn normalise(self, discard_wsp: bool) -> Self;
-  }
-
-  impl WhiteSpaceNormalised for String {
-    /// Normalises a [String] to abide by the `.properties` file leading whitespace rules.
-    ///
-    /// If the provided argument is `true` the provided [String] is trimmed of leading whitespace if
-    /// necessary.
-    /// - `"foo"` and `"    foo"` will both be rendered as `"foo"`
-    ///
-    /// If the provided argument is `false`, a leading backslash is inserted if necessary to preserve
-    /// the leading whitespace:
-    /// - `"bar"` will be left unchanged
-    /// - `"    bar"` will be rendered as `"\    bar"`
-    fn normalise(self, discard_wsp: bool) -> Self {
-      let starts_with_wsp = match self.chars().next() {
-        Some(c) => c.is_whitespace(),
-        _ => false,
+    let sep = Self::separator(config);
+    let mut comments = mem::take(&mut self.comments);
+    for (k, v) in self.into_entries(config.order(), config.namespace_separator()) {
+      for comment in Self::take_comments(&mut comments, k.raw(), config.namespace_separator()) {
+        if config.ascii {
+          write!(w, "{}{eol}", unicode_escape::escape(&comment))?;
+        } else {
+          write!(w, "{comment}{eol}")?;
+        }
+      }
+      if config.annotate_types {
+        write!(w, "# type: {}{eol}", v.json_type())?;
+      }
+      let k = Self::written_key(&k, config);
+      let line = if config.keys_only {
+        k
+      } else if config.values_only {
+        v.to_string()
+      } else {
+        format!("{k}{sep}{v}")
       };
-
-      if starts_with_wsp && discard_wsp {
-        self.trim_start().into()
-      } else if starts_with_wsp {
-        let mut ret = String::with_capacity(self.len() + 1);
-        ret.push('\\');
-        ret.push_str(&self);
-        ret
+      if config.ascii {
+        write!(w, "{}{eol}", unicode_escape::escape(&line))?;
       } else {
-        self
+        write!(w, "{line}{eol}")?;
       }
     }
+    Ok(())
+  }
+
+  /// Returns the entry separator, padded with spaces if configured.
+  fn separator(config: &Config) -> String {
+    if config.pad_separator {
+      format!(" {} ", config.entry_separator())
+    } else {
+      String::from(config.entry_separator())
+    }
+  }
+
+  /// Returns the key as written in properties output: percent-encoded, unescaped or escaped, as
+  /// configured.
+  fn written_key(k: &PropKey, config: &Config) -> String {
+    match config.key_encoding() {
+      KeyEncoding::Percent => percent_encoding::encode_key(k.raw(), config.namespace_separator()),
+      KeyEncoding::Properties if config.no_key_escape => String::from(k.raw()),
+      KeyEncoding::Properties => k.escaped_with(config.space_escape(), config.escape_all_hashes),
+    }
   }
 }
 
-#[cfg(test)]
-mod tests {
-  use crate::app_config::Config;
-  use crate::props::prop_key::PropKey;
-  use crate::props::Properties;
+/// Flattens JSON into [Properties], keeping count of the [ConversionStats] and collecting the
+/// comments of objects along the way, as well as the JSON Pointers of the values flattened under
+/// each namespace when validating keys.
+struct PropertiesBuilder<'a>(
+  &'a Config,
+  RefCell<ConversionStats>,
+  RefCell<Vec<(String, String)>>,
+  RefCell<HashMap<String, String>>,
+);
 
-  fn assert_key_has_value(prop: &Properties, key: &str, expected: &str) {
-    let k = PropKey::new(key);
-    let actual = prop.props.get(&k).unwrap_or_else(|| panic!("key {key} is present"));
-    assert_eq!(format!("{actual}"), expected);
+impl<'a> PropertiesBuilder<'a> {
+  fn new(config: &'a Config) -> Self {
+    PropertiesBuilder(config, RefCell::default(), RefCell::default(), RefCell::default())
   }
 
-  #[test]
-  fn foo_1() {
-    let config = Config::empty();
-    let value = serde_json::json!({
-        "a" : "a value",
-        "b" : "b value",
-        "c" : false
-    });
-    let prop = Properties::create(value, &config).expect("JSON is parsed");
-    assert_key_has_value(&prop, "a", "a value");
-    assert_key_has_value(&prop, "b", "b value");
-    assert_key_has_value(&prop, "c", "false");
+  fn build(&self, value: Value) -> Result<Properties, PropertyConstructionError> {
+    let members = self.members(value)?;
+    let top_level = match self.0.split_top_level {
+      true => members.iter().map(|(namespace, _)| namespace.clone()).collect(),
+      false => Vec::new(),
+    };
+    let mut props = self.parse_members(members)?;
+    props.top_level = top_level;
+    let duplicate_keys = mem::take(&mut props.stats.duplicate_keys);
+    props.stats = ConversionStats { properties: props.props.len(), duplicate_keys, ..self.1.take() };
+    props.comments = self.2.take();
+    Ok(props)
   }
 
-  #[test]
-  fn foo_2() {
-    let config = Config::empty();
-    let value = serde_json::json!({
-      "a" : "a value",
-      "b" : {
-        "foo" : 123,
-        "bar" : "bar val",
-        "baz" : false
-      },
-      "c" : {
-        "foo" : 999
-      }
-    });
-    let prop = Properties::create(value, &config).expect("JSON is parsed");
-    assert_eq!(prop.props.len(), 5);
-    assert_key_has_value(&prop, "a", "a value");
-    assert_key_has_value(&prop, "b.foo", "123");
-    assert_key_has_value(&prop, "b.bar", "bar val");
-    assert_key_has_value(&prop, "b.baz", "false");
-    assert_key_has_value(&prop, "c.foo", "999");
+  /// Returns the top-level `(namespace, value)` pairs of the source.
+  fn members(&self, value: Value) -> Result<Vec<(String, Value)>, PropertyConstructionError> {
+    match value {
+      Value::Object(object_map) => self.object_members(object_map),
+      Value::Null => Ok(Vec::new()),
+      Value::String(_) | Value::Bool(_) | Value::Number(_) => Err(TopLevelPrimitiveError(value)),
+      Value::Array(values) if self.0.allow_top_array => self.top_array_members(values),
+      Value::Array(_) => Err(TopLevelArrayError(value)),
+    }
   }
 
-  #[test]
-  fn creating_prop_key_with_a_simple_string_leaves_the_string_unchanged() {
-    let k = PropKey::new("foo");
-    assert_eq!(format!("{k}"), "foo");
+  fn object_members(
+    &self,
+    mut object_map: serde_json::Map<String, Value>,
+  ) -> Result<Vec<(String, Value)>, PropertyConstructionError> {
+    self.take_comment(self.0.prefix().unwrap_or_default(), &mut object_map);
+    let mut members = Vec::with_capacity(object_map.len());
+    for (s, v) in object_map {
+      let Some(segment) = self.member_segment(self.0.prefix().unwrap_or_default(), &s)? else {
+        continue;
+      };
+      let namespace = match self.0.prefix() {
+        Some(prefix) => self.concat_namespace(prefix, &segment),
+        None => segment,
+      };
+      self.record_source(&namespace, None, &s);
+      members.push((namespace, v));
+    }
+    Ok(members)
   }
 
-  #[test]
-  fn creating_prop_key_with_colon_has_the_colon_escaped() {
-    let k = PropKey::new("fo:o");
-    assert_eq!(format!("{k}"), "fo\\:o");
+  /// Returns the members of a top-level array: the array under the prefix like any other list, or
+  /// else its members under their indices.
+  ///
+  /// Without a prefix, only `multi-prop` list handling can name the keys of the array.
+  fn top_array_members(
+    &self,
+    mut values: Vec<Value>,
+  ) -> Result<Vec<(String, Value)>, PropertyConstructionError> {
+    let members = match (self.0.prefix(), self.0.list_handling()) {
+      (Some(prefix), _) => vec![(String::from(prefix), Value::Array(values))],
+      (None, ListHandling::MultiProp) => {
+        self.1.borrow_mut().expanded_lists += 1;
+        let truncated = self.limit_elements(str_constant::EMPTY, &mut values)?
+          .map(|(k, _)| (String::from(k.raw()), Value::Bool(true)));
+        values.into_iter().enumerate()
+          .map(|(i, v)| {
+            let namespace = self.index_namespace(str_constant::EMPTY, self.0.array_base() + i);
+            self.record_source(&namespace, None, &i.to_string());
+            (namespace, v)
+          })
+          .chain(truncated)
+          .collect()
+      }
+      (None, ListHandling::SingleProp | ListHandling::Count) => {
+        return Err(TopLevelArrayError(Value::Array(values)));
+      }
+    };
+    Ok(members)
   }
 
-  #[test]
-  fn creating_prop_key_with_leading_number_sign_escapes_the_first_character() {
-    let k = PropKey::new("#foo");
-    assert_eq!(format!("{k}"), "\\#foo");
+  /// Parses the top-level `(namespace, value)` pairs, then finishes the resulting entries.
+  fn parse_members(
+    &self,
+    members: Vec<(String, Value)>,
+  ) -> Result<Properties, PropertyConstructionError> {
+    let progress = Progress::new(members.len(), self.0.shows_progress(), std::io::stderr());
+    let mut entries = Vec::new();
+    for (namespace, value) in members {
+      entries.extend(self.parse_value(&namespace, value, 1)?);
+      progress.tick();
+    }
+    progress.finish();
+    let entries = self.finish_entries(entries)?;
+    Properties::from_entries(entries, self.0.on_duplicate())
+  }
+
+  /// Selects, validates, transforms, redacts and limits the length of parsed entries.
+  fn finish_entries(
+    &self,
+    mut entries: Vec<(PropKey, PropVal)>,
+  ) -> Result<Vec<(PropKey, PropVal)>, PropertyConstructionError> {
+    entries.retain(|(k, _)| self.0.is_key_selected(k.raw()));
+    self.validate_keys(&entries)?;
+    if let Some(command) = self.0.transform_command() {
+      for (k, v) in entries.iter_mut().filter(|(k, _)| !self.0.is_key_redacted(k.raw())) {
+        let transformed = transform::run(&command, v.raw())
+          .map_err(|reason| TransformError(String::from(k.raw()), reason))?;
+        *v = self.string_val(transformed.clone()).rendered_from(Value::String(transformed));
+      }
+    }
+    for (k, v) in entries.iter_mut().filter(|(k, _)| self.0.is_key_redacted(k.raw())) {
+      debug!("The value of {k} is redacted.");
+      let placeholder = String::from(self.0.redact_placeholder());
+      *v = PropVal::of_string(placeholder.clone(), false).rendered_from(Value::String(placeholder));
+    }
+    if let Some(max_len) = self.0.max_value_length() {
+      entries = entries.into_iter()
+        .map(|(k, v)| self.limit_length(k, v, max_len))
+        .collect::<Result<_, _>>()?;
+    }
+    Ok(entries)
+  }
+
+  /// Checks every key against the `--key-pattern`, failing with all the keys not matching it.
+  fn validate_keys(&self, entries: &[(PropKey, PropVal)]) -> Result<(), PropertyConstructionError> {
+    let Some(pattern) = self.0.key_pattern().filter(|_| self.0.validates_keys()) else {
+      return Ok(());
+    };
+    let invalid: Vec<_> = entries.iter()
+      .filter(|(k, _)| !pattern.is_match(k.raw()))
+      .map(|(k, _)| (String::from(k.raw()), self.source_of(k.raw())))
+      .collect();
+    if invalid.is_empty() { Ok(()) } else { Err(InvalidKeyError(invalid)) }
+  }
+
+  /// Records the JSON Pointer of the value flattened under `namespace` when validating keys: the
+  /// pointer of the value under the `parent` namespace (or of the converted root) and `token`.
+  fn record_source(&self, namespace: &str, parent: Option<&str>, token: &str) {
+    if !self.0.validates_keys() {
+      return;
+    }
+    let mut sources = self.3.borrow_mut();
+    let parent = match parent {
+      Some(parent) => sources.get(parent).cloned().unwrap_or_default(),
+      None => String::from(self.0.pointer().unwrap_or_default()),
+    };
+    let pointer = format!("{parent}/{}", token.replace('~', "~0").replace('/', "~1"));
+    sources.insert(String::from(namespace), pointer);
+  }
+
+  /// Returns the JSON Pointer of the value the key is flattened from; a key of no value of its own
+  /// (e.g. the `count` of a list) is attributed to the longest namespace it starts with.
+  fn source_of(&self, key: &str) -> String {
+    self.3.borrow().iter()
+      .filter(|(namespace, _)| key.starts_with(namespace.as_str()))
+      .max_by_key(|(namespace, _)| namespace.len())
+      .map_or_else(|| String::from(self.0.pointer().unwrap_or_default()), |(_, p)| p.clone())
+  }
+
+  /// Handles a value longer than `max_len` bytes, once escaped, as configured.
+  fn limit_length(
+    &self,
+    key: PropKey,
+    val: PropVal,
+    max_len: usize,
+  ) -> Result<(PropKey, PropVal), PropertyConstructionError> {
+    let len = val.written_len(self.0.ascii);
+    if len <= max_len {
+      return Ok((key, val));
+    }
+
+    match self.0.on_oversize() {
+      OversizeHandling::Truncate => {
+        debug!("The value of {key} is {len} bytes long, it is truncated to {max_len} bytes.");
+        let val = val.truncate(max_len, self.0.ascii);
+        Ok((key, val))
+      }
+      OversizeHandling::Error => Err(ValueTooLongError(String::from(key.raw()), len)),
+      OversizeHandling::Keep => {
+        warn!("The value of {key} is {len} bytes long, exceeding {max_len} bytes.");
+        Ok((key, val))
+      }
+    }
+  }
+
+  /// Parses every `(namespace, value)` pair, collecting all the resulting entries.
+  fn parse_values(
+    &self,
+    members: impl Iterator<Item=(String, Value)>,
+    depth: usize,
+  ) -> Result<Vec<(PropKey, PropVal)>, PropertyConstructionError> {
+    let mut entries = Vec::new();
+    for (namespace, value) in members {
+      entries.extend(self.parse_value(&namespace, value, depth)?);
+    }
+    Ok(entries)
+  }
+
+  fn parse_value(
+    &self,
+    namespace: &str,
+    value: Value,
+    depth: usize,
+  ) -> Result<Vec<(PropKey, PropVal)>, PropertyConstructionError> {
+    let key = PropKey::new(namespace);
+    let mut stats = self.1.borrow_mut();
+    stats.max_depth = stats.max_depth.max(depth);
+    drop(stats);
+    if self.is_max_depth_reached(depth) && self.is_expanded(&value) {
+      debug!("{namespace} is at the maximum depth of {depth}, its value is kept as a JSON string.");
+      let val = self.string_val(value.to_string()).rendered_from(value);
+      return Ok(vec![(key, val)]);
+    }
+
+    let value = match value {
+      Value::Object(mut object_map) => {
+        self.take_comment(namespace, &mut object_map);
+        Value::Object(object_map)
+      }
+      value => value,
+    };
+    match value {
+      Value::Null => match self.0.null_handling() {
+        NullHandling::Empty => Ok(vec![(key, PropVal::empty())]),
+        NullHandling::Omit => Ok(vec![]),
+        NullHandling::Literal => Ok(vec![(key, PropVal::null())]),
+      },
+      Value::Number(n) if Self::is_non_finite(&n) => match self.non_finite_str(namespace, &n)? {
+        Some(s) => Ok(vec![(key, self.string_val(s).rendered_from(Value::Number(n)))]),
+        None => Ok(vec![]),
+      },
+      Value::Number(n) => match self.format_value(namespace, &n.to_string())? {
+        Some(s) => Ok(vec![(key, self.string_val(s).rendered_from(Value::Number(n)))]),
+        None => Ok(vec![(key, PropVal::of_num(&n, self.number_str(&n)))]),
+      },
+      Value::String(s) => {
+        let s = self.decode_base64(namespace, s)?;
+        if let Some(embedded) = self.parse_embedded_json(&s) {
+          debug!("{namespace} holds embedded JSON, it is flattened as well.");
+          return self.parse_value(namespace, embedded, depth);
+        }
+        let s = self.expand_env(namespace, s)?;
+        if let Some(b) = self.bool_string(&s) {
+          return Ok(vec![(key, PropVal::of_bool(b, self.0.bool_format()))]);
+        }
+        let s = self.format_value(namespace, &s)?.unwrap_or(s);
+        Ok(vec![(key, self.string_val(s))])
+      }
+      Value::Bool(b) => Ok(vec![(key, PropVal::of_bool(b, self.0.bool_format()))]),
+      Value::Object(object_map) if object_map.is_empty() => match self.0.empty_object() {
+        EmptyObjectHandling::Omit => Ok(vec![]),
+        EmptyObjectHandling::KeyOnly => Ok(vec![(key, PropVal::empty())]),
+        EmptyObjectHandling::Marker => {
+          let key = PropKey::new(&self.concat_namespace(namespace, EMPTY_OBJECT_SEGMENT));
+          Ok(vec![(key, PropVal::of_bool(true, self.0.bool_format()))])
+        }
+      },
+      Value::Object(object_map) => {
+        let members = object_map.into_iter()
+          .filter_map(|(s, v)| {
+            let segment = self.member_segment(namespace, &s).transpose()?;
+            Some(segment.map(|segment| {
+              let member_namespace = self.concat_namespace(namespace, &segment);
+              self.record_source(&member_namespace, Some(namespace), &s);
+              (member_namespace, v)
+            }))
+          })
+          .collect::<Result<Vec<_>, _>>()?;
+        self.parse_values(members.into_iter(), depth + 1)
+      }
+      Value::Array(values) => match self.0.list_handling() {
+        ListHandling::SingleProp => if Self::has_only_primitives(&values) {
+          let mut members = values.iter()
+            .filter_map(|member| self.primitive_to_string(namespace, member.clone()).transpose())
+            .collect::<Result<Vec<String>, _>>()?;
+          self.sort_list_members(&mut members);
+          if self.0.dedup_list {
+            let mut seen = HashSet::new();
+            members.retain(|member| seen.insert(member.clone()));
+          }
+          let list_val = members.into_iter()
+            .map(|member| self.escape_list_member(namespace, member))
+            .collect::<Vec<_>>()
+            .join(self.0.list_delimiter());
+          let val = self.string_val(list_val).rendered_from(Value::Array(values));
+          Ok(vec![(key, val)])
+        } else {
+          self.parse_complex_list(namespace, values, depth)
+        },
+        ListHandling::MultiProp => self.parse_list_members(namespace, values, depth),
+        ListHandling::Count => Ok(vec![self.count_entry(namespace, values.len())]),
+      },
+    }
+  }
+
+  /// Sorts the rendered members of a list joined into a single property, if configured.
+  fn sort_list_members(&self, members: &mut [String]) {
+    if self.0.sort_list {
+      members.sort();
+    } else if self.0.sort_list_numeric {
+      members.sort_by(|a, b| match (a.parse::<f64>(), b.parse::<f64>()) {
+        (Ok(x), Ok(y)) => x.total_cmp(&y),
+        (Ok(_), Err(_)) => Ordering::Less,
+        (Err(_), Ok(_)) => Ordering::Greater,
+        (Err(_), Err(_)) => a.cmp(b),
+      });
+    }
+  }
+
+  /// Removes the comment field from an object if it holds a string, recording it as the comment of
+  /// the namespace of the object.
+  fn take_comment(&self, namespace: &str, object_map: &mut serde_json::Map<String, Value>) {
+    let Some(field) = self.0.comment_field() else {
+      return;
+    };
+    if object_map.get(field).is_some_and(Value::is_string) {
+      if let Some(Value::String(comment)) = object_map.remove(field) {
+        self.2.borrow_mut().push((String::from(namespace), comment));
+      }
+    }
+  }
+
+  /// Parses a string holding a JSON object or array, if enabled.
+  ///
+  /// Other JSON values (e.g. `"8080"`) are not considered embedded JSON. As every level of
+  /// embedding is strictly shorter than the string holding it, the recursion always terminates.
+  fn parse_embedded_json(&self, s: &str) -> Option<Value> {
+    if !self.0.parse_embedded_json || !s.trim_start().starts_with(['{', '[']) {
+      return None;
+    }
+    serde_json::from_str(s).ok().filter(|v: &Value| v.is_object() || v.is_array())
+  }
+
+  /// Creates the `<namespace>.count` entry of a list of `len` members.
+  fn count_entry(&self, namespace: &str, len: usize) -> (PropKey, PropVal) {
+    let key = PropKey::new(&self.concat_namespace(namespace, LIST_COUNT_SEGMENT));
+    let len = Number::from(len);
+    (key, PropVal::of_num(&len, prop_val::number_to_str(&len, self.0.number_format())))
+  }
+
+  /// Handles a list with non-primitive members when using `single-prop` list handling.
+  fn parse_complex_list(
+    &self,
+    namespace: &str,
+    values: Vec<Value>,
+    depth: usize,
+  ) -> Result<Vec<(PropKey, PropVal)>, PropertyConstructionError> {
+    match self.0.on_complex_list() {
+      ComplexListHandling::Omit => {
+        debug!(
+          "{0} denotes a list, and its members are not exclusively primitives!\n\
+          List handling is configured to run as [ single-prop ], thus key {0} shall be omitted.\n\
+          The list values were: {1:?}",
+          namespace, &values);
+        let mut stats = self.1.borrow_mut();
+        stats.dropped_lists += 1;
+        stats.dropped_list_keys.push(String::from(namespace));
+        Ok(vec![])
+      }
+      ComplexListHandling::Error => Err(ComplexListError(String::from(namespace), values)),
+      ComplexListHandling::FallbackMulti => {
+        debug!("{namespace} denotes a list of non-primitives, it is expanded as [ multi-prop ].");
+        self.parse_list_members(namespace, values, depth)
+      }
+    }
+  }
+
+  /// Expands every member of a list under its own indexed key, up to the maximum number of array
+  /// elements.
+  fn parse_list_members(
+    &self,
+    namespace: &str,
+    mut values: Vec<Value>,
+    depth: usize,
+  ) -> Result<Vec<(PropKey, PropVal)>, PropertyConstructionError> {
+    self.1.borrow_mut().expanded_lists += 1;
+    let count = self.0.count_lists.then(|| self.count_entry(namespace, values.len()));
+    let truncated = self.limit_elements(namespace, &mut values)?;
+    let members = values.into_iter().enumerate()
+      .map(|(i, v)| {
+        let member_namespace = self.index_namespace(namespace, self.0.array_base() + i);
+        self.record_source(&member_namespace, Some(namespace), &i.to_string());
+        (member_namespace, v)
+      });
+    let mut entries = self.parse_values(members, depth + 1)?;
+    entries.extend(count);
+    entries.extend(truncated);
+    Ok(entries)
+  }
+
+  /// Handles a list longer than the maximum number of array elements as configured, returning the
+  /// marker of the truncated list.
+  fn limit_elements(
+    &self,
+    namespace: &str,
+    values: &mut Vec<Value>,
+  ) -> Result<Option<(PropKey, PropVal)>, PropertyConstructionError> {
+    let Some(max) = self.0.max_array_elements().filter(|max| values.len() > *max) else {
+      return Ok(None);
+    };
+    match self.0.on_array_overflow() {
+      ArrayOverflowHandling::Truncate => {
+        debug!("{namespace} has {} members, only the first {max} are expanded.", values.len());
+        values.truncate(max);
+        let key = match namespace {
+          "" => PropKey::new(TRUNCATED_LIST_SEGMENT),
+          _ => PropKey::new(&self.concat_namespace(namespace, TRUNCATED_LIST_SEGMENT)),
+        };
+        Ok(Some((key, PropVal::of_bool(true, self.0.bool_format()))))
+      }
+      ArrayOverflowHandling::Error => {
+        Err(TooManyElementsError(String::from(namespace), values.len()))
+      }
+    }
+  }
+
+  /// Creates a string value, discarding its leading or trailing whitespace as configured.
+  fn string_val(&self, s: String) -> PropVal {
+    let s = if self.0.trim_trailing { String::from(s.trim_end()) } else { s };
+    PropVal::of_string(s, self.0.discard_wsp)
+  }
+
+  fn is_max_depth_reached(&self, depth: usize) -> bool {
+    let max_depth = self.0.max_depth();
+    max_depth != 0 && depth >= max_depth
+  }
+
+  /// Returns `true` if the value would be expanded into multiple keys if the depth allowed it.
+  fn is_expanded(&self, value: &Value) -> bool {
+    match value {
+      Value::Object(_) => true,
+      Value::Array(values) => match self.0.list_handling() {
+        ListHandling::MultiProp => true,
+        ListHandling::Count => false,
+        ListHandling::SingleProp => !Self::has_only_primitives(values)
+          && *self.0.on_complex_list() == ComplexListHandling::FallbackMulti,
+      },
+      _ => false,
+    }
+  }
+
+  /// Converts the key of a JSON object member into a key segment, applying the key case and the
+  /// key replacements.
+  fn segment(&self, key: &str) -> String {
+    let mut segment = key_case::convert(key, self.0.key_case());
+    for replacement in self.0.key_replace() {
+      let replaced = replacement.apply(&segment);
+      let sep = self.0.namespace_separator();
+      if replaced.matches(sep).count() > segment.matches(sep).count() {
+        warn!(
+          "Key segment [ {segment} ] is replaced by [ {replaced} ], which contains the namespace \
+          separator [ {sep} ]: the segment looks nested!");
+      }
+      segment = replaced;
+    }
+    segment
+  }
+
+  /// Returns the segment of the object member `key`, handling an empty segment as configured, or
+  /// `None` if the member is skipped.
+  fn member_segment(
+    &self,
+    namespace: &str,
+    key: &str,
+  ) -> Result<Option<String>, PropertyConstructionError> {
+    let segment = self.segment(key);
+    if !segment.is_empty() {
+      return Ok(Some(segment));
+    }
+    match self.0.on_empty_key() {
+      EmptyKeyHandling::Keep => Ok(Some(segment)),
+      EmptyKeyHandling::Error => Err(EmptyKeyError(String::from(namespace))),
+      EmptyKeyHandling::Skip => {
+        debug!("A member of [ {namespace} ] has an empty key, it is skipped.");
+        Ok(None)
+      }
+      EmptyKeyHandling::Placeholder => Ok(Some(String::from(self.0.empty_key_placeholder()))),
+    }
+  }
+
+  fn concat_namespace(&self, namespace: &str, sub_key: &str) -> String {
+    let sep = self.0.namespace_separator();
+    let mut inner_namespace = String::with_capacity(namespace.len() + sub_key.len() + sep.len());
+    inner_namespace.push_str(namespace);
+    inner_namespace.push_str(sep);
+    inner_namespace.push_str(sub_key);
+    inner_namespace
+  }
+
+  fn index_namespace(&self, namespace: &str, index: usize) -> String {
+    match self.0.array_style() {
+      ArrayStyle::Dot if namespace.is_empty() => index.to_string(),
+      ArrayStyle::Dot => self.concat_namespace(namespace, &index.to_string()),
+      ArrayStyle::Bracket => format!("{namespace}[{index}]"),
+    }
+  }
+
+  /// Escapes the list delimiter in a list member with the configured escape character, or quotes
+  /// the member with the configured quote style.
+  ///
+  /// The escape character itself is escaped as well, so the joined list can be split unambiguously.
+  fn escape_list_member(&self, namespace: &str, member: String) -> String {
+    let delimiter = self.0.list_delimiter();
+    if let Some(quote) = self.0.quote_style().quote() {
+      return Self::quote_list_member(member, delimiter, quote);
+    }
+
+    match self.0.list_escape() {
+      Some(escape) if member.contains(delimiter) || member.contains(escape) => {
+        let escaped_escape = format!("{escape}{escape}");
+        member.replace(escape, &escaped_escape).replace(delimiter, &format!("{escape}{delimiter}"))
+      }
+      None if member.contains(delimiter) => {
+        warn!(
+          "{namespace} denotes a list, and its member [ {member} ] contains the list delimiter \
+          [ {delimiter} ]. The list cannot be split unambiguously!");
+        member
+      }
+      _ => member,
+    }
+  }
+
+  fn quote_list_member(member: String, delimiter: &str, quote: char) -> String {
+    if !member.contains(delimiter) && !member.contains(|c: char| c.is_whitespace() || c == quote) {
+      return member;
+    }
+
+    let mut quoted = String::with_capacity(member.len() + 2);
+    quoted.push(quote);
+    for c in member.chars() {
+      if c == quote || c == '\\' {
+        quoted.push('\\');
+      }
+      quoted.push(c);
+    }
+    quoted.push(quote);
+    quoted
+  }
+
+  fn has_only_primitives(values: &[Value]) -> bool {
+    values.iter().all(|v| !matches!(v, Value::Array { .. } | Value::Object { .. }))
+  }
+
+  /// Renders a list member, or returns `None` if it is skipped.
+  fn primitive_to_string(
+    &self,
+    namespace: &str,
+    value: Value,
+  ) -> Result<Option<String>, PropertyConstructionError> {
+    match value {
+      Value::String(s) => {
+        let s = self.expand_env(namespace, s)?;
+        match self.bool_string(&s) {
+          Some(b) => Ok(Some(String::from(prop_val::bool_to_str(b, self.0.bool_format())))),
+          None => Ok(Some(s)),
+        }
+      }
+      Value::Bool(b) => Ok(Some(String::from(prop_val::bool_to_str(b, self.0.bool_format())))),
+      Value::Number(n) if Self::is_non_finite(&n) => self.non_finite_str(namespace, &n),
+      Value::Number(n) => Ok(Some(self.number_str(&n))),
+      Value::Null => match self.0.list_null_handling() {
+        NullHandling::Empty => Ok(Some(String::new())),
+        NullHandling::Omit => Ok(None),
+        NullHandling::Literal => Ok(Some(value.to_string())),
+      },
+      _ => unreachable!()
+    }
+  }
+
+  /// Renders a (finite) number in the configured format, writing whole floats as integers or
+  /// integers as decimals if requested.
+  fn number_str(&self, n: &Number) -> String {
+    let s = prop_val::number_to_str(n, self.0.number_format());
+    let is_float = s.contains(['.', 'e', 'E']);
+    if self.0.integer_floats && is_float {
+      let whole = Self::as_float(n).filter(|f| f.fract() == 0.0 && f.abs() <= 2f64.powi(53));
+      if let Some(f) = whole {
+        // The cast is exact for whole floats of this magnitude, and turns `-0.0` into `0`:
+        return (f as i64).to_string();
+      }
+    }
+    if self.0.force_decimal && !is_float {
+      return s + ".0";
+    }
+    s
+  }
+
+  /// Returns `true` if the number is infinite or NaN as a 64-bit float, which a number can only be
+  /// if its exact token is kept (e.g. `1e400`), or a lenient parser produced it.
+  fn is_non_finite(n: &Number) -> bool {
+    Self::as_float(n).is_some_and(|f| !f.is_finite())
+  }
+
+  /// Returns the number as a 64-bit float, which unlike [Number::as_f64] may be infinite or NaN.
+  fn as_float(n: &Number) -> Option<f64> {
+    n.to_string().parse().ok()
+  }
+
+  /// Handles a number which is not finite as configured, returning `None` if it is skipped.
+  fn non_finite_str(
+    &self,
+    namespace: &str,
+    n: &Number,
+  ) -> Result<Option<String>, PropertyConstructionError> {
+    match self.0.on_nonfinite() {
+      NonFiniteHandling::Error => Err(NonFiniteNumberError(String::from(namespace), n.to_string())),
+      NonFiniteHandling::Skip => {
+        debug!("The value of {namespace} is not a finite number ({n}), it is skipped.");
+        Ok(None)
+      }
+      NonFiniteHandling::Placeholder => {
+        let placeholder = match (self.0.nonfinite_placeholder(), Self::as_float(n)) {
+          (Some(placeholder), _) => placeholder,
+          (None, Some(f)) if f.is_nan() => "NaN",
+          (None, Some(f)) if f < 0.0 => "-Infinity",
+          (None, _) => "Infinity",
+        };
+        Ok(Some(String::from(placeholder)))
+      }
+    }
+  }
+
+  /// Decodes a base64 string value into UTF-8 text, if its key is configured to be decoded.
+  fn decode_base64(&self, namespace: &str, s: String) -> Result<String, PropertyConstructionError> {
+    if !self.0.is_key_base64(namespace) {
+      return Ok(s);
+    }
+    let bytes = base64::decode(&s).ok_or_else(|| InvalidBase64Error(String::from(namespace)))?;
+    match (String::from_utf8(bytes), self.0.on_invalid_utf8()) {
+      (Ok(decoded), _) => Ok(decoded),
+      (Err(e), InvalidUtf8Handling::Hex) => {
+        debug!("The decoded value of {namespace} is not UTF-8, it is written as hexadecimal.");
+        Ok(e.into_bytes().iter().map(|b| format!("{b:02x}")).collect())
+      }
+      (Err(_), InvalidUtf8Handling::Error) => Err(InvalidUtf8Error(String::from(namespace))),
+    }
+  }
+
+  /// Expands the environment variable placeholders in a string value, if configured.
+  fn expand_env(&self, namespace: &str, s: String) -> Result<String, PropertyConstructionError> {
+    if !self.0.expand_env {
+      return Ok(s);
+    }
+    env_expand::expand(&s, self.0.on_missing_env(), |name| std::env::var(name).ok())
+      .map_err(|name| MissingEnvVarError(name, String::from(namespace)))
+  }
+
+  /// Returns the boolean a string value stands for, if boolean strings are normalised.
+  fn bool_string(&self, s: &str) -> Option<bool> {
+    if !self.0.normalize_bool_strings {
+      return None;
+    }
+    let is = |literal: &str| {
+      if self.0.bool_strings_ignore_case { s.eq_ignore_ascii_case(literal) } else { s == literal }
+    };
+    if is("true") {
+      Some(true)
+    } else if is("false") {
+      Some(false)
+    } else {
+      None
+    }
+  }
+
+  /// Formats the value of the flattened key with its `--format-path` formatter, if it has one.
+  fn format_value(
+    &self,
+    namespace: &str,
+    s: &str,
+  ) -> Result<Option<String>, PropertyConstructionError> {
+    self.0.value_format(namespace)
+      .map(|f| f.apply(s).map_err(|reason| FormatError(String::from(namespace), reason)))
+      .transpose()
+  }
+}
+
+/// Property keys employing the necessary escaping logic.
+mod prop_key {
+  use std::cmp::Ordering;
+  use std::fmt::{Display, Formatter};
+
+  use crate::app_config::SpaceEscape;
+  use crate::escape;
+
+  #[derive(Clone, PartialEq, Eq)]
+  pub(super) struct PropKey {
+    escaped: String,
+    raw: String,
+  }
+
+  /// Keys are compared by their escaped form, which is unique to every unescaped key, with the
+  /// numbers in them (e.g. list indices) compared by value: `a.2` comes before `a.10`.
+  impl Ord for PropKey {
+    fn cmp(&self, other: &Self) -> Ordering {
+      natural_cmp(&self.escaped, &other.escaped)
+        .then_with(|| self.escaped.cmp(&other.escaped))
+        .then_with(|| self.raw.cmp(&other.raw))
+    }
+  }
+
+  impl PartialOrd for PropKey {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+      Some(self.cmp(other))
+    }
+  }
+
+  /// Compares strings character by character, except for runs of ASCII digits, which are compared
+  /// by their numeric value. Runs differing only in leading zeros compare equal.
+  pub(super) fn natural_cmp(a: &str, b: &str) -> Ordering {
+    let (mut a, mut b) = (a, b);
+    loop {
+      match (a.chars().next(), b.chars().next()) {
+        (Some(x), Some(y)) if x.is_ascii_digit() && y.is_ascii_digit() => {
+          let (a_digits, a_rest) = split_digits(a);
+          let (b_digits, b_rest) = split_digits(b);
+          let a_digits = a_digits.trim_start_matches('0');
+          let b_digits = b_digits.trim_start_matches('0');
+          let ord = a_digits.len().cmp(&b_digits.len()).then_with(|| a_digits.cmp(b_digits));
+          if ord.is_ne() {
+            return ord;
+          }
+          (a, b) = (a_rest, b_rest);
+        }
+        (Some(x), Some(y)) if x != y => return x.cmp(&y),
+        (Some(x), Some(_)) => (a, b) = (&a[x.len_utf8()..], &b[x.len_utf8()..]),
+        (x, y) => return x.is_some().cmp(&y.is_some()),
+      }
+    }
+  }
+
+  fn split_digits(s: &str) -> (&str, &str) {
+    s.split_at(s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len()))
+  }
+
+  impl PropKey {
+    pub(super) fn new(s: &str) -> Self {
+      PropKey { escaped: escape::escape_key(s), raw: String::from(s) }
+    }
+
+    /// Returns the key as it was before escaping.
+    pub(super) fn raw(&self) -> &str {
+      &self.raw
+    }
+
+    /// Returns the key escaped with only the given spaces escaped, and every `#` if `all_hashes`
+    /// is `true`, see [escape::escape_key_with_hashes].
+    pub(super) fn escaped_with(&self, spaces: SpaceEscape, all_hashes: bool) -> String {
+      match (spaces, all_hashes) {
+        (SpaceEscape::All, false) => self.escaped.clone(),
+        _ => escape::escape_key_with_hashes(&self.raw, spaces, all_hashes),
+      }
+    }
+  }
+
+  impl Display for PropKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      self.escaped.fmt(f)
+    }
+  }
+}
+
+mod prop_val {
+  use std::fmt::{Display, Formatter};
+
+  use serde_json::{Number, Value};
+
+  use crate::app_config::{BoolFormat, NumberFormat};
+  use crate::escape::{self, WhiteSpaceNormalised};
+
+  const PROP_VAL_TRUE: &str = "true";
+  const PROP_VAL_FALSE: &str = "false";
+  const PROP_VAL_YES: &str = "yes";
+  const PROP_VAL_NO: &str = "no";
+  const PROP_VAL_ON: &str = "on";
+  const PROP_VAL_OFF: &str = "off";
+  const PROP_VAL_ONE: &str = "1";
+  const PROP_VAL_ZERO: &str = "0";
+  const PROP_VAL_NULL: &str = "null";
+  const TRUNCATION_MARKER: &str = "...";
+
+  /// Renders a boolean in the given format.
+  pub(super) fn bool_to_str(b: bool, format: &BoolFormat) -> &'static str {
+    match (format, b) {
+      (BoolFormat::TrueFalse, true) => PROP_VAL_TRUE,
+      (BoolFormat::TrueFalse, false) => PROP_VAL_FALSE,
+      (BoolFormat::YesNo, true) => PROP_VAL_YES,
+      (BoolFormat::YesNo, false) => PROP_VAL_NO,
+      (BoolFormat::OnOff, true) => PROP_VAL_ON,
+      (BoolFormat::OnOff, false) => PROP_VAL_OFF,
+      (BoolFormat::OneZero, true) => PROP_VAL_ONE,
+      (BoolFormat::OneZero, false) => PROP_VAL_ZERO,
+    }
+  }
+
+  /// Renders a number in the given format.
+  ///
+  /// Numbers keep their source token, so normalising means re-rendering anything but a 64-bit
+  /// integer as a 64-bit float.
+  pub(super) fn number_to_str(n: &Number, format: &NumberFormat) -> String {
+    match format {
+      NumberFormat::AsWritten => n.to_string(),
+      NumberFormat::Normalized => if let Some(i) = n.as_i64() {
+        i.to_string()
+      } else if let Some(u) = n.as_u64() {
+        u.to_string()
+      } else {
+        n.as_f64().and_then(Number::from_f64).unwrap_or_else(|| n.clone()).to_string()
+      },
+    }
+  }
+
+  #[derive(PartialEq, Eq)]
+  pub(super) struct PropVal {
+    escaped: String,
+    raw: String,
+    /// The JSON value the property value is rendered from.
+    value: Value,
+  }
+
+  impl PropVal {
+    pub(super) fn empty() -> Self {
+      Self::verbatim(String::new(), Value::Null)
+    }
+
+    pub(super) fn null() -> Self {
+      Self::verbatim(String::from(PROP_VAL_NULL), Value::Null)
+    }
+
+    pub(super) fn of_bool(b: bool, format: &BoolFormat) -> Self {
+      Self::verbatim(String::from(bool_to_str(b, format)), Value::Bool(b))
+    }
+
+    /// Creates the value of a number, as rendered by [number_to_str] or otherwise.
+    pub(super) fn of_num(n: &Number, rendered: String) -> Self {
+      Self::verbatim(rendered, Value::Number(n.clone()))
+    }
+
+    pub(super) fn of_string(s: String, discard_wsp: bool) -> Self {
+      let value = Value::String(s.clone());
+      // Leading whitespace must be discarded before escaping, otherwise leading line breaks and
+      // tabs would be hidden behind a backslash:
+      let raw = if discard_wsp { s.normalise(true) } else { s };
+      PropVal { escaped: escape::escape_value(&raw, false), raw, value }
+    }
+
+    /// Creates a value which needs no escaping.
+    fn verbatim(s: String, value: Value) -> Self {
+      PropVal { escaped: s.clone(), raw: s, value }
+    }
+
+    /// Replaces the JSON value the property value is rendered from, e.g. with the whole list the
+    /// members of which are joined.
+    pub(super) fn rendered_from(self, value: Value) -> Self {
+      PropVal { value, ..self }
+    }
+
+    /// Returns the length in bytes of the value as it is written, with every non-ASCII character
+    /// written as its `\uXXXX` escape sequence if `ascii` is `true`.
+    pub(super) fn written_len(&self, ascii: bool) -> usize {
+      Self::escaped_len(&self.escaped, ascii)
+    }
+
+    fn escaped_len(escaped: &str, ascii: bool) -> usize {
+      if ascii { super::unicode_escape::escape(escaped).len() } else { escaped.len() }
+    }
+
+    /// Truncates the value, so that its written form (ending with an ellipsis marker) is at most
+    /// `max_len` bytes long, see [PropVal::written_len].
+    pub(super) fn truncate(self, max_len: usize, ascii: bool) -> Self {
+      let marker = &TRUNCATION_MARKER[..TRUNCATION_MARKER.len().min(max_len)];
+      let budget = max_len - marker.len();
+      // Escaping is done character by character, so the escaped form of every prefix of the raw
+      // value is a prefix of the escaped value, and its length grows with the prefix:
+      let ends: Vec<usize> = self.raw.char_indices().map(|(i, c)| i + c.len_utf8()).collect();
+      let fitting = ends.partition_point(|end| {
+        Self::escaped_len(&escape::escape_value(&self.raw[..*end], false), ascii) <= budget
+      });
+      let end = if fitting == 0 { 0 } else { ends[fitting - 1] };
+
+      let raw = format!("{}{marker}", &self.raw[..end]);
+      PropVal { escaped: escape::escape_value(&raw, false), raw, value: self.value }
+    }
+
+    /// Returns the value as it was before escaping.
+    pub(super) fn raw(&self) -> &str {
+      &self.raw
+    }
+
+    pub(super) fn into_value(self) -> Value {
+      self.value
+    }
+
+    /// Returns the name of the type of the JSON value the property value is rendered from.
+    pub(super) fn json_type(&self) -> &'static str {
+      match self.value {
+        Value::String(_) => "string",
+        Value::Number(_) => "number",
+        Value::Bool(_) => "bool",
+        Value::Null => "null",
+        Value::Array(_) => "list",
+        Value::Object(_) => "object",
+      }
+    }
+  }
+
+  impl Display for PropVal {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+      self.escaped.fmt(f)
+    }
+  }
+}
+
+/// Expansion of `${VAR}` environment variable placeholders in string values.
+mod env_expand {
+  use crate::app_config::MissingEnvHandling;
+
+  /// Replaces every `${VAR}` placeholder with the value `lookup` returns for `VAR`, and every
+  /// escaped `$${` with a literal `${`.
+  ///
+  /// Returns the name of the first variable `lookup` returns no value for, unless missing variables
+  /// are configured to be kept or replaced with an empty string.
+  pub(super) fn expand(
+    s: &str,
+    on_missing: &MissingEnvHandling,
+    lookup: impl Fn(&str) -> Option<String>,
+  ) -> Result<String, String> {
+    let mut expanded = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find("${") {
+      if rest[..start].ends_with('$') {
+        expanded.push_str(&rest[..start - 1]);
+        expanded.push_str("${");
+        rest = &rest[start + 2..];
+        continue;
+      }
+
+      expanded.push_str(&rest[..start]);
+      let Some(len) = rest[start + 2..].find('}') else {
+        // An unterminated placeholder is kept as it is:
+        rest = &rest[start..];
+        break;
+      };
+      let name = &rest[start + 2..start + 2 + len];
+      let placeholder = &rest[start..start + 3 + len];
+      match (lookup(name), on_missing) {
+        (Some(value), _) => expanded.push_str(&value),
+        (None, MissingEnvHandling::Error) => return Err(String::from(name)),
+        (None, MissingEnvHandling::Keep) => expanded.push_str(placeholder),
+        (None, MissingEnvHandling::Empty) => {}
+      }
+      rest = &rest[start + 3 + len..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+  }
+}
+
+/// Formatting of env-file entries.
+mod env_format {
+  /// Converts a key to an environment variable name: namespace separators and every other
+  /// character not allowed in a name become `_`, and letters are uppercased.
+  ///
+  /// - `"db.url"` will be rendered as `"DB_URL"`
+  /// - `"db.pool-size"` will be rendered as `"DB_POOL_SIZE"`
+  pub(super) fn key(key: &str, separator: &str) -> String {
+    key.replace(separator, "_")
+      .chars()
+      .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+      .collect()
+  }
+
+  /// Wraps a value in double quotes if it contains whitespace, quotes, `#` or backslashes,
+  /// escaping quotes, backslashes and line breaks inside.
+  pub(super) fn value(value: &str) -> String {
+    if !value.contains(|c: char| c.is_whitespace() || matches!(c, '"' | '\'' | '#' | '\\')) {
+      return String::from(value);
+    }
+
+    let mut quoted = String::with_capacity(value.len() + 2);
+    quoted.push('"');
+    for c in value.chars() {
+      match c {
+        '"' => quoted.push_str("\\\""),
+        '\\' => quoted.push_str("\\\\"),
+        '\n' => quoted.push_str("\\n"),
+        '\r' => quoted.push_str("\\r"),
+        _ => quoted.push(c),
+      }
+    }
+    quoted.push('"');
+    quoted
+  }
+}
+
+/// Case conversion of key segments.
+mod key_case {
+  use crate::app_config::KeyCase;
+
+  /// Converts a single key segment to the given case.
+  ///
+  /// Words are delimited by `_`, `-`, whitespace, and changes from lowercase to uppercase:
+  /// - `"maxPoolSize"` will be rendered as `"max-pool-size"` in [KeyCase::Kebab]
+  /// - `"HTTPServer"` will be rendered as `"http_server"` in [KeyCase::Snake]
+  pub(super) fn convert(segment: &str, case: &KeyCase) -> String {
+    match case {
+      KeyCase::Original => String::from(segment),
+      KeyCase::Kebab => words(segment).join("-"),
+      KeyCase::Snake => words(segment).join("_"),
+      KeyCase::Lower => segment.to_lowercase(),
+      KeyCase::Upper => segment.to_uppercase(),
+    }
+  }
+
+  /// Splits a segment into lowercase words.
+  fn words(segment: &str) -> Vec<String> {
+    let chars: Vec<char> = segment.chars().collect();
+    let mut words = Vec::new();
+    let mut word = String::new();
+    for (i, &c) in chars.iter().enumerate() {
+      if c == '_' || c == '-' || c.is_whitespace() {
+        if !word.is_empty() {
+          words.push(std::mem::take(&mut word));
+        }
+        continue;
+      }
+
+      let prev = i.checked_sub(1).map(|j| chars[j]);
+      let next = chars.get(i + 1);
+      // Either "maxPool" or the last capital of an acronym in "HTTPServer":
+      let starts_word = c.is_uppercase() && !word.is_empty()
+        && (prev.is_some_and(|p| p.is_lowercase() || p.is_numeric())
+        || next.is_some_and(|n| n.is_lowercase()));
+      if starts_word {
+        words.push(std::mem::take(&mut word));
+      }
+      word.extend(c.to_lowercase());
+    }
+    if !word.is_empty() {
+      words.push(word);
+    }
+    words
+  }
+}
+
+/// Escaping of XML text and attribute values.
+mod xml_escape {
+  /// Replaces the XML markup characters with their predefined entities.
+  ///
+  /// If `ascii` is `true`, every non-ASCII character is replaced by a numeric character reference:
+  /// - `"a < b & c"` will be rendered as `"a &lt; b &amp; c"`
+  /// - `"é"` will be rendered as `"&#xE9;"` if `ascii` is `true`
+  pub(super) fn escape(s: &str, ascii: bool) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+      match c {
+        '&' => ret.push_str("&amp;"),
+        '<' => ret.push_str("&lt;"),
+        '>' => ret.push_str("&gt;"),
+        '"' => ret.push_str("&quot;"),
+        '\'' => ret.push_str("&apos;"),
+        _ if ascii && !c.is_ascii() => ret.push_str(&format!("&#x{:X};", c as u32)),
+        _ => ret.push(c),
+      }
+    }
+    ret
+  }
+}
+
+/// Percent-encoding of keys for URL compatible output.
+mod percent_encoding {
+  /// Percent-encodes every namespace segment of the key, keeping the separators between them.
+  ///
+  /// Only the unreserved characters of URIs (RFC 3986) are kept: ASCII letters, digits and `-._~`.
+  /// Every other character is written as the `%XX` escapes of its UTF-8 bytes, e.g. `"a b:é"` will
+  /// be rendered as `"a%20b%3A%C3%A9"`.
+  pub(super) fn encode_key(key: &str, separator: &str) -> String {
+    key.split(separator).map(encode).collect::<Vec<_>>().join(separator)
+  }
+
+  fn encode(segment: &str) -> String {
+    let mut ret = String::with_capacity(segment.len());
+    for b in segment.bytes() {
+      if b.is_ascii_alphanumeric() || matches!(b, b'-' | b'.' | b'_' | b'~') {
+        ret.push(char::from(b));
+      } else {
+        ret.push_str(&format!("%{b:02X}"));
+      }
+    }
+    ret
+  }
+}
+
+/// Unicode escaping for ISO-8859-1 (Latin-1) compatible output.
+mod unicode_escape {
+  /// Replaces every non-ASCII character with its `\uXXXX` escape sequence.
+  ///
+  /// Characters outside the Basic Multilingual Plane are written as a UTF-16 surrogate pair, as
+  /// `java.util.Properties` would:
+  /// - `"é"` will be rendered as `"\u00E9"`
+  /// - `"😀"` will be rendered as `"\uD83D\uDE00"`
+  pub(super) fn escape(s: &str) -> String {
+    let mut ret = String::with_capacity(s.len());
+    for c in s.chars() {
+      if c.is_ascii() {
+        ret.push(c);
+      } else {
+        let mut buf = [0u16; 2];
+        for unit in c.encode_utf16(&mut buf) {
+          ret.push_str(&format!("\\u{unit:04X}"));
+        }
+      }
+    }
+    ret
+  }
+}
+
+/// Decoding of base64 encoded values.
+mod base64 {
+  /// Decodes the standard base64 alphabet (RFC 4648), with or without its `=` padding.
+  ///
+  /// Returns `None` if the string contains any other character, or it has an impossible length.
+  pub(super) fn decode(s: &str) -> Option<Vec<u8>> {
+    let s = s.strip_suffix("==").or_else(|| s.strip_suffix('=')).unwrap_or(s);
+    if s.len() % 4 == 1 {
+      return None;
+    }
+
+    let mut bytes = Vec::with_capacity(s.len() * 3 / 4);
+    let (mut buf, mut bits) = (0u32, 0);
+    for c in s.bytes() {
+      let sextet = match c {
+        b'A'..=b'Z' => c - b'A',
+        b'a'..=b'z' => c - b'a' + 26,
+        b'0'..=b'9' => c - b'0' + 52,
+        b'+' => 62,
+        b'/' => 63,
+        _ => return None,
+      };
+      buf = (buf << 6) | u32::from(sextet);
+      bits += 6;
+      if bits >= 8 {
+        bits -= 8;
+        bytes.push((buf >> bits) as u8);
+        buf &= (1 << bits) - 1;
+      }
+    }
+    Some(bytes)
+  }
+}
+
+/// A progress indicator redrawn in place, on a single terminal line.
+mod progress {
+  use std::cell::{Cell, RefCell};
+  use std::io::Write;
+
+  pub(super) struct Progress<W: Write> {
+    total: usize,
+    done: Cell<usize>,
+    /// The percentage last drawn, so the line is only redrawn when it changes.
+    drawn: Cell<Option<usize>>,
+    out: Option<RefCell<W>>,
+  }
+
+  impl<W: Write> Progress<W> {
+    /// Creates an indicator of `total` steps, drawing to `out` only if it is `enabled`.
+    pub(super) fn new(total: usize, enabled: bool, out: W) -> Self {
+      let out = enabled.then(|| RefCell::new(out));
+      Progress { total, done: Cell::new(0), drawn: Cell::new(None), out }
+    }
+
+    /// Marks a step as done.
+    pub(super) fn tick(&self) {
+      self.done.set(self.done.get() + 1);
+      self.draw();
+    }
+
+    /// Ends the line of the indicator, if anything was drawn.
+    pub(super) fn finish(&self) {
+      if let (Some(out), Some(_)) = (&self.out, self.drawn.get()) {
+        // Progress is best-effort, failing to draw it does not fail the conversion:
+        let _ = writeln!(out.borrow_mut());
+      }
+    }
+
+    fn draw(&self) {
+      let Some(out) = &self.out else {
+        return;
+      };
+      let (done, total) = (self.done.get(), self.total.max(1));
+      let percent = done * 100 / total;
+      if self.drawn.replace(Some(percent)) == Some(percent) {
+        return;
+      }
+      let _ = write!(out.borrow_mut(), "\rConverting: {done}/{total} keys ({percent}%)");
+      let _ = out.borrow_mut().flush();
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::Parser;
+
+  use crate::app_config::{Config, EntryOrder};
+  use crate::props::prop_key::{natural_cmp, PropKey};
+  use crate::app_config::KeyCase;
+  use crate::app_config::MissingEnvHandling;
+  use crate::app_config::EmptyKeyHandling;
+  use crate::props::progress::Progress;
+  use crate::props::{
+    base64, env_expand, key_case, Properties, PropertyConstructionError, unicode_escape,
+  };
+  use crate::reader;
+
+  fn assert_key_has_value(prop: &Properties, key: &str, expected: &str) {
+    let k = PropKey::new(key);
+    let actual = prop.props.get(&k).unwrap_or_else(|| panic!("key {key} is present"));
+    assert_eq!(format!("{actual}"), expected);
+  }
+
+  fn keys_in_order(prop: Properties, order: &EntryOrder) -> Vec<String> {
+    prop.into_entries(order, ".").into_iter().map(|(k, _)| k.to_string()).collect()
+  }
+
+  #[test]
+  fn foo_1() {
+    let config = Config::empty();
+    let value = serde_json::json!({
+        "a" : "a value",
+        "b" : "b value",
+        "c" : false
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "a value");
+    assert_key_has_value(&prop, "b", "b value");
+    assert_key_has_value(&prop, "c", "false");
+  }
+
+  #[test]
+  fn foo_2() {
+    let config = Config::empty();
+    let value = serde_json::json!({
+      "a" : "a value",
+      "b" : {
+        "foo" : 123,
+        "bar" : "bar val",
+        "baz" : false
+      },
+      "c" : {
+        "foo" : 999
+      }
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 5);
+    assert_key_has_value(&prop, "a", "a value");
+    assert_key_has_value(&prop, "b.foo", "123");
+    assert_key_has_value(&prop, "b.bar", "bar val");
+    assert_key_has_value(&prop, "b.baz", "false");
+    assert_key_has_value(&prop, "c.foo", "999");
+  }
+
+  #[test]
+  fn nested_keys_are_joined_with_the_configured_namespace_separator() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--namespace-separator", "__"]);
+    let value = serde_json::json!({
+      "a" : {
+        "b" : {
+          "c" : "abc"
+        },
+        "d.e" : "ade"
+      }
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 2);
+    assert_key_has_value(&prop, "a__b__c", "abc");
+    assert_key_has_value(&prop, "a__d.e", "ade");
+  }
+
+  #[test]
+  fn creating_prop_key_with_a_simple_string_leaves_the_string_unchanged() {
+    let k = PropKey::new("foo");
+    assert_eq!(format!("{k}"), "foo");
+  }
+
+  #[test]
+  fn creating_prop_key_with_colon_has_the_colon_escaped() {
+    let k = PropKey::new("fo:o");
+    assert_eq!(format!("{k}"), "fo\\:o");
+  }
+
+  #[test]
+  fn creating_prop_key_with_leading_number_sign_escapes_the_first_character() {
+    let k = PropKey::new("#foo");
+    assert_eq!(format!("{k}"), "\\#foo");
+  }
+
+  #[test]
+  fn creating_prop_key_with_leading_wsp_and_number_sign_escapes_the_wsp_only() {
+    let k = PropKey::new("  #foo");
+    assert_eq!(format!("{k}"), "\\ \\ #foo");
+  }
+
+  #[test]
+  fn creating_prop_key_with_leading_exclamation_mark_escapes_the_first_character() {
+    let k = PropKey::new("!important");
+    assert_eq!(format!("{k}"), "\\!important");
+  }
+
+  #[test]
+  fn creating_prop_key_with_leading_wsp_and_exclamation_mark_escapes_the_wsp_only() {
+    let k = PropKey::new("  !important");
+    assert_eq!(format!("{k}"), "\\ \\ !important");
+  }
+
+  #[test]
+  fn control_characters_are_escaped_in_keys_and_values() {
+    let value = serde_json::json!({ "a\u{c}b\tc" : "form\u{c}feed, bell\u{7}, line\nbreak" });
+    let config = Config::parse_from(["jsonprops", "foo.json"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.escaped_keys().collect::<Vec<_>>(), [r"a\fb\tc"]);
+    assert_key_has_value(&prop, "a\u{c}b\tc", r"form\ffeed, bell\u0007, line\nbreak");
+  }
+
+  #[test]
+  fn unicode_escaping_leaves_ascii_unchanged_and_escapes_everything_else() {
+    assert_eq!(unicode_escape::escape("foo bar"), "foo bar");
+    assert_eq!(unicode_escape::escape("Grüße"), "Gr\\u00FC\\u00DFe");
+    assert_eq!(unicode_escape::escape("a😀"), "a\\uD83D\\uDE00");
+  }
+
+  #[test]
+  fn line_breaks_tabs_and_backslashes_in_values_are_escaped() {
+    let config = Config::empty();
+    let value = serde_json::json!({
+      "a" : "first\nsecond\r\nthird",
+      "b" : "C:\\foo\tbar",
+      "c" : ["x\ny", "z"]
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "first\\nsecond\\r\\nthird");
+    assert_key_has_value(&prop, "b", "C:\\\\foo\\tbar");
+    assert_key_has_value(&prop, "c.0", "x\\ny");
+  }
+
+  #[test]
+  fn leading_line_breaks_are_discarded_or_escaped_but_never_prefixed_with_a_backslash() {
+    let mut config = Config::empty();
+    let value = serde_json::json!({ "a" : "\n  foo", "b" : " \tbar" });
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "\\n  foo");
+    assert_key_has_value(&prop, "b", "\\ \\tbar");
+
+    config.discard_wsp = true;
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "foo");
+    assert_key_has_value(&prop, "b", "bar");
+  }
+
+  #[test]
+  fn values_below_the_max_depth_are_kept_as_json_strings() {
+    let config = Config::parse_from(
+      ["jsonprops", "foo.json", "--max-depth", "2", "--list-handling", "multi-prop"]);
+    let value = serde_json::json!({
+      "a" : {
+        "b" : { "c" : { "d" : 1 } },
+        "e" : [1, { "f" : "g" }],
+        "h" : true
+      },
+      "i" : [[1, 2], 3]
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 5);
+    assert_key_has_value(&prop, "a.b", r#"{"c":{"d":1}}"#);
+    assert_key_has_value(&prop, "a.e", r#"[1,{"f":"g"}]"#);
+    assert_key_has_value(&prop, "a.h", "true");
+    assert_key_has_value(&prop, "i.0", "[1,2]");
+    assert_key_has_value(&prop, "i.1", "3");
+  }
+
+  #[test]
+  fn entries_are_sorted_by_key_or_depth_or_kept_in_insertion_order() {
+    let config = Config::empty();
+    let value = serde_json::json!({
+      "a" : { "b" : 1, "c" : 2 },
+      "a-b" : 3
+    });
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(keys_in_order(prop, &EntryOrder::Sorted), ["a-b", "a.b", "a.c"]);
+
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(keys_in_order(prop, &EntryOrder::Insertion), ["a.b", "a.c", "a-b"]);
+
+    let value = serde_json::json!({ "z" : 1, "a" : { "c" : { "d" : 2 }, "b" : 3 }, "b" : 4 });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(keys_in_order(prop, &EntryOrder::Depth), ["b", "z", "a.b", "a.c.d"]);
+  }
+
+  #[test]
+  fn numbers_in_keys_are_sorted_by_their_value() {
+    let config = Config::empty();
+    let value = serde_json::json!({ "items" : [ 0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10 ], "v10" : 1 });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    let keys = keys_in_order(prop, &EntryOrder::Sorted);
+    assert_eq!(keys[..4], ["items.0", "items.1", "items.2", "items.3"]);
+    assert_eq!(keys[10..], ["items.10", "v10"]);
+
+    assert!(natural_cmp("a.2.b", "a.10").is_lt());
+    assert!(natural_cmp("a.02", "a.2").is_eq());
+    assert!(PropKey::new("a.02") < PropKey::new("a.2"));
+  }
+
+  #[test]
+  fn list_indices_are_written_in_brackets_with_bracket_array_style() {
+    let config = Config::parse_from(
+      ["jsonprops", "foo.json", "--list-handling", "multi-prop", "--array-style", "bracket"]);
+    let value = serde_json::json!({
+      "items" : [
+        { "name" : "foo" },
+        [1, [2]]
+      ]
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "items[0].name", "foo");
+    assert_key_has_value(&prop, "items[1][0]", "1");
+    assert_key_has_value(&prop, "items[1][1][0]", "2");
+  }
+
+  #[test]
+  fn lists_beyond_the_max_array_elements_are_truncated_or_rejected() {
+    let value = serde_json::json!({ "a" : [ 1, 2, 3 ], "b" : [ 1, 2 ] });
+    let config = Config::parse_from(
+      ["jsonprops", "foo.json", "-l", "multi-prop", "--max-array-elements", "2"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 5);
+    assert_key_has_value(&prop, "a.1", "2");
+    assert_key_has_value(&prop, "a.truncated", "true");
+    assert_key_has_value(&prop, "b.1", "2");
+
+    let config = Config::builder()
+      .list_handling(crate::ListHandling::MultiProp)
+      .max_array_elements(2)
+      .on_array_overflow(crate::ArrayOverflowHandling::Error)
+      .build()
+      .expect("config is valid");
+    let err = Properties::create(value, &config).err().expect("a is too long");
+    assert!(err.to_string().starts_with("a denotes a list of 3 members"), "{err}");
+
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "-l", "multi-prop", "--max-array-elements", "1",
+      "--allow-top-array"]);
+    let prop = Properties::create(serde_json::json!([ 1, 2 ]), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 2);
+    assert_key_has_value(&prop, "truncated", "true");
+  }
+
+  #[test]
+  fn every_key_is_prefixed_with_the_configured_prefix() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--prefix", "my app.db"]);
+    let value = serde_json::json!({
+      "url" : "localhost",
+      "pool" : { "size" : 10 }
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 2);
+    assert_key_has_value(&prop, "my app.db.url", "localhost");
+    assert_key_has_value(&prop, "my app.db.pool.size", "10");
+    assert_eq!(
+      prop.render(&config).expect("properties are rendered"),
+      "my\\ app.db.pool.size=10\nmy\\ app.db.url=localhost\n");
+  }
+
+  #[test]
+  fn an_empty_prefix_leaves_keys_unchanged() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--prefix", ""]);
+    let value = serde_json::json!({ "url" : "localhost" });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "url", "localhost");
+  }
+
+  #[test]
+  fn list_members_are_joined_with_the_configured_delimiter() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--list-delimiter", "; "]);
+    let value = serde_json::json!({ "a" : ["foo", 1, true, "bar; baz"] });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "foo; 1; true; bar; baz");
+  }
+
+  #[test]
+  fn list_delimiters_in_list_members_are_escaped_with_the_configured_escape_char() {
+    let config = Config::parse_from(
+      ["jsonprops", "foo.json", "--list-delimiter", ";", "--list-escape", "^"]);
+    let value = serde_json::json!({ "a" : ["foo;bar", "^baz", "qux"] });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "foo^;bar;^^baz;qux");
+  }
+
+  #[test]
+  fn null_list_members_are_handled_as_configured() {
+    let value = serde_json::json!({ "a" : [ "x", null, 1 ] });
+    for (handling, expected) in [("literal", "x,null,1"), ("empty", "x,,1"), ("omit", "x,1")] {
+      let config = Config::parse_from(["jsonprops", "foo.json", "--list-null-handling", handling]);
+      let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+      assert_key_has_value(&prop, "a", expected);
+    }
+  }
+
+  #[test]
+  fn list_members_are_sorted_if_configured() {
+    let value = serde_json::json!({ "a" : [ 10, "x", 9, 1.5, 9 ] });
+    let render = |args: &[&str]| {
+      let config = Config::parse_from([&["jsonprops", "foo.json"], args].concat());
+      Properties::create(value.clone(), &config).expect("JSON is parsed")
+    };
+    assert_key_has_value(&render(&[]), "a", "10,x,9,1.5,9");
+    assert_key_has_value(&render(&["--sort-list"]), "a", "1.5,10,9,9,x");
+    assert_key_has_value(&render(&["--sort-list-numeric", "--dedup-list"]), "a", "1.5,9,10,x");
+    let prop = render(&["--sort-list", "--list-handling", "multi-prop"]);
+    assert_key_has_value(&prop, "a.0", "10");
+  }
+
+  #[test]
+  fn boolean_strings_are_normalised_if_configured() {
+    let value = serde_json::json!({
+      "a" : "true", "b" : "False", "c" : "yes", "d" : [ "false", 1 ]
+    });
+    let render = |args: &[&str]| {
+      let args = [&["jsonprops", "foo.json", "--bool-format", "onoff"], args].concat();
+      let config = Config::parse_from(args);
+      Properties::create(value.clone(), &config).expect("JSON is parsed")
+    };
+    let prop = render(&[]);
+    assert_key_has_value(&prop, "a", "true");
+    assert_key_has_value(&prop, "b", "False");
+
+    let prop = render(&["--normalize-bool-strings"]);
+    assert_key_has_value(&prop, "a", "on");
+    assert_key_has_value(&prop, "b", "False");
+    assert_key_has_value(&prop, "c", "yes");
+    assert_key_has_value(&prop, "d", "off,1");
+
+    let prop = render(&["--normalize-bool-strings", "--bool-strings-ignore-case"]);
+    assert_key_has_value(&prop, "b", "off");
+    assert_key_has_value(&prop, "c", "yes");
+  }
+
+  #[test]
+  fn lists_of_non_primitives_are_rejected_by_default_with_single_prop_list_handling() {
+    let config = Config::parse_from(["jsonprops", "foo.json"]);
+    let value = serde_json::json!({ "a" : [1, { "b" : 2 }] });
+    let err = Properties::create(value, &config).err().expect("list is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::ComplexListError(k, _)) if k == "a"));
+  }
+
+  #[test]
+  fn lists_of_non_primitives_are_omitted_or_expanded_as_configured() {
+    let value = serde_json::json!({ "a" : [1, { "b" : 2 }], "c" : [3, 4] });
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--on-complex-list", "omit"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "c", "3,4");
+
+    let config = Config::parse_from(
+      ["jsonprops", "foo.json", "--on-complex-list", "fallback-multi"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "a.0", "1");
+    assert_key_has_value(&prop, "a.1.b", "2");
+    assert_key_has_value(&prop, "c", "3,4");
+  }
+
+  #[test]
+  fn header_comment_precedes_the_entries() {
+    let config = Config::parse_from(["jsonprops", "config.json", "--header", "-e", "colon"]);
+    let value = serde_json::json!({ "a" : "b" });
+    let rendered = Properties::create(value, &config).expect("JSON is parsed")
+      .render(&config)
+      .expect("properties are rendered");
+    let (header, entries) = rendered.split_once('\n').expect("there are multiple lines");
+    assert!(header.starts_with("# Generated from config.json at "), "header is {header}");
+    assert!(header.ends_with('Z'), "header is {header}");
+    assert_eq!(entries, "a:b\n");
+  }
+
+  #[test]
+  fn xml_output_contains_the_unescaped_keys_and_values_as_xml_entries() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--output", "xml"]);
+    let value = serde_json::json!({
+      "a b" : { "c" : " <d> & \"e\"\n" },
+      "#f" : "g"
+    });
+    let rendered = Properties::create(value, &config).expect("JSON is parsed")
+      .render(&config)
+      .expect("properties are rendered");
+    assert_eq!(rendered, concat!(
+      "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"no\"?>\n",
+      "<!DOCTYPE properties SYSTEM \"http://java.sun.com/dtd/properties.dtd\">\n",
+      "<properties>\n",
+      "<entry key=\"#f\">g</entry>\n",
+      "<entry key=\"a b.c\"> &lt;d&gt; &amp; &quot;e&quot;\n</entry>\n",
+      "</properties>\n"));
+  }
+
+  #[test]
+  fn only_included_and_not_excluded_keys_are_kept() {
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--list-handling", "multi-prop",
+      "--include", "db.**", "--include", "*.password", "--exclude", "db.pool.*",
+    ]);
+    let value = serde_json::json!({
+      "db" : { "url" : "localhost", "pool" : { "size" : 10 }, "hosts" : ["a"] },
+      "admin" : { "password" : "secret", "name" : "root" },
+      "mail" : { "smtp" : { "password" : "secret" } }
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "db.url", "localhost");
+    assert_key_has_value(&prop, "db.hosts.0", "a");
+    assert_key_has_value(&prop, "admin.password", "secret");
+  }
+
+  #[test]
+  fn only_the_value_at_the_pointer_is_converted() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--pointer", "/spring/datasource"]);
+    let value = serde_json::json!({
+      "spring" : { "datasource" : { "url" : "localhost" }, "name" : "app" }
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "url", "localhost");
+  }
+
+  #[test]
+  fn pointers_to_missing_or_non_object_values_are_rejected() {
+    let value = serde_json::json!({ "a" : { "b" : [1, 2] } });
+    let err_of = |pointer: &str| {
+      let config = Config::parse_from(["jsonprops", "foo.json", "--pointer", pointer]);
+      Properties::create(value.clone(), &config).err().expect("pointer is rejected")
+    };
+    assert!(matches!(
+      err_of("/a/c").downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::MissingPointerError(p)) if p == "/a/c"));
+    assert!(matches!(
+      err_of("/a/b").downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::TopLevelArrayError(_))));
+    assert!(matches!(
+      err_of("/a/b/0").downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::TopLevelPrimitiveError(_))));
+  }
+
+  #[test]
+  fn key_segments_are_converted_to_the_configured_case() {
+    let cases = [
+      ("maxPoolSize", "max-pool-size", "max_pool_size", "maxpoolsize", "MAXPOOLSIZE"),
+      ("HTTPServer", "http-server", "http_server", "httpserver", "HTTPSERVER"),
+      ("ipv4Address", "ipv4-address", "ipv4_address", "ipv4address", "IPV4ADDRESS"),
+      ("already_snake-kebab", "already-snake-kebab", "already_snake_kebab", "already_snake-kebab",
+        "ALREADY_SNAKE-KEBAB"),
+    ];
+    for (original, kebab, snake, lower, upper) in cases {
+      assert_eq!(key_case::convert(original, &KeyCase::Original), original);
+      assert_eq!(key_case::convert(original, &KeyCase::Kebab), kebab);
+      assert_eq!(key_case::convert(original, &KeyCase::Snake), snake);
+      assert_eq!(key_case::convert(original, &KeyCase::Lower), lower);
+      assert_eq!(key_case::convert(original, &KeyCase::Upper), upper);
+    }
+  }
+
+  #[test]
+  fn key_case_is_applied_per_segment_but_not_to_the_prefix() {
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--key-case", "kebab", "--prefix", "myApp",
+      "--list-handling", "multi-prop",
+    ]);
+    let value = serde_json::json!({
+      "dataSource" : { "maxPoolSize" : 10, "hostNames" : ["a", "b"] }
+    });
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "myApp.data-source.max-pool-size", "10");
+    assert_key_has_value(&prop, "myApp.data-source.host-names.0", "a");
+    assert_key_has_value(&prop, "myApp.data-source.host-names.1", "b");
+  }
+
+  #[test]
+  fn booleans_are_rendered_in_the_configured_format() {
+    let value = serde_json::json!({ "a" : true, "b" : false, "c" : [true, false] });
+    for (format, t, f) in [("truefalse", "true", "false"), ("yesno", "yes", "no"),
+      ("onoff", "on", "off"), ("10", "1", "0")] {
+      let config = Config::parse_from(["jsonprops", "foo.json", "--bool-format", format]);
+      let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+      assert_key_has_value(&prop, "a", t);
+      assert_key_has_value(&prop, "b", f);
+      assert_key_has_value(&prop, "c", &format!("{t},{f}"));
+    }
+  }
+
+  #[test]
+  fn nulls_are_rendered_empty_omitted_or_as_literal_as_configured() {
+    let value = serde_json::json!({ "a" : null, "b" : [1, null] });
+    let prop_of = |null_handling: &str| {
+      let config = Config::parse_from([
+        "jsonprops", "foo.json", "--null-handling", null_handling, "--list-handling", "multi-prop",
+      ]);
+      Properties::create(value.clone(), &config).expect("JSON is parsed")
+    };
+
+    let prop = prop_of("empty");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "a", "");
+    assert_key_has_value(&prop, "b.1", "");
+
+    let prop = prop_of("omit");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "b.0", "1");
+
+    let prop = prop_of("literal");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "a", "null");
+    assert_key_has_value(&prop, "b.1", "null");
+  }
+
+  #[test]
+  fn duplicate_keys_keep_the_last_or_first_value_or_are_rejected_as_configured() {
+    // Object members are visited alphabetically, "a" before "a.b":
+    let value = serde_json::json!({ "a.b" : 1, "a" : { "b" : 2 } });
+    let config_of = |on_duplicate: &str| {
+      Config::parse_from(["jsonprops", "foo.json", "--on-duplicate", on_duplicate])
+    };
+
+    let prop = Properties::create(value.clone(), &config_of("keep-last")).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "a.b", "1");
+
+    let prop = Properties::create(value.clone(), &config_of("keep-first")).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "a.b", "2");
+
+    let err = Properties::create(value, &config_of("error")).err().expect("duplicate is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::DuplicateKeyError(k)) if k == "a.b"));
+  }
+
+  #[test]
+  fn export_replaces_the_destination_without_leaving_the_temporary_file() {
+    let dir = std::env::temp_dir().join(format!("jsonprops-export-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir is created");
+    let dest = dir.join("app.properties");
+    std::fs::write(&dest, "stale=true\n").expect("destination is written");
+
+    let config = Config::builder().dest(dest.clone()).build().expect("config is valid");
+    let value = serde_json::json!({ "a" : 1 });
+    Properties::create(value, &config).and_then(|p| p.export(&config)).expect("export succeeds");
+
+    assert_eq!(std::fs::read_to_string(&dest).expect("destination is read"), "a=1\n");
+    assert_eq!(Properties::temp_path(&dest), dir.join("app.properties.tmp"));
+    assert!(!Properties::temp_path(&dest).exists());
+    std::fs::remove_dir_all(&dir).expect("temp dir is removed");
+  }
+
+  #[test]
+  fn output_dir_names_the_destination_after_the_source() {
+    let dir = std::env::temp_dir().join(format!("jsonprops-output-dir-{}", std::process::id()));
+    let source = std::env::temp_dir().join(format!("jsonprops-app-{}.json", std::process::id()));
+    std::fs::write(&source, "{}").expect("source is written");
+    let args = ["jsonprops", source.to_str().unwrap(), "--output-dir", dir.to_str().unwrap()];
+    let result = Config::parse_from(args).validate();
+    assert!(matches!(result, Err(crate::ConfigValidationError::MissingFileError(_))));
+
+    let config = Config::parse_from(args.into_iter().chain(["--mkdir", "--output", "xml"]))
+      .validate()
+      .expect("config is valid");
+    let stem = source.file_stem().unwrap();
+    assert_eq!(config.dest(), Some(dir.join(stem).with_extension("xml").as_path()));
+    assert!(dir.is_dir());
+
+    let config = Config::parse_from(["jsonprops", "-", "--output-dir", dir.to_str().unwrap()]);
+    let result = config.validate();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+    std::fs::remove_dir_all(&dir).expect("temp dir is removed");
+    std::fs::remove_file(&source).expect("source is removed");
+  }
+
+  #[test]
+  fn the_prefix_is_taken_from_the_file_stem_of_the_source() {
+    let source = std::env::temp_dir().join(format!("jsonprops db{}.json", std::process::id()));
+    std::fs::write(&source, "{}").expect("source is written");
+    let config = Config::builder()
+      .source(&source)
+      .prefix_from_filename(true)
+      .build()
+      .expect("config is valid");
+    let prop = Properties::create(serde_json::json!({ "url" : "x" }), &config).expect("JSON");
+    let key = format!("jsonprops db{}.url", std::process::id());
+    assert_key_has_value(&prop, &key, "x");
+    std::fs::remove_file(&source).expect("source is removed");
+
+    let result = Config::builder().prefix_from_filename(true).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn dry_run_export_does_not_create_the_destination() {
+    let dest = std::env::temp_dir()
+      .join(format!("jsonprops-dry-run-{}.properties", std::process::id()));
+    let config = Config::builder()
+      .dest(dest.clone())
+      .dry_run(true)
+      .build()
+      .expect("config is valid");
+    let value = serde_json::json!({ "a" : 1 });
+    Properties::create(value, &config).and_then(|p| p.export(&config)).expect("export succeeds");
+
+    assert!(!dest.exists());
   }
 
   #[test]
-  fn creating_prop_key_with_leading_wsp_and_number_sign_escapes_the_wsp_only() {
-    let k = PropKey::new("  #foo");
-    assert_eq!(format!("{k}"), "\\ \\ #foo");
+  fn dry_run_report_lists_the_keys_which_would_be_written() {
+    let config = Config::builder().dry_run(true).build().expect("config is valid");
+    let value = serde_json::json!({ "b" : 1, "a" : { "c" : 2 } });
+    let lines = Properties::create(value, &config).expect("JSON is parsed").report_lines(&config);
+    let expected = [
+      "Dry run: 2 entries would be written to standard output", "Dry run: a.c", "Dry run: b",
+    ];
+    assert_eq!(lines, expected);
+  }
+
+  #[test]
+  fn list_members_are_quoted_if_they_contain_the_delimiter_whitespace_or_the_quote() {
+    let value = serde_json::json!({ "a" : [ "x", "y,z", "hello world", "it's", "\\" ] });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--quote-style", "single"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", r"x,'y,z','hello world','it\\'s',\\");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--quote-style", "double"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", r#"x,"y,z","hello world",it's,\\"#);
+  }
+
+  #[test]
+  fn numbers_are_written_as_in_the_source_or_normalised() {
+    let value: serde_json::Value = serde_json::from_str(
+      r#"{ "big" : 9007199254740993, "huge" : 123456789012345678901234567890, "price" : 1.50,
+           "exp" : 1e10, "int" : -42 }"#).expect("JSON is valid");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--number-format", "as-written"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "big", "9007199254740993");
+    assert_key_has_value(&prop, "huge", "123456789012345678901234567890");
+    assert_key_has_value(&prop, "price", "1.50");
+    assert_key_has_value(&prop, "exp", "1e10");
+    assert_key_has_value(&prop, "int", "-42");
+
+    let config = Config::parse_from(["jsonprops", "foo.json"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "big", "9007199254740993");
+    assert_key_has_value(&prop, "huge", "1.2345678901234568e29");
+    assert_key_has_value(&prop, "price", "1.5");
+    assert_key_has_value(&prop, "exp", "10000000000.0");
+    assert_key_has_value(&prop, "int", "-42");
+  }
+
+  #[test]
+  fn entries_are_sorted_by_value_then_by_key() {
+    let value = serde_json::json!({ "d" : "beta", "c" : "alpha", "b" : "beta", "a" : "gamma" });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--sort", "value"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(keys_in_order(prop, config.order()), ["c", "b", "d", "a"]);
+  }
+
+  #[test]
+  fn map_keeps_the_json_values_of_the_flattened_keys() {
+    let value = serde_json::json!({
+      "db" : { "url" : "  jdbc:h2 ", "port" : 5432, "ssl" : true, "user" : null },
+      "hosts" : [ "a", "b" ],
+      "my key" : 1.50
+    });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--discard-wsp"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.escaped_keys().collect::<Vec<_>>(), [
+      "db.port", "db.ssl", "db.url", "db.user", "hosts", r"my\ key"
+    ]);
+
+    let map = prop.into_map();
+    assert_eq!(map["db.url"], serde_json::json!("  jdbc:h2 "));
+    assert_eq!(map["db.port"], serde_json::json!(5432));
+    assert_eq!(map["db.ssl"], serde_json::json!(true));
+    assert_eq!(map["db.user"], serde_json::Value::Null);
+    assert_eq!(map["hosts"], serde_json::json!([ "a", "b" ]));
+    assert_eq!(map["my key"], serde_json::json!(1.50));
+  }
+
+  #[test]
+  fn empty_properties_are_rejected_if_configured() {
+    let config = Config::parse_from(["jsonprops", "foo.json", "--fail-on-empty"]);
+    for value in [serde_json::json!({}), serde_json::Value::Null, serde_json::json!({ "a" : {} })] {
+      let err = Properties::create(value, &config).err().expect("empty properties are rejected");
+      assert!(matches!(
+        err.downcast_ref::<PropertyConstructionError>(),
+        Some(PropertyConstructionError::EmptyPropertiesError(source)) if source == "foo.json"));
+    }
+
+    let value = serde_json::json!({ "a" : 1 });
+    assert!(Properties::create(value, &config).is_ok());
+  }
+
+  #[test]
+  fn env_placeholders_are_expanded() {
+    let lookup = |name: &str| (name == "DB_HOST").then(|| String::from("localhost"));
+    let expand = |s: &str, on_missing| env_expand::expand(s, &on_missing, lookup);
+
+    assert_eq!(expand("jdbc://${DB_HOST}:5432", MissingEnvHandling::Error).unwrap(),
+      "jdbc://localhost:5432");
+    assert_eq!(expand("$${DB_HOST} is ${DB_HOST}", MissingEnvHandling::Error).unwrap(),
+      "${DB_HOST} is localhost");
+    assert_eq!(expand("${DB_PORT}", MissingEnvHandling::Error).unwrap_err(), "DB_PORT");
+    assert_eq!(expand("${DB_PORT}/${DB_HOST}", MissingEnvHandling::Keep).unwrap(),
+      "${DB_PORT}/localhost");
+    assert_eq!(expand("${DB_PORT}/${DB_HOST}", MissingEnvHandling::Empty).unwrap(), "/localhost");
+    assert_eq!(expand("${DB_HOST", MissingEnvHandling::Error).unwrap(), "${DB_HOST");
+  }
+
+  #[test]
+  fn missing_env_variable_is_reported_with_its_key() {
+    let value = serde_json::json!({ "db" : { "url" : "${JSONPROPS_SURELY_UNDEFINED}" } });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--expand-env"]);
+    let err = Properties::create(value, &config).err().expect("missing variable is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::MissingEnvVarError(name, k))
+        if name == "JSONPROPS_SURELY_UNDEFINED" && k == "db.url"));
+  }
+
+  #[test]
+  fn array_base_offsets_the_indices_at_every_level() {
+    let value = serde_json::json!({ "m" : [ [ "a", "b" ], [ { "c" : [ true ] } ] ] });
+    let config =
+      Config::parse_from(["jsonprops", "foo.json", "-l", "multi-prop", "--array-base", "1"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "m.1.1", "a");
+    assert_key_has_value(&prop, "m.1.2", "b");
+    assert_key_has_value(&prop, "m.2.1.c.1", "true");
+
+    let result = Config::builder().array_base(2).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::InvalidArrayBaseError(2))));
+  }
+
+  #[test]
+  fn stats_count_the_properties_depth_and_lists() {
+    let value = serde_json::json!({
+      "a" : { "b" : [ { "c" : 1 }, { "c" : 2 } ], "d" : [ 1, 2 ] },
+      "e" : [ [ 1 ], [ 2 ] ],
+      "f" : null
+    });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--on-complex-list", "omit"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    let stats = prop.stats();
+    assert_eq!((stats.properties(), stats.max_depth()), (2, 2));
+    assert_eq!((stats.expanded_lists(), stats.dropped_lists()), (0, 2));
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "-l", "multi-prop"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    let stats = prop.stats();
+    assert_eq!((stats.properties(), stats.max_depth()), (7, 4));
+    assert_eq!((stats.expanded_lists(), stats.dropped_lists()), (5, 0));
+  }
+
+  #[test]
+  fn summary_lists_the_dropped_lists_duplicates_and_coercions() {
+    let value = serde_json::json!({
+      "a" : { "b" : [ { "c" : 1 } ], "d" : "8080" },
+      "a.d" : 1,
+    });
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "out.properties", "--on-complex-list", "omit",
+      "--on-duplicate", "keep-first", "--coerce-numeric-strings"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.summary(&config), serde_json::json!({
+      "source" : "foo.json",
+      "dest" : "out.properties",
+      "properties" : 1,
+      "max_depth" : 2,
+      "expanded_lists" : 0,
+      "dropped_lists" : [ "a.b" ],
+      "duplicate_keys" : [ "a.d" ],
+      "coerced_values" : [ "/a/d" ],
+    }));
+  }
+
+  #[test]
+  fn trailing_whitespace_is_trimmed_from_values_only_if_configured() {
+    let value = serde_json::json!({ "a " : "  foo \t ", "b" : [ "x ", "y  " ] });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--trim-trailing"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a ", r"\  foo");
+    assert_key_has_value(&prop, "b", "x ,y");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--trim-trailing", "--discard-wsp"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a ", "foo");
+  }
+
+  #[test]
+  fn env_output_uppercases_keys_and_quotes_values_with_spaces() {
+    let value = serde_json::json!({
+      "db" : { "url" : "jdbc:h2:mem", "pool-size" : 10, "name" : "my \"db\"" }
+    });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--output", "env"]);
+    let env = Properties::create(value, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(env, "DB_NAME=\"my \\\"db\\\"\"\nDB_POOL_SIZE=10\nDB_URL=jdbc:h2:mem\n");
+
+    let result = Config::builder()
+      .output(crate::OutputFormat::Env)
+      .entry_separator(crate::EntrySeparator::Colon)
+      .build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn values_longer_than_the_maximum_once_escaped_are_truncated_or_rejected() {
+    let value = serde_json::json!({
+      "a" : "short", "b" : "line\nbreaks\nhere", "c" : "éééééé"
+    });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--max-value-length", "10",
+      "--on-oversize", "truncate"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "short");
+    assert_key_has_value(&prop, "b", r"line\nb...");
+    assert_key_has_value(&prop, "c", "ééé...");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--max-value-length", "9",
+      "--on-oversize", "truncate", "--ascii"]);
+    let accented = serde_json::json!({ "a" : "ééééééééé" });
+    let props = Properties::create(accented, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(props, "a=\\u00E9...\n");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--max-value-length", "10"]);
+    let err = Properties::create(value, &config).err().expect("long value is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::ValueTooLongError(k, 18)) if k == "b"));
+  }
+
+  #[test]
+  fn spaced_separators_keep_the_leading_whitespace_of_values_escaped() {
+    let value = serde_json::json!({ "a" : "1", "b" : "  indented" });
+    let config = Config::parse_from(["jsonprops", "foo.json", "-e", "equals-spaced"]);
+    let props = Properties::create(value.clone(), &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(props, "a = 1\nb = \\  indented\n");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "-e", "colon-spaced", "--verify"]);
+    let props = Properties::create(value, &config)
+      .and_then(|p| p.render_verified(&config))
+      .expect("JSON is rendered");
+    assert_eq!(props, "a : 1\nb : \\  indented\n");
+  }
+
+  #[test]
+  fn padded_separators_keep_the_leading_whitespace_of_values_escaped() {
+    let value = serde_json::json!({ "a" : "1", "b" : " indented" });
+    let config = Config::parse_from(["jsonprops", "foo.json", "-e", "colon", "--pad-separator"]);
+    let props = Properties::create(value, &config)
+      .and_then(|p| p.render_verified(&config))
+      .expect("JSON is rendered");
+    assert_eq!(props, "a : 1\nb : \\ indented\n");
+
+    let result = Config::builder()
+      .entry_separator(crate::EntrySeparator::EqualsSpaced)
+      .pad_separator(true)
+      .build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn verified_rendering_reads_back_every_key_and_value() {
+    let value = serde_json::json!({
+      "#a b" : "  lead\ttab\\", "c:d=e" : "ü", "f" : { "g" : [ "x", "y" ], "h" : null }
+    });
+    for args in [vec![], vec!["--ascii"], vec!["-e", "space", "--discard-wsp"], vec!["--header"]] {
+      let config = Config::parse_from(["jsonprops", "foo.json"].into_iter().chain(args));
+      let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+      let expected = Properties::create(value.clone(), &config)
+        .and_then(|p| p.render(&config))
+        .expect("JSON is rendered");
+      assert_eq!(prop.render_verified(&config).expect("properties read back"), expected);
+    }
+  }
+
+  #[test]
+  fn progress_is_redrawn_only_when_the_percentage_changes() {
+    let mut out = Vec::new();
+    let progress = Progress::new(200, true, &mut out);
+    (0..3).for_each(|_| progress.tick());
+    progress.finish();
+    assert_eq!(String::from_utf8(out).unwrap(),
+      "\rConverting: 1/200 keys (0%)\rConverting: 2/200 keys (1%)\n");
+
+    let mut out = Vec::new();
+    let progress = Progress::new(2, false, &mut out);
+    progress.tick();
+    progress.finish();
+    assert!(out.is_empty());
+  }
+
+  #[test]
+  fn lists_are_counted_instead_of_or_alongside_their_members() {
+    let value = serde_json::json!({ "items" : [ "a", "b", [ 1, 2, 3 ] ], "empty" : [] });
+    let config = Config::parse_from(["jsonprops", "foo.json", "-l", "count"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 2);
+    assert_key_has_value(&prop, "items.count", "3");
+    assert_key_has_value(&prop, "empty.count", "0");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "-l", "multi-prop", "--count-lists"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "items.count", "3");
+    assert_key_has_value(&prop, "items.0", "a");
+    assert_key_has_value(&prop, "items.2.count", "3");
+    assert_key_has_value(&prop, "items.2.2", "3");
+    assert_key_has_value(&prop, "empty.count", "0");
+  }
+
+  #[test]
+  fn key_manifest_lists_the_unescaped_keys_in_sorted_order() {
+    let value = serde_json::json!({ "b" : { "c d" : 1, "a=b" : 2 }, "#a" : "secret" });
+    let config = Config::parse_from(["jsonprops", "foo.json"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.key_manifest(&config), "#a\nb.a=b\nb.c d\n");
+
+    let items: Vec<String> = (0..11).map(|i| format!("item{i}")).collect();
+    let value = serde_json::json!({ "a" : items, "item10" : 1, "item9" : 2 });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--list-handling", "multi-prop"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    let expected: String = (0..11).map(|i| format!("a.{i}\n")).collect();
+    let expected = format!("{expected}item9\nitem10\n");
+    assert_eq!(prop.key_manifest(&config), expected);
+  }
+
+  #[test]
+  fn values_of_matching_keys_are_redacted_but_kept() {
+    let value = serde_json::json!({
+      "db" : { "url" : "jdbc:h2:mem", "password" : "hunter2", "admin" : { "secret_key" : 42 } }
+    });
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--redact", "**password*", "--redact", "**secret*",
+    ]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "db.url", "jdbc:h2:mem");
+    assert_key_has_value(&prop, "db.password", "****");
+    assert_key_has_value(&prop, "db.admin.secret_key", "****");
+
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--redact", "db.password", "--redact-placeholder", " <hidden>",
+    ]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "db.password", "\\ <hidden>");
+  }
+
+  #[test]
+  fn key_diff_lists_added_removed_and_changed_keys() {
+    let existing = reader::read("a=1\nb=2\nc=3\n");
+    let rendered = reader::read("a=1\nc=4\nd=5\n");
+    assert_eq!(Properties::key_diff(&existing, &rendered), ["+ d", "- b", "~ c"]);
+  }
+
+  #[test]
+  fn only_the_entries_differing_from_the_old_file_are_exported() {
+    let dir = std::env::temp_dir().join(format!("jsonprops-diff-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir is created");
+    let (old, dest) = (dir.join("old.properties"), dir.join("diff.properties"));
+    std::fs::write(&old, "a=1\nb=2\nc\\ d=3\n").expect("old file is written");
+
+    let config = Config::builder().diff_against(&old).dest(&dest).build().expect("config is valid");
+    let value = serde_json::json!({ "a" : 1, "c d" : "x y", "e" : 5 });
+    Properties::create(value, &config).and_then(|p| p.export(&config)).expect("export succeeds");
+    let diff = std::fs::read_to_string(&dest).expect("diff is written");
+    assert_eq!(diff, "+ e=5\n- b=2\n~ c\\ d=x y\n");
+
+    let config = Config::builder()
+      .diff_against(&old)
+      .dest(&dest)
+      .no_key_escape(true)
+      .pad_separator(true)
+      .build()
+      .expect("config is valid");
+    let value = serde_json::json!({ "a" : 1, "b" : 2, "c d" : 3, "e f" : { "g" : 4 } });
+    Properties::create(value, &config).and_then(|p| p.export(&config)).expect("export succeeds");
+    let diff = std::fs::read_to_string(&dest).expect("diff is written");
+    assert_eq!(diff, "+ e f.g = 4\n");
+
+    let result = Config::builder()
+      .diff_against(&old)
+      .key_encoding(crate::KeyEncoding::Percent)
+      .build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+    std::fs::remove_dir_all(&dir).expect("temp dir is removed");
+  }
+
+  #[test]
+  fn properties_are_appended_to_the_existing_destination() {
+    let dir = std::env::temp_dir().join(format!("jsonprops-append-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir is created");
+    let dest = dir.join("app.properties");
+    let config = Config::builder().append(true).dest(&dest).build().expect("config is valid");
+
+    let export = |value| Properties::create(value, &config).and_then(|p| p.export(&config));
+    export(serde_json::json!({ "a" : 1 })).expect("export to a new file succeeds");
+    std::fs::write(&dest, "# first\na=1").expect("file is extended");
+    export(serde_json::json!({ "a" : 2, "b" : 3 })).expect("export succeeds");
+    let appended = std::fs::read_to_string(&dest).expect("file is read");
+    assert_eq!(appended, "# first\na=1\n\na=2\nb=3\n");
+    std::fs::remove_dir_all(&dir).expect("temp dir is removed");
+
+    let result = Config::builder().append(true).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn lines_end_with_the_configured_line_ending() {
+    let value = serde_json::json!({ "a" : "1", "b" : "x\ny" });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--line-ending", "crlf"]);
+    let props = Properties::create(value, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(props, "a=1\r\nb=x\\ny\r\n");
+
+    let config =
+      Config::parse_from(["jsonprops", "foo.json", "--output", "xml", "--line-ending", "crlf"]);
+    let xml = Properties::create(serde_json::json!({ "a" : "1" }), &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(xml.matches("\r\n").count(), 5);
+    assert_eq!(xml.matches('\n').count(), 5);
+  }
+
+  #[test]
+  fn embedded_json_is_flattened_only_if_enabled() {
+    let value = serde_json::json!({
+      "extra" : "{\"a\":1,\"b\":\"{\\\"c\\\":[1,2]}\"}", "port" : "8080", "broken" : "{ a"
+    });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--parse-embedded-json"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "extra.a", "1");
+    assert_key_has_value(&prop, "extra.b.c", "1,2");
+    assert_key_has_value(&prop, "port", "8080");
+    assert_key_has_value(&prop, "broken", "{ a");
+
+    let config = Config::parse_from(["jsonprops", "foo.json"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+  }
+
+  #[test]
+  fn empty_objects_are_omitted_or_recorded_as_configured() {
+    let value = serde_json::json!({ "a" : {}, "b" : { "c" : {} }, "d" : 1 });
+    let prop_of = |empty_object: &str| {
+      let config = Config::parse_from(["jsonprops", "foo.json", "--empty-object", empty_object]);
+      Properties::create(value.clone(), &config).expect("JSON is parsed")
+    };
+
+    assert_eq!(prop_of("omit").props.len(), 1);
+
+    let prop = prop_of("key-only");
+    assert_key_has_value(&prop, "a", "");
+    assert_key_has_value(&prop, "b.c", "");
+
+    let prop = prop_of("marker");
+    assert_key_has_value(&prop, "a.empty", "true");
+    assert_key_has_value(&prop, "b.c.empty", "true");
+    assert_eq!(prop.props.len(), 3);
+  }
+
+  #[test]
+  fn base64_is_decoded_with_or_without_padding() {
+    assert_eq!(base64::decode("aGVsbG8=").unwrap(), b"hello");
+    assert_eq!(base64::decode("aGVsbG8").unwrap(), b"hello");
+    assert_eq!(base64::decode("w6k+Lw==").unwrap(), "é>/".as_bytes());
+    assert_eq!(base64::decode("").unwrap(), b"");
+    assert!(base64::decode("aGVsb").is_none());
+    assert!(base64::decode("aGV*bG8=").is_none());
+  }
+
+  #[test]
+  fn values_of_matching_keys_are_base64_decoded() {
+    let value = serde_json::json!({
+      "db" : { "password" : "aHVudGVyMg==", "url" : "aHVudGVyMg==" }, "key" : "/w=="
+    });
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--base64-decode", "*.password", "--base64-decode", "key",
+      "--on-invalid-utf8", "hex",
+    ]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "db.password", "hunter2");
+    assert_key_has_value(&prop, "db.url", "aHVudGVyMg==");
+    assert_key_has_value(&prop, "key", "ff");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--base64-decode", "key"]);
+    let err = Properties::create(value, &config).err().expect("binary value is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::InvalidUtf8Error(k)) if k == "key"));
+  }
+
+  #[test]
+  fn values_of_matching_keys_are_formatted() {
+    let value = serde_json::json!({
+      "job" : { "created" : "2024-01-02T03:04:05Z", "started" : 1704164645006_u64 },
+      "env" : " prod ", "tags" : [ "a", "b" ]
+    });
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--list-handling", "multi-prop",
+      "--format-path", "job.created=epoch", "--format-path", "job.*=iso-date",
+      "--format-path", "env=trim", "--format-path", "tags.*=upper",
+    ]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "job.created", "1704164645000");
+    assert_key_has_value(&prop, "job.started", "2024-01-02T03:04:05.006Z");
+    assert_key_has_value(&prop, "env", "prod");
+    assert_key_has_value(&prop, "tags.1", "B");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--format-path", "env=epoch"]);
+    let err = Properties::create(value, &config).err().expect("invalid timestamp is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::FormatError(k, _)) if k == "env"));
+    assert!(matches!(
+      Config::builder().format_path("env=camel"),
+      Err(crate::ConfigValidationError::InvalidFormatPathError(_))));
+  }
+
+  #[test]
+  fn keys_not_matching_the_key_pattern_are_rejected_with_their_source() {
+    let value = serde_json::json!({
+      "db" : { "url" : "localhost", "user name" : "sa" }, "hosts" : [ { "a/b" : 1 } ]
+    });
+    let args = ["jsonprops", "foo.json", "--list-handling", "multi-prop", "--validate-keys"];
+    let config = Config::parse_from(args);
+    assert!(Properties::create(value.clone(), &config).is_ok());
+
+    let config = Config::parse_from([&args[..], &["--key-pattern", "[A-Za-z0-9._-]+"]].concat());
+    let err = Properties::create(value, &config).err().expect("invalid keys are rejected");
+    let Some(PropertyConstructionError::InvalidKeyError(keys)) = err.downcast_ref() else {
+      panic!("unexpected error: {err}");
+    };
+    assert_eq!(keys, &[
+      (String::from("db.user name"), String::from("/db/user name")),
+      (String::from("hosts.0.a/b"), String::from("/hosts/0/a~1b")),
+    ]);
+    assert!(Config::builder().validate_keys(true).key_pattern("(a").build().is_err());
+  }
+
+  #[test]
+  fn top_level_arrays_are_converted_if_allowed() {
+    let value = serde_json::json!([ { "name" : "a" }, { "name" : "b", "tags" : [ 1 ] } ]);
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--allow-top-array", "--list-handling", "multi-prop",
+    ]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 3);
+    assert_key_has_value(&prop, "0.name", "a");
+    assert_key_has_value(&prop, "1.tags.0", "1");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--allow-top-array"]);
+    let err = Properties::create(value, &config).err().expect("array is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::TopLevelArrayError(_))));
+
+    let value = serde_json::json!([ "x", "y" ]);
+    let config = Config::parse_from(["jsonprops", "foo.json", "--allow-top-array", "-p", "hosts"]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "hosts", "x,y");
+  }
+
+  #[test]
+  fn entries_are_handed_to_the_callback_as_they_are_flattened() {
+    let value = serde_json::json!({ "a" : [ 1, 2 ], "z" : { "password" : "s3cr3t", "url" : "x" } });
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--list-handling", "multi-prop", "--redact", "*.password",
+      "--exclude", "a.1",
+    ]);
+    let mut entries = Vec::new();
+    Properties::for_each_entry(value, &config, |k, v| entries.push((k.to_owned(), v.to_owned())))
+      .expect("JSON is converted");
+    let expected = [("a.0", "1"), ("z.password", "****"), ("z.url", "x")];
+    assert_eq!(entries, expected.map(|(k, v)| (String::from(k), String::from(v))));
+
+    let result = Properties::for_each_entry(serde_json::json!(1), &config, |_, _| {});
+    assert!(result.is_err());
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn values_are_transformed_by_the_command_and_failures_name_the_key() {
+    let value = serde_json::json!({ "db" : { "user" : "admin", "port" : 5432 } });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--transform-cmd", "rev"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "db.user", "nimda");
+    assert_key_has_value(&prop, "db.port", "2345");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--transform-cmd", "false"]);
+    let err = Properties::create(value, &config).err().expect("transform fails");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::TransformError(k, _)) if k == "db.port"));
+
+    let result = Config::builder().transform_cmd("rev 'x").build();
+    assert!(matches!(
+      result,
+      Err(crate::ConfigValidationError::InvalidTransformCommandError(_))));
+  }
+
+  #[test]
+  fn keys_are_written_unescaped_if_requested() {
+    let value = serde_json::json!({ "a=b" : { "c:d e" : 1 } });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--no-key-escape"]);
+    let rendered = Properties::create(value.clone(), &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(rendered, "a=b.c:d e=1\n");
+
+    let result = Config::builder().no_key_escape(true).verify(true).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn only_the_configured_spaces_of_keys_are_escaped() {
+    let value = serde_json::json!({ "  my key" : { "a b" : 1 }, " #x y" : 2 });
+    let render = |spaces: &str| {
+      let config = Config::parse_from(["jsonprops", "foo.json", "--space-escape", spaces]);
+      Properties::create(value.clone(), &config)
+        .and_then(|p| p.render(&config))
+        .expect("JSON is rendered")
+    };
+    assert_eq!(render("all"), "\\ #x\\ y=2\n\\ \\ my\\ key.a\\ b=1\n");
+    assert_eq!(render("leading"), "\\ #x y=2\n\\ \\ my key.a b=1\n");
+    assert_eq!(render("none"), " \\#x y=2\n  my key.a b=1\n");
+
+    let result = Config::builder().space_escape(crate::SpaceEscape::None).verify(true).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn every_hash_of_keys_is_escaped_if_configured() {
+    let value = serde_json::json!({ "a#b" : 1, "#c" : { "d#" : 2 } });
+    let render = |args: &[&str]| {
+      let config = Config::parse_from([&["jsonprops", "foo.json"], args].concat());
+      Properties::create(value.clone(), &config)
+        .and_then(|p| p.render(&config))
+        .expect("JSON is rendered")
+    };
+    assert_eq!(render(&[]), "\\#c.d#=2\na#b=1\n");
+    assert_eq!(render(&["--escape-all-hashes"]), "\\#c.d\\#=2\na\\#b=1\n");
+  }
+
+  #[test]
+  fn non_finite_numbers_are_rejected_skipped_or_replaced() {
+    let value: serde_json::Value =
+      serde_json::from_str(r#"{ "a" : 1e400, "b" : [ 1, -1e400 ], "c" : 2 }"#).expect("JSON");
+    let config = Config::parse_from(["jsonprops", "foo.json"]);
+    let err = Properties::create(value.clone(), &config).err().expect("number is rejected");
+    assert!(matches!(
+      err.downcast_ref::<PropertyConstructionError>(),
+      Some(PropertyConstructionError::NonFiniteNumberError(k, n)) if k == "a" && n == "1e400"));
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--on-nonfinite", "skip"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 2);
+    assert_key_has_value(&prop, "b", "1");
+
+    let config = Config::parse_from(["jsonprops", "foo.json", "--on-nonfinite", "placeholder"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "Infinity");
+    assert_key_has_value(&prop, "b", "1,-Infinity");
+
+    let config = Config::builder()
+      .on_nonfinite(crate::NonFiniteHandling::Placeholder)
+      .nonfinite_placeholder("n/a")
+      .build()
+      .expect("config is valid");
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "n/a");
+  }
+
+  #[test]
+  fn entries_are_preceded_by_the_json_type_they_are_converted_from() {
+    let value = serde_json::json!({ "a" : "x", "b" : 1, "c" : true, "d" : null, "e" : [ 1, 2 ] });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--annotate-types"]);
+    let rendered = Properties::create(value, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(
+      rendered,
+      "# type: string\na=x\n# type: number\nb=1\n# type: bool\nc=true\n# type: null\nd=\n\
+      # type: list\ne=1,2\n");
+    assert_eq!(reader::read(&rendered).len(), 5);
+  }
+
+  #[test]
+  fn key_segments_are_substituted_in_order() {
+    let value = serde_json::json!({ "user@host" : { "maxPool" : 1, "v2" : 2 } });
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "-k", "kebab", "--key-replace", "@=_at_",
+      "--key-replace", r"^(\w+)-(\w+)$=$2-$1", "--key-replace", "_at_=-at-",
+    ]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "user-at-host.pool-max", "1");
+    assert_key_has_value(&prop, "user-at-host.v2", "2");
+
+    let result = Config::builder().key_replace("(=x");
+    assert!(matches!(result, Err(crate::ConfigValidationError::InvalidKeyReplacementError(_))));
+  }
+
+  #[test]
+  fn keys_or_values_are_written_alone_in_entry_order() {
+    let value = serde_json::json!({ "b c" : "x=y", "a" : "é\n" });
+    let keys = Config::parse_from(["jsonprops", "foo.json", "--keys-only", "--ascii"]);
+    let rendered = Properties::create(value.clone(), &keys)
+      .and_then(|p| p.render(&keys))
+      .expect("JSON is rendered");
+    assert_eq!(rendered, "a\nb\\ c\n");
+
+    let values = Config::parse_from(["jsonprops", "foo.json", "--values-only", "--ascii"]);
+    let rendered = Properties::create(value, &values)
+      .and_then(|p| p.render(&values))
+      .expect("JSON is rendered");
+    assert_eq!(rendered, "\\u00E9\\n\nx=y\n");
+
+    let result = Config::builder().keys_only(true).values_only(true).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn repeated_list_members_are_dropped_if_requested() {
+    let value = serde_json::json!({ "a" : [ 1, "2", 1, true, 2, "1", true ], "b" : [ 1, 1 ] });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--dedup-list"]);
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "1,2,true");
+    assert_key_has_value(&prop, "b", "1");
+
+    let config = Config::parse_from([
+      "jsonprops", "foo.json", "--dedup-list", "--list-handling", "multi-prop",
+    ]);
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "b.1", "1");
+  }
+
+  #[test]
+  fn properties_are_written_to_any_writer_as_they_are_exported() {
+    let value = serde_json::json!({ "b" : 1, "a" : [ "x", "y" ] });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--verify", "--line-ending", "crlf"]);
+    let mut buf = Vec::new();
+    Properties::create(value, &config)
+      .and_then(|p| p.write_to(&mut buf, &config))
+      .expect("properties are written");
+    assert_eq!(String::from_utf8(buf).expect("output is UTF-8"), "a=x,y\r\nb=1\r\n");
+  }
+
+  #[test]
+  fn key_segments_are_percent_encoded_if_requested() {
+    let value = serde_json::json!({ "a b" : { "c:d" : "x y", "é%" : 1 } });
+    let config = Config::parse_from(["jsonprops", "foo.json", "--key-encoding", "percent"]);
+    let rendered = Properties::create(value, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(rendered, "a%20b.c%3Ad=x y\na%20b.%C3%A9%25=1\n");
+
+    let result = Config::builder()
+      .key_encoding(crate::KeyEncoding::Percent)
+      .output(crate::OutputFormat::Xml)
+      .build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn whole_floats_are_written_as_integers_or_integers_as_decimals() {
+    let value: serde_json::Value = serde_json::from_str(
+      r#"{ "a" : 1.0, "b" : 1.5, "c" : 1e3, "d" : 123456789012345678901234, "e" : 7 }"#)
+      .expect("JSON is parsed");
+    let integers = Config::parse_from(["jsonprops", "foo.json", "--integer-floats"]);
+    let prop = Properties::create(value.clone(), &integers).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "1");
+    assert_key_has_value(&prop, "b", "1.5");
+    assert_key_has_value(&prop, "c", "1000");
+    assert_key_has_value(&prop, "d", "1.2345678901234569e23");
+    assert_key_has_value(&prop, "e", "7");
+
+    let decimals = Config::parse_from([
+      "jsonprops", "foo.json", "--force-decimal", "--number-format", "as-written",
+    ]);
+    let prop = Properties::create(value, &decimals).expect("JSON is parsed");
+    assert_key_has_value(&prop, "a", "1.0");
+    assert_key_has_value(&prop, "b", "1.5");
+    assert_key_has_value(&prop, "c", "1e3");
+    assert_key_has_value(&prop, "d", "123456789012345678901234.0");
+    assert_key_has_value(&prop, "e", "7.0");
+
+    let result = Config::builder().integer_floats(true).force_decimal(true).build();
+    assert!(matches!(result, Err(crate::ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+
+  #[test]
+  fn comment_fields_are_written_above_the_first_entry_of_their_object() {
+    let config = Config::parse_from(
+      ["jsonprops", "foo.json", "--comment-field", "description", "--order", "insertion"]);
+    let value = serde_json::json!({
+      "description" : "The application",
+      "server" : { "description" : "The HTTP server\nof the app", "port" : 8080, "host" : "h" },
+      "db" : { "description" : 3, "url" : "u" },
+    });
+    let rendered = Properties::create(value, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(
+      rendered,
+      "# The application\ndb.description=3\ndb.url=u\n# The HTTP server\n# of the app\n\
+      server.host=h\nserver.port=8080\n");
+
+    let config = Config::builder()
+      .comment_field("description")
+      .output(crate::OutputFormat::Env)
+      .include("b.*")
+      .build()
+      .expect("config is valid");
+    let value = serde_json::json!({
+      "a" : { "description" : "A", "x" : 1 },
+      "b" : { "description" : "B", "y" : 2 },
+    });
+    let rendered = Properties::create(value, &config)
+      .and_then(|p| p.render(&config))
+      .expect("JSON is rendered");
+    assert_eq!(rendered, "# B\nB_Y=2\n");
+  }
+
+  #[test]
+  fn split_top_level_writes_a_file_per_top_level_key() {
+    let dir = std::env::temp_dir().join(format!("jsonprops-split-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir is created");
+    let config = Config::builder()
+      .output_dir(&dir)
+      .split_top_level(true)
+      .comment_field("description")
+      .build()
+      .expect("config is valid");
+    let value = serde_json::json!({
+      "name" : "app",
+      "server" : { "description" : "HTTP", "port" : 8080, "tls" : { "enabled" : true } },
+      "db" : { "url" : "u" },
+    });
+    Properties::create(value, &config).and_then(|p| p.export(&config)).expect("export succeeds");
+
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).expect("part is written");
+    assert_eq!(read("root.properties"), "name=app\n");
+    assert_eq!(read("server.properties"), "# HTTP\nport=8080\ntls.enabled=true\n");
+    assert_eq!(read("db.properties"), "url=u\n");
+
+    let value = serde_json::json!({ "root" : { "a" : 1 }, "b" : 2 });
+    let err = Properties::create(value, &config).and_then(|p| p.export(&config)).unwrap_err();
+    assert!(err.to_string().starts_with("Top-level key [ root ]"), "{err}");
+    std::fs::remove_dir_all(&dir).expect("temp dir is removed");
+  }
+
+  #[test]
+  fn split_top_level_keeps_top_level_keys_containing_the_separator_apart() {
+    let dir = std::env::temp_dir().join(format!("jsonprops-split-sep-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("temp dir is created");
+    let config = Config::builder()
+      .output_dir(&dir)
+      .split_top_level(true)
+      .build()
+      .expect("config is valid");
+    let value = serde_json::json!({ "a.b" : { "c" : 1 }, "a" : { "d" : 2 } });
+    Properties::create(value, &config).and_then(|p| p.export(&config)).expect("export succeeds");
+
+    let read = |name: &str| std::fs::read_to_string(dir.join(name)).expect("part is written");
+    assert_eq!(read("a.b.properties"), "c=1\n");
+    assert_eq!(read("a.properties"), "d=2\n");
+    std::fs::remove_dir_all(&dir).expect("temp dir is removed");
+  }
+
+  #[test]
+  fn empty_keys_are_kept_rejected_skipped_or_replaced_as_configured() {
+    let value = serde_json::json!({ "" : 1, "a" : { "" : 2, "b" : 3 } });
+    let prop = Properties::create(value.clone(), &Config::empty()).expect("JSON is parsed");
+    assert_key_has_value(&prop, "", "1");
+    assert_key_has_value(&prop, "a.", "2");
+
+    let config = Config::builder()
+      .on_empty_key(EmptyKeyHandling::Error)
+      .build()
+      .expect("config is valid");
+    let err = Properties::create(value.clone(), &config).err().expect("empty key is rejected");
+    assert!(err.to_string().starts_with("A member of the root object has an empty key"), "{err}");
+    let nested = serde_json::json!({ "a" : { "" : 2 } });
+    let err = Properties::create(nested, &config).err().expect("empty key is rejected");
+    assert!(err.to_string().starts_with("A member of a has an empty key"), "{err}");
+
+    let config = Config::builder()
+      .on_empty_key(EmptyKeyHandling::Skip)
+      .build()
+      .expect("config is valid");
+    let prop = Properties::create(value.clone(), &config).expect("JSON is parsed");
+    assert_eq!(prop.props.len(), 1);
+    assert_key_has_value(&prop, "a.b", "3");
+
+    let config = Config::builder()
+      .on_empty_key(EmptyKeyHandling::Placeholder)
+      .build()
+      .expect("config is valid");
+    let prop = Properties::create(value, &config).expect("JSON is parsed");
+    assert_key_has_value(&prop, "_empty_", "1");
+    assert_key_has_value(&prop, "a._empty_", "2");
   }
 }
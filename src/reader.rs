@@ -0,0 +1,155 @@
+//! A reader of `.properties` files, following the rules of `java.util.Properties.load`.
+
+/// Reads the entries of a `.properties` document, as unescaped `(key, value)` pairs in the order
+/// they appear.
+///
+/// Lines end at `\n`, `\r\n` or a lone `\r`. Comment lines (starting with `#` or `!`) and blank
+/// lines are skipped, a line ending with an odd number of backslashes continues on the next one,
+/// and keys end at the first unescaped `=`, `:` or whitespace, whichever separator the file was
+/// written with.
+pub(crate) fn read(text: &str) -> Vec<(String, String)> {
+  logical_lines(text).iter().map(|line| split_entry(line)).collect()
+}
+
+/// Joins the continued lines, dropping comments, blank lines and the leading whitespace of every
+/// line.
+fn logical_lines(text: &str) -> Vec<String> {
+  let mut lines = Vec::new();
+  let mut current: Option<String> = None;
+  let text = text.replace("\r\n", "\n").replace('\r', "\n");
+  for line in text.lines() {
+    let line = line.trim_start_matches([' ', '\t', '\u{c}']);
+    if current.is_none() && (line.is_empty() || line.starts_with(['#', '!'])) {
+      continue;
+    }
+
+    let trailing_backslashes = line.chars().rev().take_while(|c| *c == '\\').count();
+    let continues = trailing_backslashes % 2 == 1;
+    let content = if continues { &line[..line.len() - 1] } else { line };
+    let logical = current.get_or_insert_with(String::new);
+    logical.push_str(content);
+    if !continues {
+      lines.extend(current.take());
+    }
+  }
+  lines.extend(current);
+  lines
+}
+
+fn split_entry(line: &str) -> (String, String) {
+  let chars: Vec<char> = line.chars().collect();
+  let mut key_end = chars.len();
+  let mut i = 0;
+  while i < chars.len() {
+    match chars[i] {
+      '\\' => i += 1,
+      '=' | ':' | ' ' | '\t' | '\u{c}' => {
+        key_end = i;
+        break;
+      }
+      _ => {}
+    }
+    i += 1;
+  }
+
+  // The separator is any whitespace, optionally followed by a single '=' or ':', and whitespace:
+  let mut value_start = key_end;
+  while value_start < chars.len() && matches!(chars[value_start], ' ' | '\t' | '\u{c}') {
+    value_start += 1;
+  }
+  if value_start < chars.len() && matches!(chars[value_start], '=' | ':') {
+    value_start += 1;
+  }
+  while value_start < chars.len() && matches!(chars[value_start], ' ' | '\t' | '\u{c}') {
+    value_start += 1;
+  }
+
+  let key = unescape(&chars[..key_end.min(chars.len())]);
+  let value = unescape(&chars[value_start.min(chars.len())..]);
+  (key, value)
+}
+
+fn unescape(chars: &[char]) -> String {
+  let mut s = String::with_capacity(chars.len());
+  let mut i = 0;
+  while i < chars.len() {
+    let c = chars[i];
+    i += 1;
+    if c != '\\' || i == chars.len() {
+      s.push(c);
+      continue;
+    }
+
+    let escaped = chars[i];
+    i += 1;
+    match escaped {
+      't' => s.push('\t'),
+      'n' => s.push('\n'),
+      'r' => s.push('\r'),
+      'f' => s.push('\u{c}'),
+      'u' => {
+        let Some(unit) = hex_unit(chars, i) else {
+          s.push(escaped);
+          continue;
+        };
+        i += 4;
+        // A high surrogate is joined with the escaped low surrogate following it:
+        let low = (chars.get(i..i + 2) == Some(&['\\', 'u']))
+          .then(|| hex_unit(chars, i + 2))
+          .flatten()
+          .filter(|low| (0xD800..0xDC00).contains(&unit) && (0xDC00..0xE000).contains(low));
+        let code = match low {
+          Some(low) => {
+            i += 6;
+            0x10000 + ((u32::from(unit) - 0xD800) << 10) + (u32::from(low) - 0xDC00)
+          }
+          None => u32::from(unit),
+        };
+        s.push(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER));
+      }
+      _ => s.push(escaped),
+    }
+  }
+  s
+}
+
+/// Reads the 4 hexadecimal digits of a `\uXXXX` escape starting at `at`.
+fn hex_unit(chars: &[char], at: usize) -> Option<u16> {
+  let hex: String = chars.get(at..at + 4)?.iter().collect();
+  u16::from_str_radix(&hex, 16).ok()
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::reader::read;
+
+  fn entry(k: &str, v: &str) -> (String, String) {
+    (String::from(k), String::from(v))
+  }
+
+  #[test]
+  fn entries_are_split_at_every_kind_of_separator() {
+    let text = "# comment\n! comment\n\na=1\nb : 2\nc 3\n  d=\n e\\ f=g\\=h\\:i\n";
+    assert_eq!(read(text), [
+      entry("a", "1"), entry("b", "2"), entry("c", "3"), entry("d", ""), entry("e f", "g=h:i"),
+    ]);
+  }
+
+  #[test]
+  fn separators_are_mixed_freely_and_escaped_ones_belong_to_the_key() {
+    let text = "a\\:b:c\ra\\=b = c\r\na\\ b \t c\nk:=v\nk =:v\nk\\\\ v\n";
+    assert_eq!(read(text), [
+      entry("a:b", "c"), entry("a=b", "c"), entry("a b", "c"), entry("k", "=v"), entry("k", ":v"),
+      entry("k\\", "v"),
+    ]);
+  }
+
+  #[test]
+  fn escapes_and_continuation_lines_are_resolved() {
+    let text = "a=tab\\tnew\\nline\\\\\nb=\\ lead\nc=one, \\\n    two\nd=\\u00e9\\uD83D\\uDE00\n";
+    assert_eq!(read(text), [
+      entry("a", "tab\tnew\nline\\"), entry("b", " lead"), entry("c", "one, two"),
+      entry("d", "é😀"),
+    ]);
+  }
+}
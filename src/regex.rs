@@ -0,0 +1,600 @@
+//! A small regular expression engine, for substitutions in keys.
+//!
+//! The syntax is the common subset of Perl-style regular expressions:
+//! - literals, and `\` escaping punctuation (e.g. `\.`, `\=`)
+//! - `.` (any character but a line feed), `[...]` and `[^...]` classes with ranges
+//! - `\d`, `\w`, `\s` (ASCII digits, word characters and whitespace) and their negations `\D`,
+//!   `\W`, `\S`
+//! - `^`, `$` and `\b` assertions
+//! - `(...)` capture groups, `(?:...)` non-capturing groups and `|` alternation
+//! - `*`, `+`, `?`, `{n}`, `{n,}` and `{n,m}` repetition, lazy if followed by `?`
+//!
+//! Patterns are compiled into a program run by a backtracking matcher, which never visits the
+//! same instruction at the same position twice: matching is linear in the length of the text for
+//! any pattern.
+
+use std::fmt::{Debug, Formatter};
+use std::str::FromStr;
+
+/// The largest count of a `{n,m}` repetition.
+const MAX_REPETITION: u32 = 1000;
+/// The largest number of instructions a pattern may be compiled into.
+const MAX_PROGRAM_LEN: usize = 100_000;
+
+/// A compiled regular expression.
+#[derive(Clone)]
+pub(crate) struct Regex {
+  pattern: String,
+  program: Vec<Inst>,
+  /// The number of capture groups, including the whole match as group 0.
+  groups: usize,
+}
+
+impl Debug for Regex {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_tuple("Regex").field(&self.pattern).finish()
+  }
+}
+
+#[derive(Clone, Debug)]
+enum Inst {
+  Char(char),
+  Any,
+  Class(Class),
+  /// Continues at the first target, backtracking to the second one.
+  Split(usize, usize),
+  Jump(usize),
+  Save(usize),
+  Start,
+  End,
+  WordBoundary,
+  Match,
+}
+
+#[derive(Clone, Debug)]
+struct Class {
+  negated: bool,
+  ranges: Vec<(char, char)>,
+}
+
+impl Class {
+  fn of(negated: bool, ranges: &[(char, char)]) -> Self {
+    Class { negated, ranges: ranges.to_vec() }
+  }
+
+  fn matches(&self, c: char) -> bool {
+    self.ranges.iter().any(|(lo, hi)| (*lo..=*hi).contains(&c)) != self.negated
+  }
+}
+
+const DIGIT: &[(char, char)] = &[('0', '9')];
+const WORD: &[(char, char)] = &[('0', '9'), ('A', 'Z'), ('_', '_'), ('a', 'z')];
+const SPACE: &[(char, char)] = &[('\t', '\r'), (' ', ' ')];
+
+#[derive(Debug)]
+enum Node {
+  Empty,
+  Char(char),
+  Any,
+  Class(Class),
+  Start,
+  End,
+  WordBoundary,
+  Group(Box<Node>, Option<usize>),
+  Concat(Vec<Node>),
+  Alternation(Vec<Node>),
+  Repeat { node: Box<Node>, min: u32, max: Option<u32>, greedy: bool },
+}
+
+impl Regex {
+  /// Compiles the pattern, failing with a description of the problem if it is invalid.
+  pub(crate) fn new(pattern: &str) -> Result<Self, String> {
+    let mut parser = Parser { chars: pattern.chars().collect(), pos: 0, groups: 1 };
+    let node = parser.alternation()?;
+    if parser.pos < parser.chars.len() {
+      return Err(parser.error("unmatched )"));
+    }
+
+    let mut program = vec![Inst::Save(0)];
+    compile(&node, &mut program);
+    program.extend([Inst::Save(1), Inst::Match]);
+    if program.len() > MAX_PROGRAM_LEN {
+      return Err(String::from("the pattern is too large"));
+    }
+    Ok(Regex { pattern: String::from(pattern), program, groups: parser.groups })
+  }
+
+  /// Replaces every non-overlapping match in `text`, leftmost first.
+  ///
+  /// In the replacement, `$n` or `${n}` is the text of the n-th capture group (`$0` being the whole
+  /// match, and a group which did not participate being empty), and `$$` is a literal `$`.
+  pub(crate) fn replace_all(&self, text: &str, replacement: &str) -> String {
+    let mut replaced = String::with_capacity(text.len());
+    let mut visited = vec![false; self.program.len() * (text.len() + 1)];
+    let (mut last, mut pos) = (0, 0);
+    while pos <= text.len() {
+      let Some(captures) = self.captures_at(text, pos, &mut visited) else {
+        break;
+      };
+      let (start, end) = (captures[0].expect("group 0 is set"), captures[1].expect("match end"));
+      replaced.push_str(&text[last..start]);
+      expand(replacement, text, &captures, &mut replaced);
+      // The states of a match did not fail, so they must be explored again by the next search:
+      visited.fill(false);
+      last = end;
+      pos = if end > start {
+        end
+      } else {
+        // An empty match is never repeated at the same position:
+        match text[end..].chars().next() {
+          Some(c) => end + c.len_utf8(),
+          None => break,
+        }
+      };
+    }
+    replaced.push_str(&text[last..]);
+    replaced
+  }
+
+  /// Returns `true` if the pattern matches anywhere in `text`.
+  pub(crate) fn is_match(&self, text: &str) -> bool {
+    let mut visited = vec![false; self.program.len() * (text.len() + 1)];
+    self.captures_at(text, 0, &mut visited).is_some()
+  }
+
+  /// Finds the leftmost match starting at `from` or later, returning the positions of its
+  /// capture groups as `[start0, end0, start1, end1, ...]`.
+  fn captures_at(
+    &self,
+    text: &str,
+    from: usize,
+    visited: &mut [bool],
+  ) -> Option<Vec<Option<usize>>> {
+    // A state which failed once fails at any later start as well, so `visited` is kept:
+    let starts = text[from..].char_indices().map(|(i, _)| from + i).chain([text.len()]);
+    for start in starts {
+      let mut captures = vec![None; 2 * self.groups];
+      if self.backtrack(text, start, visited, &mut captures) {
+        return Some(captures);
+      }
+    }
+    None
+  }
+
+  fn backtrack(
+    &self,
+    text: &str,
+    start: usize,
+    visited: &mut [bool],
+    captures: &mut [Option<usize>],
+  ) -> bool {
+    enum Job {
+      Explore(usize, usize),
+      Restore(usize, Option<usize>),
+    }
+
+    let mut jobs = vec![Job::Explore(0, start)];
+    while let Some(job) = jobs.pop() {
+      let (mut pc, mut pos) = match job {
+        Job::Explore(pc, pos) => (pc, pos),
+        Job::Restore(slot, old) => {
+          captures[slot] = old;
+          continue;
+        }
+      };
+      loop {
+        let state = pc * (text.len() + 1) + pos;
+        if visited[state] {
+          break;
+        }
+        visited[state] = true;
+
+        let next = text[pos..].chars().next();
+        match &self.program[pc] {
+          Inst::Char(c) if next == Some(*c) => pos += c.len_utf8(),
+          Inst::Any if next.is_some_and(|c| c != '\n') => pos += next.map_or(0, char::len_utf8),
+          Inst::Class(class) if next.is_some_and(|c| class.matches(c)) => {
+            pos += next.map_or(0, char::len_utf8);
+          }
+          Inst::Char(_) | Inst::Any | Inst::Class(_) => break,
+          Inst::Split(first, second) => {
+            jobs.push(Job::Explore(*second, pos));
+            pc = *first;
+            continue;
+          }
+          Inst::Jump(target) => {
+            pc = *target;
+            continue;
+          }
+          Inst::Save(slot) => {
+            jobs.push(Job::Restore(*slot, captures[*slot]));
+            captures[*slot] = Some(pos);
+          }
+          Inst::Start if pos == 0 => {}
+          Inst::End if pos == text.len() => {}
+          Inst::WordBoundary if is_word_boundary(text, pos) => {}
+          Inst::Start | Inst::End | Inst::WordBoundary => break,
+          Inst::Match => return true,
+        }
+        pc += 1;
+      }
+    }
+    false
+  }
+}
+
+/// A substitution of the matches of a regular expression, written as `<regex>=<replacement>`.
+///
+/// The pattern ends at the first `=` not escaped by a backslash, so `\=` is a `=` in the pattern.
+#[derive(Clone, Debug)]
+pub(crate) struct Replacement {
+  regex: Regex,
+  replacement: String,
+}
+
+impl Replacement {
+  /// Replaces every match in `text`, see [Regex::replace_all].
+  pub(crate) fn apply(&self, text: &str) -> String {
+    self.regex.replace_all(text, &self.replacement)
+  }
+}
+
+impl FromStr for Replacement {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let mut escaped = false;
+    let split = s.char_indices().find(|(_, c)| {
+      let is_split = *c == '=' && !escaped;
+      escaped = *c == '\\' && !escaped;
+      is_split
+    });
+    let Some((i, _)) = split else {
+      return Err(format!("[ {s} ] must be written as <regex>=<replacement>"));
+    };
+    let regex = Regex::new(&s[..i]).map_err(|e| format!("invalid regex [ {} ]: {e}", &s[..i]))?;
+    Ok(Replacement { regex, replacement: String::from(&s[i + 1..]) })
+  }
+}
+
+fn is_word_boundary(text: &str, pos: usize) -> bool {
+  let is_word = |c: Option<char>| c.is_some_and(|c| Class::of(false, WORD).matches(c));
+  is_word(text[..pos].chars().next_back()) != is_word(text[pos..].chars().next())
+}
+
+/// Appends the replacement to `replaced`, substituting the references to capture groups.
+fn expand(replacement: &str, text: &str, captures: &[Option<usize>], replaced: &mut String) {
+  let mut rest = replacement;
+  while let Some(i) = rest.find('$') {
+    replaced.push_str(&rest[..i]);
+    rest = &rest[i + 1..];
+    let (digits, tail) = match rest.strip_prefix('{').and_then(|r| r.split_once('}')) {
+      Some((digits, tail)) if !digits.is_empty() && digits.bytes().all(|b| b.is_ascii_digit()) => {
+        (digits, tail)
+      }
+      _ => {
+        let len = rest.bytes().take_while(u8::is_ascii_digit).count();
+        (&rest[..len], &rest[len..])
+      }
+    };
+    match digits.parse::<usize>() {
+      Ok(group) => {
+        if let (Some(Some(start)), Some(Some(end))) =
+          (captures.get(2 * group), captures.get(2 * group + 1)) {
+          replaced.push_str(&text[*start..*end]);
+        }
+        rest = tail;
+      }
+      Err(_) => {
+        // `$$` is a literal `$`, as is a `$` not followed by a group reference:
+        replaced.push('$');
+        rest = rest.strip_prefix('$').unwrap_or(rest);
+      }
+    }
+  }
+  replaced.push_str(rest);
+}
+
+fn compile(node: &Node, program: &mut Vec<Inst>) {
+  match node {
+    Node::Empty => {}
+    Node::Char(c) => program.push(Inst::Char(*c)),
+    Node::Any => program.push(Inst::Any),
+    Node::Class(class) => program.push(Inst::Class(class.clone())),
+    Node::Start => program.push(Inst::Start),
+    Node::End => program.push(Inst::End),
+    Node::WordBoundary => program.push(Inst::WordBoundary),
+    Node::Group(node, None) => compile(node, program),
+    Node::Group(node, Some(group)) => {
+      program.push(Inst::Save(2 * group));
+      compile(node, program);
+      program.push(Inst::Save(2 * group + 1));
+    }
+    Node::Concat(nodes) => nodes.iter().for_each(|node| compile(node, program)),
+    Node::Alternation(nodes) => {
+      let mut jumps = Vec::new();
+      for (i, node) in nodes.iter().enumerate() {
+        if i == nodes.len() - 1 {
+          compile(node, program);
+          break;
+        }
+        let split = program.len();
+        program.push(Inst::Split(split + 1, 0));
+        compile(node, program);
+        jumps.push(program.len());
+        program.push(Inst::Jump(0));
+        program[split] = Inst::Split(split + 1, program.len());
+      }
+      let end = program.len();
+      for jump in jumps {
+        program[jump] = Inst::Jump(end);
+      }
+    }
+    Node::Repeat { node, min, max, greedy } => {
+      for _ in 0..*min {
+        compile(node, program);
+      }
+      let split = |body: usize, out: usize| {
+        if *greedy { Inst::Split(body, out) } else { Inst::Split(out, body) }
+      };
+      match max {
+        None => {
+          let start = program.len();
+          program.push(Inst::Match);
+          compile(node, program);
+          program.push(Inst::Jump(start));
+          program[start] = split(start + 1, program.len());
+        }
+        Some(max) => {
+          let mut splits = Vec::new();
+          for _ in *min..*max {
+            splits.push(program.len());
+            program.push(Inst::Match);
+            compile(node, program);
+          }
+          let end = program.len();
+          for start in splits {
+            program[start] = split(start + 1, end);
+          }
+        }
+      }
+    }
+  }
+}
+
+struct Parser {
+  chars: Vec<char>,
+  pos: usize,
+  groups: usize,
+}
+
+impl Parser {
+  fn error(&self, message: &str) -> String {
+    format!("{message} at position {}", self.pos)
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.chars.get(self.pos).copied()
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let eaten = self.peek() == Some(c);
+    if eaten {
+      self.pos += 1;
+    }
+    eaten
+  }
+
+  fn alternation(&mut self) -> Result<Node, String> {
+    let mut nodes = vec![self.concat()?];
+    while self.eat('|') {
+      nodes.push(self.concat()?);
+    }
+    Ok(if nodes.len() == 1 { nodes.remove(0) } else { Node::Alternation(nodes) })
+  }
+
+  fn concat(&mut self) -> Result<Node, String> {
+    let mut nodes = Vec::new();
+    while let Some(c) = self.peek().filter(|c| *c != '|' && *c != ')') {
+      let is_repetition = c != '{' || self.counts(self.pos).is_some();
+      self.pos += 1;
+      let atom = match c {
+        '(' => self.group()?,
+        '[' => Node::Class(self.class()?),
+        '.' => Node::Any,
+        '^' => Node::Start,
+        '$' => Node::End,
+        '\\' => self.escape()?,
+        '*' | '+' | '?' | '{' if is_repetition => return Err(self.error("nothing to repeat")),
+        c => Node::Char(c),
+      };
+      nodes.push(self.repetition(atom)?);
+    }
+    Ok(match nodes.len() {
+      0 => Node::Empty,
+      1 => nodes.remove(0),
+      _ => Node::Concat(nodes),
+    })
+  }
+
+  fn group(&mut self) -> Result<Node, String> {
+    let group = if self.chars[self.pos..].starts_with(&['?', ':']) {
+      self.pos += 2;
+      None
+    } else {
+      self.groups += 1;
+      Some(self.groups - 1)
+    };
+    let node = self.alternation()?;
+    if !self.eat(')') {
+      return Err(self.error("unclosed group"));
+    }
+    Ok(Node::Group(Box::new(node), group))
+  }
+
+  fn escape(&mut self) -> Result<Node, String> {
+    let c = self.peek().ok_or_else(|| self.error("trailing backslash"))?;
+    self.pos += 1;
+    Ok(match c {
+      'd' | 'w' | 's' | 'D' | 'W' | 'S' => Node::Class(Self::class_escape(c)),
+      'b' => Node::WordBoundary,
+      _ => Node::Char(self.literal_escape(c)?),
+    })
+  }
+
+  fn class_escape(c: char) -> Class {
+    let ranges = match c.to_ascii_lowercase() {
+      'd' => DIGIT,
+      'w' => WORD,
+      _ => SPACE,
+    };
+    Class::of(c.is_ascii_uppercase(), ranges)
+  }
+
+  fn literal_escape(&self, c: char) -> Result<char, String> {
+    match c {
+      'n' => Ok('\n'),
+      'r' => Ok('\r'),
+      't' => Ok('\t'),
+      c if c.is_ascii_alphanumeric() => Err(self.error(&format!("unknown escape \\{c}"))),
+      c => Ok(c),
+    }
+  }
+
+  fn class(&mut self) -> Result<Class, String> {
+    let negated = self.eat('^');
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+      let c = self.peek().ok_or_else(|| self.error("unclosed character class"))?;
+      self.pos += 1;
+      if c == ']' && !first {
+        break;
+      }
+      first = false;
+
+      let lo = match c {
+        '\\' => {
+          let c = self.peek().ok_or_else(|| self.error("trailing backslash"))?;
+          self.pos += 1;
+          if matches!(c, 'd' | 'w' | 's') {
+            ranges.extend_from_slice(&Self::class_escape(c).ranges);
+            continue;
+          }
+          self.literal_escape(c)?
+        }
+        c => c,
+      };
+      let is_range = self.peek() == Some('-') && self.chars.get(self.pos + 1) != Some(&']');
+      if !is_range {
+        ranges.push((lo, lo));
+        continue;
+      }
+      self.pos += 1;
+      let hi = match self.peek().ok_or_else(|| self.error("unclosed character class"))? {
+        '\\' => {
+          self.pos += 1;
+          let c = self.peek().ok_or_else(|| self.error("trailing backslash"))?;
+          self.literal_escape(c)?
+        }
+        c => c,
+      };
+      self.pos += 1;
+      if hi < lo {
+        return Err(self.error("invalid range in character class"));
+      }
+      ranges.push((lo, hi));
+    }
+    Ok(Class { negated, ranges })
+  }
+
+  fn repetition(&mut self, atom: Node) -> Result<Node, String> {
+    let (min, max) = match self.peek() {
+      Some('*') => (0, None),
+      Some('+') => (1, None),
+      Some('?') => (0, Some(1)),
+      Some('{') => {
+        let Some((min, max, len)) = self.counts(self.pos) else {
+          // A `{` which does not start a repetition count is a literal:
+          return Ok(atom);
+        };
+        self.pos += len - 1;
+        (min, max)
+      }
+      _ => return Ok(atom),
+    };
+    self.pos += 1;
+    if max.is_some_and(|max| max < min) || min.max(max.unwrap_or(0)) > MAX_REPETITION {
+      return Err(self.error("invalid repetition count"));
+    }
+    let greedy = !self.eat('?');
+    Ok(Node::Repeat { node: Box::new(atom), min, max, greedy })
+  }
+
+  /// Reads a `{n}`, `{n,}` or `{n,m}` count at `at`, returning the counts and its length.
+  fn counts(&self, at: usize) -> Option<(u32, Option<u32>, usize)> {
+    let rest: String = self.chars[at..].iter().collect();
+    let (inner, _) = rest.strip_prefix('{')?.split_once('}')?;
+    let len = inner.chars().count() + 2;
+    let number = |s: &str| (!s.is_empty() && s.bytes().all(|b| b.is_ascii_digit()))
+      .then(|| s.parse().ok())
+      .flatten();
+    match inner.split_once(',') {
+      None => number(inner).map(|n| (n, Some(n), len)),
+      Some((min, "")) => number(min).map(|min| (min, None, len)),
+      Some((min, max)) => Some((number(min)?, Some(number(max)?), len)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::regex::{Regex, Replacement};
+
+  fn replace(pattern: &str, text: &str, replacement: &str) -> String {
+    Regex::new(pattern).unwrap().replace_all(text, replacement)
+  }
+
+  #[test]
+  fn matches_are_replaced_with_their_capture_groups() {
+    assert_eq!(replace("@", "user@host@domain", "_at_"), "user_at_host_at_domain");
+    assert_eq!(replace(r"(\w+)-(\d+)", "a-1 bc-23 -4", "$2_$1"), "1_a 23_bc -4");
+    assert_eq!(replace("(a)|(b)", "ab", "[${1}${2}0$$]"), "[a0$][b0$]");
+    assert_eq!(replace("x*", "abc", "-"), "-a-b-c-");
+    assert_eq!(replace("^|$", "ab", "|"), "|ab|");
+  }
+
+  #[test]
+  fn classes_repetitions_and_assertions_are_matched() {
+    assert_eq!(replace("[^a-c]+", "abxyzcd", "_"), "ab_c_");
+    assert_eq!(replace(r"[\d.]{2,3}", "1.2345", "#"), "##");
+    assert_eq!(replace("a{2}", "aaaaa", "b"), "bba");
+    assert_eq!(replace("a+?", "aaa", "b"), "bbb");
+    assert_eq!(replace(r"\bid\b", "id idx an_id id", "ID"), "ID idx an_id ID");
+    assert_eq!(replace("(?:ab)+c", "ababcabc", "x"), "xx");
+    assert_eq!(replace("a{,2}", "a{,2}", "b"), "b");
+    assert!(Regex::new("^[a-z]+$").unwrap().is_match("abc"));
+    assert!(!Regex::new("^[a-z]+$").unwrap().is_match("ab1"));
+  }
+
+  #[test]
+  fn pathological_patterns_are_matched_in_linear_time() {
+    let text = "a".repeat(5000);
+    assert_eq!(replace("(a*)*b", &text, "x"), text);
+  }
+
+  #[test]
+  fn replacements_are_split_at_the_first_unescaped_equals_sign() {
+    let replacement: Replacement = r"a\=b=c=d".parse().unwrap();
+    assert_eq!(replacement.apply("a=b"), "c=d");
+    assert!("abc".parse::<Replacement>().is_err());
+    assert!("(=x".parse::<Replacement>().is_err());
+  }
+
+  #[test]
+  fn invalid_patterns_are_rejected() {
+    for pattern in ["(a", "a)", "*a", "[a", "[b-a]", r"\q", "a{3,2}", "a\\"] {
+      assert!(Regex::new(pattern).is_err(), "{pattern}");
+    }
+  }
+}
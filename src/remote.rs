@@ -0,0 +1,206 @@
+//! A minimal HTTP/1.1 client fetching sources from `http://` URLs.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::{bail, Context};
+use log::debug;
+
+/// The scheme of the URLs which can be fetched.
+pub const HTTP_SCHEME: &str = "http://";
+
+/// Fetches the body of `url` with a `GET` request, sending the `headers` (each written as
+/// `Name: value`) along with it.
+///
+/// Connecting, and every read or write, fails after `timeout`. Responses other than `2xx` fail
+/// with their status; redirects are not followed.
+pub fn fetch(url: &str, timeout: Duration, headers: &[String]) -> anyhow::Result<Vec<u8>> {
+  let (authority, path) = split_url(url)?;
+  let target = if has_port(authority) { authority.to_string() } else { format!("{authority}:80") };
+  let addrs = target.to_socket_addrs().with_context(|| format!("Cannot resolve {authority}"))?;
+  let stream = addrs
+    .filter_map(|addr| TcpStream::connect_timeout(&addr, timeout).ok())
+    .next()
+    .with_context(|| format!("Cannot connect to {authority}"))?;
+  stream.set_read_timeout(Some(timeout))?;
+  stream.set_write_timeout(Some(timeout))?;
+
+  let mut request = format!(
+    "GET {path} HTTP/1.1\r\nHost: {authority}\r\nUser-Agent: jsonprops/{}\r\nAccept: */*\r\n\
+    Connection: close\r\n",
+    env!("CARGO_PKG_VERSION"));
+  for header in headers {
+    match header.split_once(':') {
+      Some((name, value)) if !name.trim().is_empty() && !header.contains(['\r', '\n']) => {
+        request.push_str(&format!("{}: {}\r\n", name.trim(), value.trim()));
+      }
+      _ => bail!("Invalid header [ {header} ]. It must be written as `Name: value`"),
+    }
+  }
+  request.push_str("\r\n");
+  debug!("Requesting GET {url}...");
+  (&stream).write_all(request.as_bytes()).with_context(|| format!("Cannot request {url}"))?;
+
+  read_response(&mut BufReader::new(&stream)).with_context(|| format!("Cannot fetch {url}"))
+}
+
+/// Splits an `http://` URL into its authority (`host[:port]`) and its path and query, the latter
+/// starting with a `/` even if the URL has a query but no path, e.g. `http://host?x`.
+fn split_url(url: &str) -> anyhow::Result<(&str, String)> {
+  let Some(rest) = url.strip_prefix(HTTP_SCHEME) else {
+    bail!("Only {HTTP_SCHEME} URLs can be fetched: {url}");
+  };
+  let rest = rest.split_once('#').map_or(rest, |(rest, _)| rest);
+  let (authority, path) = match rest.find(['/', '?']) {
+    Some(i) if rest[i..].starts_with('?') => (&rest[..i], format!("/{}", &rest[i..])),
+    Some(i) => (&rest[..i], String::from(&rest[i..])),
+    None => (rest, String::from("/")),
+  };
+  if authority.is_empty() || authority.contains('@') {
+    bail!("Invalid URL: {url}");
+  }
+  Ok((authority, path))
+}
+
+/// Returns `true` if the authority has a port, i.e. a `:` after the host, which is in brackets if
+/// it is an IPv6 address, e.g. `[::1]:8080`.
+fn has_port(authority: &str) -> bool {
+  match authority.rfind(']') {
+    Some(i) => authority[i..].contains(':'),
+    None => authority.contains(':'),
+  }
+}
+
+/// Reads the status line and the headers of a response, then its body.
+fn read_response(reader: &mut impl BufRead) -> anyhow::Result<Vec<u8>> {
+  let mut status_line = String::new();
+  reader.read_line(&mut status_line)?;
+  let mut parts = status_line.trim_end().splitn(3, ' ');
+  let (Some(_), Some(status)) = (parts.next(), parts.next().and_then(|s| s.parse::<u16>().ok()))
+  else {
+    bail!("Malformed status line [ {} ]", status_line.trim_end());
+  };
+  let reason = parts.next().unwrap_or_default().to_string();
+
+  let (mut content_length, mut chunked, mut location) = (None, false, None);
+  loop {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+      break;
+    }
+    let Some((name, value)) = header.split_once(':') else {
+      continue;
+    };
+    let (name, value) = (name.trim(), value.trim());
+    if name.eq_ignore_ascii_case("content-length") {
+      content_length = Some(value.parse::<usize>().context("Invalid Content-Length")?);
+    } else if name.eq_ignore_ascii_case("transfer-encoding") {
+      chunked = value.eq_ignore_ascii_case("chunked");
+    } else if name.eq_ignore_ascii_case("location") {
+      location = Some(value.to_string());
+    }
+  }
+
+  if !(200..300).contains(&status) {
+    let location = location.map(|l| format!(", redirecting to {l}")).unwrap_or_default();
+    bail!("The server answered {status} {reason}{location}");
+  }
+  let mut body = Vec::new();
+  match (chunked, content_length) {
+    (true, _) => read_chunks(reader, &mut body)?,
+    (false, Some(len)) => {
+      body.resize(len, 0);
+      reader.read_exact(&mut body)?;
+    }
+    (false, None) => {
+      reader.read_to_end(&mut body)?;
+    }
+  }
+  Ok(body)
+}
+
+/// Reads a body of the `chunked` transfer coding, ignoring chunk extensions and trailers.
+fn read_chunks(reader: &mut impl BufRead, body: &mut Vec<u8>) -> anyhow::Result<()> {
+  loop {
+    let mut size_line = String::new();
+    reader.read_line(&mut size_line)?;
+    let size = size_line.split(';').next().unwrap_or_default().trim();
+    let size = usize::from_str_radix(size, 16)
+      .with_context(|| format!("Invalid chunk size [ {size} ]"))?;
+    if size == 0 {
+      return Ok(());
+    }
+    let start = body.len();
+    body.resize(start + size, 0);
+    reader.read_exact(&mut body[start..])?;
+    let mut crlf = String::new();
+    reader.read_line(&mut crlf)?;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::io::{BufRead, BufReader, Write};
+  use std::net::TcpListener;
+  use std::thread;
+  use std::time::Duration;
+
+  use crate::remote::{fetch, has_port, read_response, split_url};
+
+  #[test]
+  fn urls_are_split_into_their_authority_and_path() {
+    let split = |url| split_url(url).unwrap();
+    assert_eq!(split("http://host:8080/a/b?c=d#e"), ("host:8080", String::from("/a/b?c=d")));
+    assert_eq!(split("http://host"), ("host", String::from("/")));
+    assert_eq!(split("http://host?x"), ("host", String::from("/?x")));
+    assert!(split_url("https://host/").is_err());
+    assert!(split_url("http://user@host/").is_err());
+  }
+
+  #[test]
+  fn only_a_colon_after_the_host_is_taken_as_a_port() {
+    assert!(has_port("host:8080"));
+    assert!(has_port("[::1]:8080"));
+    assert!(!has_port("host"));
+    assert!(!has_port("[::1]"));
+  }
+
+  #[test]
+  fn bodies_are_read_by_length_or_chunks() {
+    let response = "HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n{}\r\nignored";
+    assert_eq!(read_response(&mut response.as_bytes()).unwrap(), b"{}\r\n");
+
+    let response = "HTTP/1.1 200 OK\r\nTransfer-Encoding: chunked\r\n\r\n\
+      3;ext=1\r\n{ \"\r\n6\r\na\": 1}\r\n0\r\n\r\n";
+    assert_eq!(read_response(&mut response.as_bytes()).unwrap(), b"{ \"a\": 1}");
+
+    let response = "HTTP/1.1 302 Found\r\nLocation: http://elsewhere/\r\n\r\n";
+    let err = read_response(&mut response.as_bytes()).unwrap_err();
+    assert_eq!(err.to_string(), "The server answered 302 Found, redirecting to http://elsewhere/");
+  }
+
+  #[test]
+  fn sources_are_fetched_with_the_given_headers() {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = listener.local_addr().unwrap();
+    let server = thread::spawn(move || {
+      let (stream, _) = listener.accept().unwrap();
+      let mut request = String::new();
+      let mut reader = BufReader::new(&stream);
+      while reader.read_line(&mut request).unwrap() > 2 && !request.ends_with("\r\n\r\n") {}
+      (&stream).write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 8\r\n\r\n{\"a\":1}\n").unwrap();
+      request
+    });
+
+    let url = format!("http://{addr}/config.json");
+    let headers = [String::from("Authorization:  Bearer token")];
+    let body = fetch(&url, Duration::from_secs(5), &headers).unwrap();
+    assert_eq!(body, b"{\"a\":1}\n");
+    let request = server.join().unwrap();
+    assert!(request.starts_with("GET /config.json HTTP/1.1\r\n"), "{request}");
+    assert!(request.contains("\r\nAuthorization: Bearer token\r\n"), "{request}");
+
+    assert!(fetch(&url, Duration::from_secs(5), &[String::from("not a header")]).is_err());
+  }
+}
@@ -0,0 +1,270 @@
+//! A minimal HTTP/1.1 endpoint converting POSTed JSON bodies into properties.
+
+use std::ffi::OsString;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+use clap::{CommandFactory, Parser};
+use log::{debug, info, warn};
+use serde_json::Value;
+
+use crate::app_config::{Config, OutputFormat};
+use crate::props::Properties;
+use crate::str_constant;
+
+/// The options which can be set by query parameters. Options reading or writing files, or reading
+/// the environment of the server, are deliberately left out.
+const QUERY_OPTIONS: &[&str] = &[
+  "list-handling", "entry-separator", "namespace-separator", "order", "array-style", "array-base",
+  "key-case", "bool-format", "null-handling", "list-null-handling", "number-format",
+  "integer-floats", "force-decimal", "list-delimiter", "list-escape", "quote-style",
+  "on-complex-list", "on-duplicate", "output", "prefix", "pointer", "include", "exclude", "redact",
+  "redact-placeholder", "format-path", "max-depth", "max-value-length", "max-array-elements",
+  "on-array-overflow", "on-oversize", "line-ending", "ascii", "no-key-escape", "space-escape",
+  "escape-all-hashes", "key-encoding", "discard-wsp", "trim-trailing", "header", "annotate-types",
+  "comment-field", "count-lists", "dedup-list", "sort-list", "sort-list-numeric",
+  "coerce-numeric-strings", "normalize-bool-strings", "bool-strings-ignore-case",
+  "parse-embedded-json", "allow-top-array", "on-nonfinite", "on-empty-key", "empty-key-placeholder",
+  "nonfinite-placeholder", "key-replace", "validate-keys", "key-pattern", "keys-only",
+  "values-only",
+];
+/// The largest request body accepted, in bytes.
+const MAX_BODY_LEN: usize = 16 * 1024 * 1024;
+/// The time a connection may stay silent before it is dropped.
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Accepts connections on `addr` until the process is interrupted, answering every connection on
+/// its own thread.
+pub fn serve(addr: &str) -> anyhow::Result<()> {
+  let listener = TcpListener::bind(addr).with_context(|| format!("Cannot listen on {addr}"))?;
+  info!("Serving conversions on http://{}", listener.local_addr()?);
+  for stream in listener.incoming() {
+    match stream {
+      Ok(stream) => {
+        thread::spawn(move || {
+          if let Err(e) = handle(stream) {
+            warn!("Cannot answer a request: {e}");
+          }
+        });
+      }
+      Err(e) => warn!("Cannot accept a connection: {e}"),
+    }
+  }
+  Ok(())
+}
+
+fn handle(mut stream: TcpStream) -> io::Result<()> {
+  stream.set_read_timeout(Some(READ_TIMEOUT))?;
+  let response = match read_request(&mut BufReader::new(&stream))? {
+    Ok(request) => respond(&request),
+    Err(response) => response,
+  };
+  debug!("Answering with {} {}", response.status, response.reason);
+  response.write_to(&mut stream)
+}
+
+struct Request {
+  method: String,
+  /// The path and query of the request, e.g. `/?list-handling=multi-prop`.
+  target: String,
+  body: Vec<u8>,
+}
+
+struct Response {
+  status: u16,
+  reason: &'static str,
+  content_type: &'static str,
+  body: String,
+}
+
+impl Response {
+  fn ok(body: String, output: &OutputFormat) -> Self {
+    let content_type = match output {
+      OutputFormat::Xml => "application/xml; charset=utf-8",
+      OutputFormat::Properties | OutputFormat::Env => "text/plain; charset=utf-8",
+    };
+    Response { status: 200, reason: "OK", content_type, body }
+  }
+
+  fn error(status: u16, reason: &'static str, message: impl Into<String>) -> Self {
+    let mut body = message.into();
+    body.push('\n');
+    Response { status, reason, content_type: "text/plain; charset=utf-8", body }
+  }
+
+  fn write_to(&self, w: &mut impl Write) -> io::Result<()> {
+    write!(
+      w,
+      "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n",
+      self.status, self.reason, self.content_type, self.body.len())?;
+    if self.status == 405 {
+      write!(w, "Allow: POST\r\n")?;
+    }
+    write!(w, "\r\n{}", self.body)?;
+    w.flush()
+  }
+}
+
+/// Reads the request line, the headers and the body of a request.
+///
+/// Returns the response to send instead if the request is malformed or too large.
+fn read_request(reader: &mut impl BufRead) -> io::Result<Result<Request, Response>> {
+  let mut request_line = String::new();
+  reader.read_line(&mut request_line)?;
+  let mut parts = request_line.split_whitespace();
+  let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+    return Ok(Err(Response::error(400, "Bad Request", "Malformed request line")));
+  };
+  let (method, target) = (String::from(method), String::from(target));
+
+  let mut content_length = 0;
+  loop {
+    let mut header = String::new();
+    if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+      break;
+    }
+    let Some((name, value)) = header.split_once(':') else {
+      continue;
+    };
+    if name.trim().eq_ignore_ascii_case("content-length") {
+      let Ok(len) = value.trim().parse::<usize>() else {
+        return Ok(Err(Response::error(400, "Bad Request", "Invalid Content-Length")));
+      };
+      content_length = len;
+    }
+  }
+
+  if content_length > MAX_BODY_LEN {
+    let message = format!("The body must be at most {MAX_BODY_LEN} bytes long");
+    return Ok(Err(Response::error(413, "Payload Too Large", message)));
+  }
+  let mut body = vec![0; content_length];
+  reader.read_exact(&mut body)?;
+  Ok(Ok(Request { method, target, body }))
+}
+
+fn respond(request: &Request) -> Response {
+  if request.method != "POST" {
+    return Response::error(405, "Method Not Allowed", "Only POST requests are converted");
+  }
+
+  let query = request.target.split_once('?').map_or(str_constant::EMPTY, |(_, query)| query);
+  let config = match config_from_query(query) {
+    Ok(config) => config,
+    Err(message) => return Response::error(400, "Bad Request", message),
+  };
+  let value: Value = match serde_json::from_slice(&request.body) {
+    Ok(value) => value,
+    Err(e) => {
+      let message =
+        format!("Cannot parse the body at line {}, column {}: {e}", e.line(), e.column());
+      return Response::error(400, "Bad Request", message);
+    }
+  };
+
+  match Properties::create(value, &config).and_then(|props| props.render(&config)) {
+    Ok(rendered) => Response::ok(rendered, config.output()),
+    Err(e) => Response::error(422, "Unprocessable Entity", format!("{e:#}")),
+  }
+}
+
+/// Creates the [Config] of a conversion from the query parameters of its request, e.g.
+/// `list-handling=multi-prop&ascii`. Only the [QUERY_OPTIONS] may be set; flags are set by an empty
+/// value or `true`.
+fn config_from_query(query: &str) -> Result<Config, String> {
+  let command = Config::command();
+  let mut args = vec![OsString::from("jsonprops"), OsString::from(str_constant::DASH)];
+  for param in query.split('&').filter(|param| !param.is_empty()) {
+    let (name, value) = param.split_once('=').unwrap_or((param, str_constant::EMPTY));
+    let (name, value) = (percent_decode(name)?, percent_decode(value)?);
+    if !QUERY_OPTIONS.contains(&name.as_str()) {
+      return Err(format!("Option [ {name} ] cannot be set by a query parameter"));
+    }
+
+    let takes_values = command.get_arguments()
+      .find(|arg| arg.get_long() == Some(name.as_str()))
+      .is_some_and(|arg| arg.get_action().takes_values());
+    match (takes_values, value.as_str()) {
+      (true, _) => args.push(OsString::from(format!("--{name}={value}"))),
+      (false, "" | "true") => args.push(OsString::from(format!("--{name}"))),
+      (false, "false") => {}
+      (false, _) => return Err(format!("Option [ {name} ] is a flag, it must be true or false")),
+    }
+  }
+
+  Config::try_parse_from(args)
+    .map_err(|e| e.to_string())
+    .and_then(|config| config.validate().map_err(|e| e.to_string()))
+}
+
+/// Decodes a `application/x-www-form-urlencoded` component: `+` is a space, and `%XX` is the byte
+/// of the hexadecimal digits `XX`.
+fn percent_decode(s: &str) -> Result<String, String> {
+  let mut bytes = Vec::with_capacity(s.len());
+  let mut rest = s.as_bytes();
+  while let Some((&b, tail)) = rest.split_first() {
+    rest = tail;
+    match b {
+      b'+' => bytes.push(b' '),
+      b'%' => {
+        let byte = rest.get(..2)
+          .and_then(|hex| std::str::from_utf8(hex).ok())
+          .and_then(|hex| u8::from_str_radix(hex, 16).ok())
+          .ok_or_else(|| format!("Invalid percent-encoding in [ {s} ]"))?;
+        bytes.push(byte);
+        rest = &rest[2..];
+      }
+      _ => bytes.push(b),
+    }
+  }
+  String::from_utf8(bytes).map_err(|_| format!("Query parameter [ {s} ] is not valid UTF-8"))
+}
+
+#[cfg(test)]
+mod tests {
+  use clap::CommandFactory;
+
+  use crate::app_config::Config;
+  use crate::serve::{config_from_query, percent_decode, read_request, respond, QUERY_OPTIONS};
+
+  #[test]
+  fn every_query_option_is_an_option_of_the_command_line() {
+    let command = Config::command();
+    for name in QUERY_OPTIONS {
+      assert!(command.get_arguments().any(|arg| arg.get_long() == Some(name)), "{name}");
+    }
+  }
+
+  #[test]
+  fn query_parameters_are_decoded_into_options() {
+    assert_eq!(percent_decode("a+b%3D%C3%A9").unwrap(), "a b=é");
+    assert!(percent_decode("%4").is_err());
+
+    let config = config_from_query("list-handling=multi-prop&entry-separator=colon&ascii").unwrap();
+    assert_eq!(config.entry_separator(), ":");
+    assert!(config.ascii);
+    assert!(config_from_query("merge=%2Fetc%2Fpasswd").is_err());
+    assert!(config_from_query("ascii=maybe").is_err());
+  }
+
+  #[test]
+  fn posted_bodies_are_answered_with_their_properties() {
+    let request = "POST /?list-handling=multi-prop HTTP/1.1\r\nHost: localhost\r\n\
+      Content-Length: 21\r\n\r\n{ \"a\" : [ 1, true ] }";
+    let request = read_request(&mut request.as_bytes()).unwrap().ok().unwrap();
+    let response = respond(&request);
+    assert_eq!(response.status, 200);
+    assert_eq!(response.body, "a.0=1\na.1=true\n");
+
+    let request = "POST / HTTP/1.1\r\nContent-Length: 3\r\n\r\n{ 1";
+    let request = read_request(&mut request.as_bytes()).unwrap().ok().unwrap();
+    assert_eq!(respond(&request).status, 400);
+
+    let request = "GET / HTTP/1.1\r\n\r\n";
+    let request = read_request(&mut request.as_bytes()).unwrap().ok().unwrap();
+    assert_eq!(respond(&request).status, 405);
+  }
+}
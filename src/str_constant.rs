@@ -1,5 +1,12 @@
 pub const COLON: &str = ":";
 pub const EQ: &str = "=";
+pub const COLON_SPACED: &str = " : ";
+pub const EQ_SPACED: &str = " = ";
 pub const SPACE: &str = " ";
 pub const EMPTY: &str = "";
-pub const COMMA: &str = ",";
\ No newline at end of file
+pub const COMMA: &str = ",";
+pub const DASH: &str = "-";
+pub const DOT: &str = ".";
+pub const STDIN: &str = "standard input";
+pub const LF: &str = "\n";
+pub const CRLF: &str = "\r\n";
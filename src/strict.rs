@@ -0,0 +1,118 @@
+//! Strict JSON parsing, rejecting objects with duplicate keys.
+//!
+//! `serde_json` keeps the last of the duplicate keys of an object, which hides them from every
+//! later check: duplicates are therefore looked for in the source text itself.
+
+use std::collections::HashSet;
+use std::fmt::Formatter;
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde_json::Value;
+
+/// Parses JSON like [serde_json::from_str], failing at the first object with a duplicate key.
+///
+/// The error locates the duplicate key, and names the object by its JSON Pointer (RFC 6901).
+pub fn from_str(s: &str) -> serde_json::Result<Value> {
+  let mut de = serde_json::Deserializer::from_str(s);
+  DuplicateKeyCheck { pointer: String::new() }.deserialize(&mut de)?;
+  de.end()?;
+  serde_json::from_str(s)
+}
+
+/// Walks a JSON value, checking the keys of every object in it.
+struct DuplicateKeyCheck {
+  /// The JSON Pointer of the value being checked.
+  pointer: String,
+}
+
+impl DuplicateKeyCheck {
+  fn member(&self, token: &str) -> Self {
+    let token = token.replace('~', "~0").replace('/', "~1");
+    DuplicateKeyCheck { pointer: format!("{}/{token}", self.pointer) }
+  }
+}
+
+impl<'de> DeserializeSeed<'de> for DuplicateKeyCheck {
+  type Value = ();
+
+  fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+    deserializer.deserialize_any(self)
+  }
+}
+
+impl<'de> Visitor<'de> for DuplicateKeyCheck {
+  type Value = ();
+
+  fn expecting(&self, f: &mut Formatter) -> std::fmt::Result {
+    f.write_str("any JSON value")
+  }
+
+  fn visit_bool<E: de::Error>(self, _: bool) -> Result<(), E> {
+    Ok(())
+  }
+
+  fn visit_i64<E: de::Error>(self, _: i64) -> Result<(), E> {
+    Ok(())
+  }
+
+  fn visit_u64<E: de::Error>(self, _: u64) -> Result<(), E> {
+    Ok(())
+  }
+
+  fn visit_f64<E: de::Error>(self, _: f64) -> Result<(), E> {
+    Ok(())
+  }
+
+  fn visit_str<E: de::Error>(self, _: &str) -> Result<(), E> {
+    Ok(())
+  }
+
+  fn visit_unit<E: de::Error>(self) -> Result<(), E> {
+    Ok(())
+  }
+
+  fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+    let mut index = 0usize;
+    while seq.next_element_seed(self.member(&index.to_string()))?.is_some() {
+      index += 1;
+    }
+    Ok(())
+  }
+
+  fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+    // Numbers are visited as single-member maps when `serde_json` keeps their source token, which
+    // cannot have duplicates:
+    let mut keys = HashSet::new();
+    while let Some(key) = map.next_key::<String>()? {
+      if !keys.insert(key.clone()) {
+        let object = if self.pointer.is_empty() { "the root object" } else { &self.pointer };
+        return Err(de::Error::custom(format!("duplicate key [ {key} ] in {object}")));
+      }
+      map.next_value_seed(self.member(&key))?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::strict::from_str;
+
+  #[test]
+  fn documents_without_duplicate_keys_are_parsed() {
+    let value = from_str(r#"{ "a" : { "b" : [ { "c" : 1.5 }, { "c" : 2 } ] }, "b" : null }"#);
+    let expected = json!({ "a" : { "b" : [ { "c" : 1.5 }, { "c" : 2 } ] }, "b" : null });
+    assert_eq!(value.unwrap(), expected);
+  }
+
+  #[test]
+  fn duplicate_keys_are_reported_with_the_pointer_of_their_object() {
+    let err = from_str("{\n  \"a\" : { \"x/y\" : [ { \"c\" : 1, \"c\" : 2 } ] }\n}").unwrap_err();
+    assert_eq!(err.to_string(), "duplicate key [ c ] in /a/x~1y/0 at line 2 column 35");
+
+    let err = from_str(r#"{ "a" : 1, "a" : 2 }"#).unwrap_err();
+    assert!(err.to_string().starts_with("duplicate key [ a ] in the root object"));
+  }
+}
@@ -0,0 +1,652 @@
+//! A TOML parser producing the same [Value] tree `serde_json` produces for JSON.
+//!
+//! TOML tables become objects and TOML arrays become lists, so the converted document can be fed
+//! to [Properties::create](crate::Properties::create) as is. Datetimes have no JSON counterpart,
+//! they are kept as strings in their RFC 3339 form.
+
+use std::collections::HashSet;
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+
+use serde_json::{Map, Number, Value};
+
+#[derive(Debug)]
+pub struct TomlError {
+  line: usize,
+  column: usize,
+  message: String,
+}
+
+impl TomlError {
+  pub fn line(&self) -> usize {
+    self.line
+  }
+
+  pub fn column(&self) -> usize {
+    self.column
+  }
+}
+
+impl Display for TomlError {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    write!(f, "{} at line {} column {}", self.message, self.line, self.column)
+  }
+}
+
+impl Error for TomlError {}
+
+/// Parses a TOML document into a JSON [Value].
+pub fn from_str(s: &str) -> Result<Value, TomlError> {
+  TomlParser::new(s).parse().map(Value::Object)
+}
+
+struct TomlParser {
+  chars: Vec<char>,
+  pos: usize,
+  root: Map<String, Value>,
+  /// The path of the table the next key-value pairs belong to.
+  current: Vec<String>,
+  /// The paths of the tables already defined by a `[table]` header.
+  defined: HashSet<Vec<String>>,
+}
+
+impl TomlParser {
+  fn new(s: &str) -> Self {
+    TomlParser {
+      chars: s.chars().collect(),
+      pos: 0,
+      root: Map::new(),
+      current: Vec::new(),
+      defined: HashSet::new(),
+    }
+  }
+
+  fn parse(mut self) -> Result<Map<String, Value>, TomlError> {
+    loop {
+      self.skip_blank_lines();
+      match self.peek() {
+        None => break,
+        Some('[') => self.parse_table_header()?,
+        Some(_) => {
+          let (key, value) = self.parse_key_value()?;
+          let inserted = table_at(&mut self.root, &self.current)
+            .and_then(|table| insert_dotted(table, key, value));
+          inserted.map_err(|e| self.error(e))?;
+        }
+      }
+      self.expect_line_end()?;
+    }
+    Ok(self.root)
+  }
+
+  fn parse_table_header(&mut self) -> Result<(), TomlError> {
+    self.advance();
+    let is_array = self.eat('[');
+    self.skip_ws();
+    let path = self.parse_key()?;
+    self.skip_ws();
+    self.expect(']')?;
+    if is_array {
+      self.expect(']')?;
+      let pushed = push_array_table(&mut self.root, &path);
+      pushed.map_err(|e| self.error(e))?;
+      self.defined.retain(|defined| !defined.starts_with(&path));
+    } else {
+      if !self.defined.insert(path.clone()) {
+        return Err(self.error(format!("table [{}] is defined more than once", path.join("."))));
+      }
+      let created = table_at(&mut self.root, &path).map(|_| ());
+      created.map_err(|e| self.error(e))?;
+    }
+    self.current = path;
+    Ok(())
+  }
+
+  fn parse_key_value(&mut self) -> Result<(Vec<String>, Value), TomlError> {
+    let key = self.parse_key()?;
+    self.skip_ws();
+    self.expect('=')?;
+    self.skip_ws();
+    let value = self.parse_value()?;
+    Ok((key, value))
+  }
+
+  /// Parses a possibly dotted key into its segments.
+  fn parse_key(&mut self) -> Result<Vec<String>, TomlError> {
+    let mut segments = vec![self.parse_simple_key()?];
+    loop {
+      self.skip_ws();
+      if !self.eat('.') {
+        return Ok(segments);
+      }
+      self.skip_ws();
+      segments.push(self.parse_simple_key()?);
+    }
+  }
+
+  fn parse_simple_key(&mut self) -> Result<String, TomlError> {
+    match self.peek() {
+      Some('"') => {
+        self.advance();
+        self.parse_basic_string()
+      }
+      Some('\'') => {
+        self.advance();
+        self.parse_literal_string()
+      }
+      _ => {
+        let key = self.take_while(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+        if key.is_empty() {
+          Err(self.error("expected a key"))
+        } else {
+          Ok(key)
+        }
+      }
+    }
+  }
+
+  fn parse_value(&mut self) -> Result<Value, TomlError> {
+    match self.peek() {
+      Some('"') if self.lookahead("\"\"\"") => {
+        self.pos += 3;
+        self.parse_multiline_basic_string().map(Value::String)
+      }
+      Some('"') => {
+        self.advance();
+        self.parse_basic_string().map(Value::String)
+      }
+      Some('\'') if self.lookahead("'''") => {
+        self.pos += 3;
+        self.parse_multiline_literal_string().map(Value::String)
+      }
+      Some('\'') => {
+        self.advance();
+        self.parse_literal_string().map(Value::String)
+      }
+      Some('[') => self.parse_array(),
+      Some('{') => self.parse_inline_table(),
+      Some(_) if self.lookahead("true") => {
+        self.pos += 4;
+        Ok(Value::Bool(true))
+      }
+      Some(_) if self.lookahead("false") => {
+        self.pos += 5;
+        Ok(Value::Bool(false))
+      }
+      Some(_) => self.parse_scalar(),
+      None => Err(self.error("expected a value")),
+    }
+  }
+
+  fn parse_array(&mut self) -> Result<Value, TomlError> {
+    self.advance();
+    let mut values = Vec::new();
+    loop {
+      self.skip_ws_and_newlines();
+      if self.eat(']') {
+        return Ok(Value::Array(values));
+      }
+      values.push(self.parse_value()?);
+      self.skip_ws_and_newlines();
+      if self.eat(']') {
+        return Ok(Value::Array(values));
+      }
+      self.expect(',')?;
+    }
+  }
+
+  fn parse_inline_table(&mut self) -> Result<Value, TomlError> {
+    self.advance();
+    let mut table = Map::new();
+    self.skip_ws();
+    if self.eat('}') {
+      return Ok(Value::Object(table));
+    }
+    loop {
+      self.skip_ws();
+      let (key, value) = self.parse_key_value()?;
+      insert_dotted(&mut table, key, value).map_err(|e| self.error(e))?;
+      self.skip_ws();
+      if self.eat('}') {
+        return Ok(Value::Object(table));
+      }
+      self.expect(',')?;
+    }
+  }
+
+  fn parse_basic_string(&mut self) -> Result<String, TomlError> {
+    let mut s = String::new();
+    loop {
+      match self.advance() {
+        None | Some('\n') => return Err(self.error("unterminated string")),
+        Some('"') => return Ok(s),
+        Some('\\') => s.push(self.parse_escape()?),
+        Some(c) => s.push(c),
+      }
+    }
+  }
+
+  fn parse_multiline_basic_string(&mut self) -> Result<String, TomlError> {
+    self.skip_newline();
+    let mut s = String::new();
+    loop {
+      if self.lookahead("\"\"\"") {
+        self.close_multiline_string(&mut s, '"');
+        return Ok(s);
+      }
+      match self.advance() {
+        None => return Err(self.error("unterminated string")),
+        Some('\\') if self.peek().is_some_and(char::is_whitespace) => {
+          // A line ending backslash trims every whitespace up to the next non-whitespace:
+          self.take_while(char::is_whitespace);
+        }
+        Some('\\') => s.push(self.parse_escape()?),
+        Some(c) => s.push(c),
+      }
+    }
+  }
+
+  fn parse_literal_string(&mut self) -> Result<String, TomlError> {
+    let mut s = String::new();
+    loop {
+      match self.advance() {
+        None | Some('\n') => return Err(self.error("unterminated string")),
+        Some('\'') => return Ok(s),
+        Some(c) => s.push(c),
+      }
+    }
+  }
+
+  fn parse_multiline_literal_string(&mut self) -> Result<String, TomlError> {
+    self.skip_newline();
+    let mut s = String::new();
+    loop {
+      if self.lookahead("'''") {
+        self.close_multiline_string(&mut s, '\'');
+        return Ok(s);
+      }
+      match self.advance() {
+        None => return Err(self.error("unterminated string")),
+        Some(c) => s.push(c),
+      }
+    }
+  }
+
+  /// Consumes a closing delimiter, keeping up to two quotes directly preceding it in the string.
+  fn close_multiline_string(&mut self, s: &mut String, quote: char) {
+    let quotes = self.take_while(|c| c == quote);
+    s.extend(quotes.chars().skip(3).take(2));
+  }
+
+  fn parse_escape(&mut self) -> Result<char, TomlError> {
+    match self.advance() {
+      Some('b') => Ok('\u{8}'),
+      Some('t') => Ok('\t'),
+      Some('n') => Ok('\n'),
+      Some('f') => Ok('\u{c}'),
+      Some('r') => Ok('\r'),
+      Some('"') => Ok('"'),
+      Some('\\') => Ok('\\'),
+      Some('u') => self.parse_unicode_escape(4),
+      Some('U') => self.parse_unicode_escape(8),
+      _ => Err(self.error("invalid escape sequence")),
+    }
+  }
+
+  fn parse_unicode_escape(&mut self, len: usize) -> Result<char, TomlError> {
+    let hex: String = self.chars.iter().skip(self.pos).take(len).collect();
+    self.pos += hex.chars().count();
+    u32::from_str_radix(&hex, 16).ok()
+      .filter(|_| hex.len() == len)
+      .and_then(char::from_u32)
+      .ok_or_else(|| self.error(format!("invalid unicode escape sequence: {hex}")))
+  }
+
+  /// Parses numbers, datetimes, and the special float values.
+  fn parse_scalar(&mut self) -> Result<Value, TomlError> {
+    let mut token = self.take_while(|c| c.is_ascii_alphanumeric() || "+-_.:".contains(c));
+    // A space may separate the date and the time of a datetime:
+    let time_follows =
+      self.peek() == Some(' ') && self.peek_at(1).is_some_and(|c| c.is_ascii_digit());
+    if is_date(&token) && time_follows {
+      self.advance();
+      token.push('T');
+      token.push_str(&self.take_while(|c| c.is_ascii_alphanumeric() || "+-.:".contains(c)));
+    }
+
+    if token.is_empty() {
+      return Err(self.error("expected a value"));
+    }
+    if is_date(&token) || is_time(&token) {
+      return Ok(Value::String(token.to_uppercase()));
+    }
+    if matches!(token.trim_start_matches(['+', '-']), "inf" | "nan") {
+      return Err(self.error(format!("{token} cannot be represented in JSON")));
+    }
+    parse_number(&token).ok_or_else(|| self.error(format!("invalid value: {token}")))
+  }
+
+  fn expect_line_end(&mut self) -> Result<(), TomlError> {
+    self.skip_ws();
+    self.skip_comment();
+    match self.peek() {
+      None => Ok(()),
+      Some('\n') | Some('\r') => {
+        self.skip_newline();
+        Ok(())
+      }
+      Some(c) => Err(self.error(format!("unexpected character {c:?}"))),
+    }
+  }
+
+  fn skip_blank_lines(&mut self) {
+    self.skip_ws_and_newlines();
+  }
+
+  fn skip_ws_and_newlines(&mut self) {
+    loop {
+      self.skip_ws();
+      self.skip_comment();
+      if !self.skip_newline() {
+        return;
+      }
+    }
+  }
+
+  fn skip_ws(&mut self) {
+    self.take_while(|c| c == ' ' || c == '\t');
+  }
+
+  fn skip_comment(&mut self) {
+    if self.peek() == Some('#') {
+      self.take_while(|c| c != '\n');
+    }
+  }
+
+  fn skip_newline(&mut self) -> bool {
+    if self.lookahead("\r\n") {
+      self.pos += 2;
+      true
+    } else {
+      self.eat('\n')
+    }
+  }
+
+  fn take_while(&mut self, predicate: impl Fn(char) -> bool) -> String {
+    let start = self.pos;
+    while self.peek().is_some_and(&predicate) {
+      self.pos += 1;
+    }
+    self.chars[start..self.pos].iter().collect()
+  }
+
+  fn peek(&self) -> Option<char> {
+    self.peek_at(0)
+  }
+
+  fn peek_at(&self, offset: usize) -> Option<char> {
+    self.chars.get(self.pos + offset).copied()
+  }
+
+  fn lookahead(&self, s: &str) -> bool {
+    s.chars().enumerate().all(|(i, c)| self.peek_at(i) == Some(c))
+  }
+
+  fn advance(&mut self) -> Option<char> {
+    let c = self.peek();
+    if c.is_some() {
+      self.pos += 1;
+    }
+    c
+  }
+
+  fn eat(&mut self, c: char) -> bool {
+    let matches = self.peek() == Some(c);
+    if matches {
+      self.pos += 1;
+    }
+    matches
+  }
+
+  fn expect(&mut self, c: char) -> Result<(), TomlError> {
+    if self.eat(c) {
+      Ok(())
+    } else {
+      Err(self.error(format!("expected {c:?}")))
+    }
+  }
+
+  fn error(&self, message: impl Into<String>) -> TomlError {
+    let consumed = &self.chars[..self.pos.min(self.chars.len())];
+    let line = consumed.iter().filter(|c| **c == '\n').count() + 1;
+    let column = consumed.iter().rev().take_while(|c| **c != '\n').count() + 1;
+    TomlError { line, column, message: message.into() }
+  }
+}
+
+/// Returns the table at the given path, creating the missing tables along the way.
+///
+/// An array of tables on the path resolves to its last table, as the TOML specification demands.
+fn table_at<'a>(
+  root: &'a mut Map<String, Value>,
+  path: &[String],
+) -> Result<&'a mut Map<String, Value>, String> {
+  let mut table = root;
+  for key in path {
+    let value = table.entry(key.clone()).or_insert_with(|| Value::Object(Map::new()));
+    table = match value {
+      Value::Object(inner) => inner,
+      Value::Array(values) => match values.last_mut() {
+        Some(Value::Object(inner)) => inner,
+        _ => return Err(format!("key {key} is not a table")),
+      },
+      _ => return Err(format!("key {key} is not a table")),
+    };
+  }
+  Ok(table)
+}
+
+/// Appends a new table to the array of tables at the given path, creating the array if necessary.
+fn push_array_table(root: &mut Map<String, Value>, path: &[String]) -> Result<(), String> {
+  let (last, parent_path) = path.split_last().expect("keys are never empty");
+  let parent = table_at(root, parent_path)?;
+  match parent.get_mut(last) {
+    None => {
+      parent.insert(last.clone(), Value::Array(vec![Value::Object(Map::new())]));
+      Ok(())
+    }
+    Some(Value::Array(tables)) => {
+      tables.push(Value::Object(Map::new()));
+      Ok(())
+    }
+    Some(_) => Err(format!("key {last} is not an array of tables")),
+  }
+}
+
+fn insert_dotted(
+  table: &mut Map<String, Value>,
+  mut key: Vec<String>,
+  value: Value,
+) -> Result<(), String> {
+  let last = key.pop().expect("keys are never empty");
+  let table = table_at(table, &key)?;
+  if table.contains_key(&last) {
+    return Err(format!("key {last} is defined more than once"));
+  }
+  table.insert(last, value);
+  Ok(())
+}
+
+fn is_date(token: &str) -> bool {
+  let b = token.as_bytes();
+  b.len() >= 10
+    && b[..4].iter().all(u8::is_ascii_digit)
+    && b[4] == b'-'
+    && b[5..7].iter().all(u8::is_ascii_digit)
+    && b[7] == b'-'
+    && b[8..10].iter().all(u8::is_ascii_digit)
+}
+
+fn is_time(token: &str) -> bool {
+  let b = token.as_bytes();
+  b.len() >= 8
+    && b[..2].iter().all(u8::is_ascii_digit)
+    && b[2] == b':'
+    && b[3..5].iter().all(u8::is_ascii_digit)
+    && b[5] == b':'
+}
+
+fn parse_number(token: &str) -> Option<Value> {
+  if !has_valid_underscores(token) {
+    return None;
+  }
+  let digits = token.replace('_', "");
+  let radix = match digits.get(..2) {
+    Some("0x") => Some(16),
+    Some("0o") => Some(8),
+    Some("0b") => Some(2),
+    _ => None,
+  };
+  if let Some(radix) = radix {
+    return i64::from_str_radix(&digits[2..], radix).ok().map(Value::from);
+  }
+
+  let unsigned = digits.trim_start_matches(['+', '-']);
+  if unsigned.len() > 1 && unsigned.starts_with('0') && unsigned.as_bytes()[1].is_ascii_digit() {
+    // Leading zeros are not allowed:
+    return None;
+  }
+
+  if unsigned.contains(['.', 'e', 'E']) {
+    // Both sides of the decimal point must be digits:
+    let point_is_enclosed = unsigned.split_once('.').is_none_or(|(int, frac)| {
+      int.ends_with(|c: char| c.is_ascii_digit()) && frac.starts_with(|c: char| c.is_ascii_digit())
+    });
+    if !point_is_enclosed {
+      return None;
+    }
+    digits.parse::<f64>().ok().and_then(Number::from_f64).map(Value::Number)
+  } else {
+    digits.parse::<i64>().ok().map(Value::from)
+  }
+}
+
+/// Returns `true` if every underscore in a number is surrounded by digits.
+fn has_valid_underscores(token: &str) -> bool {
+  let b = token.as_bytes();
+  b.iter().enumerate()
+    .filter(|(_, c)| **c == b'_')
+    .all(|(i, _)| {
+      i > 0 && b[i - 1].is_ascii_hexdigit() && b.get(i + 1).is_some_and(u8::is_ascii_hexdigit)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use serde_json::json;
+
+  use crate::toml;
+
+  #[test]
+  fn tables_and_dotted_keys_become_nested_objects() {
+    let value = toml::from_str(r#"
+      title = "TOML example" # a comment
+
+      [owner]
+      name = "Tom"
+      site."google.com" = true
+
+      [database.connection]
+      ports = [ 8000, 8001,
+        8002, ]   # trailing commas are fine
+      limits = { max = 1.5e3, min = -2 }
+    "#).expect("TOML is parsed");
+    assert_eq!(value, json!({
+      "title" : "TOML example",
+      "owner" : { "name" : "Tom", "site" : { "google.com" : true } },
+      "database" : {
+        "connection" : {
+          "ports" : [8000, 8001, 8002],
+          "limits" : { "max" : 1500.0, "min" : -2 }
+        }
+      }
+    }));
+  }
+
+  #[test]
+  fn arrays_of_tables_become_lists_of_objects() {
+    let value = toml::from_str(r#"
+      [[fruits]]
+      name = "apple"
+      [fruits.physical]
+      color = "red"
+
+      [[fruits]]
+      name = "banana"
+      [fruits.physical]
+      color = "yellow"
+    "#).expect("TOML is parsed");
+    assert_eq!(value, json!({
+      "fruits" : [
+        { "name" : "apple", "physical" : { "color" : "red" } },
+        { "name" : "banana", "physical" : { "color" : "yellow" } }
+      ]
+    }));
+  }
+
+  #[test]
+  fn strings_are_unescaped() {
+    let value = toml::from_str(concat!(
+      "basic = \"tab\\there \\u00E9\"\n",
+      "literal = 'C:\\Users'\n",
+      "multi = \"\"\"\nfirst \\\n    second\"\"\"\n",
+      "multi_literal = '''\nraw \\n''''\n",
+    )).expect("TOML is parsed");
+    assert_eq!(value, json!({
+      "basic" : "tab\there é",
+      "literal" : "C:\\Users",
+      "multi" : "first second",
+      "multi_literal" : "raw \\n'"
+    }));
+  }
+
+  #[test]
+  fn numbers_in_every_notation_are_parsed() {
+    let value = toml::from_str(
+      "a = +99\nb = 1_000\nc = 0xDEAD_BEEF\nd = 0o755\ne = 0b1101\nf = 6.626e-34\ng = -0.01\n"
+    ).expect("TOML is parsed");
+    assert_eq!(value, json!({
+      "a" : 99, "b" : 1000, "c" : 0xDEADBEEF_i64, "d" : 0o755, "e" : 13,
+      "f" : 6.626e-34, "g" : -0.01
+    }));
+  }
+
+  #[test]
+  fn datetimes_are_converted_to_rfc_3339_strings() {
+    let value = toml::from_str(concat!(
+      "odt = 1979-05-27 07:32:00z\n",
+      "ldt = 1979-05-27T07:32:00.999999\n",
+      "ld = 1979-05-27\n",
+      "lt = 07:32:00\n",
+    )).expect("TOML is parsed");
+    assert_eq!(value, json!({
+      "odt" : "1979-05-27T07:32:00Z",
+      "ldt" : "1979-05-27T07:32:00.999999",
+      "ld" : "1979-05-27",
+      "lt" : "07:32:00"
+    }));
+  }
+
+  #[test]
+  fn invalid_documents_are_rejected_with_their_position() {
+    let err = toml::from_str("a = 1\na = 2\n").expect_err("duplicate key is rejected");
+    assert_eq!((err.line(), err.column()), (2, 6));
+
+    assert!(toml::from_str("[a]\n[a]\n").is_err());
+    assert!(toml::from_str("a = 01\n").is_err());
+    assert!(toml::from_str("a = 1__0\n").is_err());
+    assert!(toml::from_str("a = \"open\n").is_err());
+    assert!(toml::from_str("a = 1 b = 2\n").is_err());
+    assert!(toml::from_str("a = inf\n").is_err());
+  }
+}
@@ -0,0 +1,114 @@
+//! Transformation of property values by an external command.
+
+use std::io::{self, Write};
+use std::process::{Command, Stdio};
+use std::thread;
+
+/// Splits a command line into its program and arguments at unquoted whitespace.
+///
+/// Single quotes keep everything between them, double quotes everything but a backslash-escaped
+/// `"` or `\`, and a backslash outside of quotes escapes the next character. Returns `None` if the
+/// command is empty, or a quote is not closed.
+pub(crate) fn split_command(cmd: &str) -> Option<Vec<String>> {
+  let mut words = Vec::new();
+  let mut word: Option<String> = None;
+  let mut chars = cmd.chars();
+  while let Some(c) = chars.next() {
+    match c {
+      c if c.is_whitespace() => words.extend(word.take()),
+      '\'' => {
+        let word = word.get_or_insert_with(String::new);
+        loop {
+          match chars.next()? {
+            '\'' => break,
+            c => word.push(c),
+          }
+        }
+      }
+      '"' => {
+        let word = word.get_or_insert_with(String::new);
+        loop {
+          match chars.next()? {
+            '"' => break,
+            '\\' => match chars.next()? {
+              c @ ('"' | '\\') => word.push(c),
+              c => word.extend(['\\', c]),
+            },
+            c => word.push(c),
+          }
+        }
+      }
+      '\\' => word.get_or_insert_with(String::new).push(chars.next()?),
+      c => word.get_or_insert_with(String::new).push(c),
+    }
+  }
+  words.extend(word);
+  (!words.is_empty()).then_some(words)
+}
+
+/// Runs the `command` (as split by [split_command]) with `input` on its standard input, returning
+/// its standard output without the line break ending it.
+///
+/// Fails with a description of the problem if the command cannot be run, exits with a non-zero
+/// status, or writes anything but UTF-8.
+pub(crate) fn run(command: &[String], input: &str) -> Result<String, String> {
+  let (program, args) = command.split_first().ok_or_else(|| String::from("empty command"))?;
+  let mut child = Command::new(program)
+    .args(args)
+    .stdin(Stdio::piped())
+    .stdout(Stdio::piped())
+    .stderr(Stdio::piped())
+    .spawn()
+    .map_err(|e| format!("cannot run {program}: {e}"))?;
+
+  // The input is written on its own thread, so a command writing before it has read everything
+  // cannot block on a full pipe:
+  let mut stdin = child.stdin.take().expect("the standard input is piped");
+  let output = thread::scope(|scope| {
+    let writer = scope.spawn(move || stdin.write_all(input.as_bytes()));
+    let output = child.wait_with_output();
+    match writer.join().expect("the writer thread does not panic") {
+      // A command may exit without reading its input:
+      Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+      _ => output,
+    }
+  }).map_err(|e| format!("cannot communicate with {program}: {e}"))?;
+
+  if !output.status.success() {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    return Err(format!("{program} failed ({}): {}", output.status, stderr.trim_end()));
+  }
+  let mut stdout = String::from_utf8(output.stdout)
+    .map_err(|_| format!("{program} did not write valid UTF-8"))?;
+  if stdout.ends_with('\n') {
+    stdout.pop();
+    if stdout.ends_with('\r') {
+      stdout.pop();
+    }
+  }
+  Ok(stdout)
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::transform::{run, split_command};
+
+  #[test]
+  fn commands_are_split_at_unquoted_whitespace() {
+    let words = split_command(r#"vault  read -field='a b' "x \"y\" \z" c\ d"#).unwrap();
+    assert_eq!(words, ["vault", "read", "-field=a b", r#"x "y" \z"#, "c d"]);
+    assert_eq!(split_command("  "), None);
+    assert_eq!(split_command("echo 'a"), None);
+  }
+
+  #[cfg(unix)]
+  #[test]
+  fn values_are_piped_through_the_command() {
+    let tr = split_command("tr a-z A-Z").unwrap();
+    assert_eq!(run(&tr, "secret\n").unwrap(), "SECRET");
+
+    let fail = split_command("sh -c 'echo nope >&2; exit 3'").unwrap();
+    let err = run(&fail, "secret").unwrap_err();
+    assert!(err.starts_with("sh failed") && err.ends_with("nope"), "{err}");
+  }
+}
@@ -0,0 +1,125 @@
+//! Formatting of the values at configured keys, see `--format-path`.
+
+use std::str::FromStr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::glob::Glob;
+
+/// A formatter applied to the values of the keys matching a glob pattern, written as
+/// `<glob>=<formatter>`, e.g. `*.created=epoch`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct PathFormat {
+  glob: Glob,
+  formatter: ValueFormatter,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ValueFormatter {
+  /// Writes an RFC 3339 timestamp (or date) as the milliseconds since the Unix epoch.
+  Epoch,
+  /// Writes the milliseconds since the Unix epoch (or any RFC 3339 timestamp) as an RFC 3339
+  /// timestamp in UTC.
+  IsoDate,
+  Upper,
+  Lower,
+  Trim,
+}
+
+const FORMATTERS: [(&str, ValueFormatter); 5] = [
+  ("epoch", ValueFormatter::Epoch),
+  ("iso-date", ValueFormatter::IsoDate),
+  ("upper", ValueFormatter::Upper),
+  ("lower", ValueFormatter::Lower),
+  ("trim", ValueFormatter::Trim),
+];
+
+impl PathFormat {
+  /// Returns `true` if the formatter applies to the value of the flattened key.
+  pub(crate) fn matches(&self, key: &str, separator: &str) -> bool {
+    self.glob.matches(key, separator)
+  }
+
+  /// Formats the value, failing with the reason if it cannot be formatted.
+  pub(crate) fn apply(&self, value: &str) -> Result<String, String> {
+    match self.formatter {
+      ValueFormatter::Epoch => {
+        let time = parse_timestamp(value)?;
+        let millis = time.duration_since(UNIX_EPOCH)
+          .map_err(|_| format!("[ {value} ] is before the Unix epoch"))?
+          .as_millis();
+        Ok(millis.to_string())
+      }
+      ValueFormatter::IsoDate => {
+        let time = match value.parse::<u64>() {
+          Ok(millis) => UNIX_EPOCH + Duration::from_millis(millis),
+          Err(_) => parse_timestamp(value)?,
+        };
+        let has_millis = time.duration_since(UNIX_EPOCH).is_ok_and(|d| d.subsec_millis() != 0);
+        if has_millis {
+          Ok(humantime::format_rfc3339_millis(time).to_string())
+        } else {
+          Ok(humantime::format_rfc3339_seconds(time).to_string())
+        }
+      }
+      ValueFormatter::Upper => Ok(value.to_uppercase()),
+      ValueFormatter::Lower => Ok(value.to_lowercase()),
+      ValueFormatter::Trim => Ok(String::from(value.trim())),
+    }
+  }
+}
+
+/// Parses an RFC 3339 timestamp, also accepting a space instead of the `T`, a missing time zone
+/// (taken as UTC), and a date without a time (taken as midnight).
+fn parse_timestamp(value: &str) -> Result<SystemTime, String> {
+  let is_date = value.len() == 10 && value.as_bytes().get(4) == Some(&b'-');
+  let timestamp = if is_date { format!("{value}T00:00:00Z") } else { String::from(value) };
+  humantime::parse_rfc3339_weak(&timestamp)
+    .map_err(|e| format!("[ {value} ] is not an RFC 3339 timestamp: {e}"))
+}
+
+impl FromStr for PathFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let Some((pattern, name)) = s.rsplit_once('=') else {
+      return Err(format!("[ {s} ] must be written as <glob>=<formatter>"));
+    };
+    let formatter = FORMATTERS.iter()
+      .find(|(n, _)| *n == name)
+      .map(|(_, formatter)| *formatter)
+      .ok_or_else(|| {
+        let names: Vec<_> = FORMATTERS.iter().map(|(n, _)| *n).collect();
+        format!("unknown formatter [ {name} ], it must be one of {}", names.join(", "))
+      })?;
+    Ok(PathFormat { glob: Glob::new(pattern), formatter })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use crate::value_format::PathFormat;
+
+  fn format(path_format: &str, value: &str) -> Result<String, String> {
+    path_format.parse::<PathFormat>().unwrap().apply(value)
+  }
+
+  #[test]
+  fn timestamps_are_converted_between_epoch_millis_and_rfc_3339() {
+    assert_eq!(format("a=epoch", "2024-01-02T03:04:05.006Z").unwrap(), "1704164645006");
+    assert_eq!(format("a=epoch", "2024-01-02").unwrap(), "1704153600000");
+    assert!(format("a=epoch", "yesterday").is_err());
+    assert_eq!(format("a=iso-date", "1704164645006").unwrap(), "2024-01-02T03:04:05.006Z");
+    assert_eq!(format("a=iso-date", "2024-01-02 03:04:05").unwrap(), "2024-01-02T03:04:05Z");
+  }
+
+  #[test]
+  fn path_formats_are_parsed_from_a_glob_and_a_formatter() {
+    let path_format: PathFormat = "db.*=upper".parse().unwrap();
+    assert!(path_format.matches("db.user", "."));
+    assert!(!path_format.matches("db.pool.user", "."));
+    assert_eq!(path_format.apply(" a ").unwrap(), " A ");
+    assert_eq!(format("a=trim", " a ").unwrap(), "a");
+    assert!("db.user".parse::<PathFormat>().is_err());
+    assert!("db.user=camel".parse::<PathFormat>().is_err());
+  }
+}
@@ -0,0 +1,60 @@
+use jsonprops::{Config, ConfigValidationError, EntryOrder, LineEnding, NumberFormat, Properties};
+
+const SOURCE: &str = r#"{
+  "z" : { "ratio" : 1.50, "big" : 123456789012345678901234567890, "exp" : 1e3 },
+  "a" : [ "x", { "b" : true }, null ],
+  "m" : { "é" : "line\nbreak", "k k" : -0.0 }
+}"#;
+
+fn render(config: &Config) -> Vec<u8> {
+  let value = serde_json::from_str(SOURCE).expect("JSON is valid");
+  Properties::create(value, config)
+    .and_then(|props| props.render(config))
+    .expect("JSON is rendered")
+    .into_bytes()
+}
+
+#[test]
+fn the_same_source_and_config_always_render_the_same_bytes() {
+  let config = Config::builder()
+    .on_complex_list(jsonprops::ComplexListHandling::FallbackMulti)
+    .canonical(true)
+    .build()
+    .expect("config is valid");
+  let first = render(&config);
+  for _ in 0..10 {
+    assert_eq!(render(&config), first);
+  }
+  assert_eq!(
+    String::from_utf8(first).unwrap(),
+    "a.0=x\na.1.b=true\na.2=\nm.k\\ k=-0.0\nm.é=line\\nbreak\nz.big=1.2345678901234568e29\n\
+    z.exp=1000.0\nz.ratio=1.5\n");
+}
+
+#[test]
+fn canonical_output_is_the_default_output() {
+  let canonical = Config::builder()
+    .on_complex_list(jsonprops::ComplexListHandling::FallbackMulti)
+    .canonical(true)
+    .build()
+    .expect("config is valid");
+  let default = Config::builder()
+    .on_complex_list(jsonprops::ComplexListHandling::FallbackMulti)
+    .build()
+    .expect("config is valid");
+  assert_eq!(render(&canonical), render(&default));
+}
+
+#[test]
+fn canonical_output_rejects_the_nondeterministic_settings() {
+  let builders = [
+    Config::builder().order(EntryOrder::Insertion),
+    Config::builder().number_format(NumberFormat::AsWritten),
+    Config::builder().line_ending(LineEnding::Crlf),
+    Config::builder().header(true),
+  ];
+  for builder in builders {
+    let result = builder.canonical(true).build();
+    assert!(matches!(result, Err(ConfigValidationError::IncompatibleOptionsError(_))));
+  }
+}